@@ -67,6 +67,220 @@ class JsonPayload extends $pb.GeneratedMessage {
   void clearData() => clearField(1);
 }
 
+/// A frame of a JavaScript stack trace
+class JsStackFrame extends $pb.GeneratedMessage {
+  factory JsStackFrame({
+    $core.String? functionName,
+    $core.String? fileName,
+    $core.int? lineNumber,
+    $core.int? columnNumber,
+  }) {
+    final $result = create();
+    if (functionName != null) {
+      $result.functionName = functionName;
+    }
+    if (fileName != null) {
+      $result.fileName = fileName;
+    }
+    if (lineNumber != null) {
+      $result.lineNumber = lineNumber;
+    }
+    if (columnNumber != null) {
+      $result.columnNumber = columnNumber;
+    }
+    return $result;
+  }
+  JsStackFrame._() : super();
+  factory JsStackFrame.fromBuffer($core.List<$core.int> i, [$pb.ExtensionRegistry r = $pb.ExtensionRegistry.EMPTY]) => create()..mergeFromBuffer(i, r);
+  factory JsStackFrame.fromJson($core.String i, [$pb.ExtensionRegistry r = $pb.ExtensionRegistry.EMPTY]) => create()..mergeFromJson(i, r);
+
+  static final $pb.BuilderInfo _i = $pb.BuilderInfo(_omitMessageNames ? '' : 'JsStackFrame', package: const $pb.PackageName(_omitMessageNames ? '' : 'globe.runtime'), createEmptyInstance: create)
+    ..aOS(1, _omitFieldNames ? '' : 'functionName')
+    ..aOS(2, _omitFieldNames ? '' : 'fileName')
+    ..a<$core.int>(3, _omitFieldNames ? '' : 'lineNumber', $pb.PbFieldType.O3)
+    ..a<$core.int>(4, _omitFieldNames ? '' : 'columnNumber', $pb.PbFieldType.O3)
+    ..hasRequiredFields = false
+  ;
+
+  @$core.Deprecated(
+  'Using this can add significant overhead to your binary. '
+  'Use [GeneratedMessageGenericExtensions.deepCopy] instead. '
+  'Will be removed in next major version')
+  JsStackFrame clone() => JsStackFrame()..mergeFromMessage(this);
+  @$core.Deprecated(
+  'Using this can add significant overhead to your binary. '
+  'Use [GeneratedMessageGenericExtensions.rebuild] instead. '
+  'Will be removed in next major version')
+  JsStackFrame copyWith(void Function(JsStackFrame) updates) => super.copyWith((message) => updates(message as JsStackFrame)) as JsStackFrame;
+
+  $pb.BuilderInfo get info_ => _i;
+
+  @$core.pragma('dart2js:noInline')
+  static JsStackFrame create() => JsStackFrame._();
+  JsStackFrame createEmptyInstance() => create();
+  static $pb.PbList<JsStackFrame> createRepeated() => $pb.PbList<JsStackFrame>();
+  @$core.pragma('dart2js:noInline')
+  static JsStackFrame getDefault() => _defaultInstance ??= $pb.GeneratedMessage.$_defaultFor<JsStackFrame>(create);
+  static JsStackFrame? _defaultInstance;
+
+  @$pb.TagNumber(1)
+  $core.String get functionName => $_getSZ(0);
+  @$pb.TagNumber(1)
+  set functionName($core.String v) { $_setString(0, v); }
+  @$pb.TagNumber(1)
+  $core.bool hasFunctionName() => $_has(0);
+  @$pb.TagNumber(1)
+  void clearFunctionName() => clearField(1);
+
+  @$pb.TagNumber(2)
+  $core.String get fileName => $_getSZ(1);
+  @$pb.TagNumber(2)
+  set fileName($core.String v) { $_setString(1, v); }
+  @$pb.TagNumber(2)
+  $core.bool hasFileName() => $_has(1);
+  @$pb.TagNumber(2)
+  void clearFileName() => clearField(2);
+
+  @$pb.TagNumber(3)
+  $core.int get lineNumber => $_getIZ(2);
+  @$pb.TagNumber(3)
+  set lineNumber($core.int v) { $_setSignedInt32(2, v); }
+  @$pb.TagNumber(3)
+  $core.bool hasLineNumber() => $_has(2);
+  @$pb.TagNumber(3)
+  void clearLineNumber() => clearField(3);
+
+  @$pb.TagNumber(4)
+  $core.int get columnNumber => $_getIZ(3);
+  @$pb.TagNumber(4)
+  set columnNumber($core.int v) { $_setSignedInt32(3, v); }
+  @$pb.TagNumber(4)
+  $core.bool hasColumnNumber() => $_has(3);
+  @$pb.TagNumber(4)
+  void clearColumnNumber() => clearField(4);
+}
+
+/// A JavaScript error thrown by a module function or passed to `Dart.send_error`
+class JsError extends $pb.GeneratedMessage {
+  factory JsError({
+    $core.String? name,
+    $core.String? message,
+    $core.String? stack,
+    $core.Iterable<JsStackFrame>? frames,
+    JsError? cause,
+    $core.List<$core.int>? properties,
+  }) {
+    final $result = create();
+    if (name != null) {
+      $result.name = name;
+    }
+    if (message != null) {
+      $result.message = message;
+    }
+    if (stack != null) {
+      $result.stack = stack;
+    }
+    if (frames != null) {
+      $result.frames.addAll(frames);
+    }
+    if (cause != null) {
+      $result.cause = cause;
+    }
+    if (properties != null) {
+      $result.properties = properties;
+    }
+    return $result;
+  }
+  JsError._() : super();
+  factory JsError.fromBuffer($core.List<$core.int> i, [$pb.ExtensionRegistry r = $pb.ExtensionRegistry.EMPTY]) => create()..mergeFromBuffer(i, r);
+  factory JsError.fromJson($core.String i, [$pb.ExtensionRegistry r = $pb.ExtensionRegistry.EMPTY]) => create()..mergeFromJson(i, r);
+
+  static final $pb.BuilderInfo _i = $pb.BuilderInfo(_omitMessageNames ? '' : 'JsError', package: const $pb.PackageName(_omitMessageNames ? '' : 'globe.runtime'), createEmptyInstance: create)
+    ..aOS(1, _omitFieldNames ? '' : 'name')
+    ..aOS(2, _omitFieldNames ? '' : 'message')
+    ..aOS(3, _omitFieldNames ? '' : 'stack')
+    ..pc<JsStackFrame>(4, _omitFieldNames ? '' : 'frames', $pb.PbFieldType.PM, subBuilder: JsStackFrame.create)
+    ..aOM<JsError>(5, _omitFieldNames ? '' : 'cause', subBuilder: JsError.create)
+    ..a<$core.List<$core.int>>(6, _omitFieldNames ? '' : 'properties', $pb.PbFieldType.OY)
+    ..hasRequiredFields = false
+  ;
+
+  @$core.Deprecated(
+  'Using this can add significant overhead to your binary. '
+  'Use [GeneratedMessageGenericExtensions.deepCopy] instead. '
+  'Will be removed in next major version')
+  JsError clone() => JsError()..mergeFromMessage(this);
+  @$core.Deprecated(
+  'Using this can add significant overhead to your binary. '
+  'Use [GeneratedMessageGenericExtensions.rebuild] instead. '
+  'Will be removed in next major version')
+  JsError copyWith(void Function(JsError) updates) => super.copyWith((message) => updates(message as JsError)) as JsError;
+
+  $pb.BuilderInfo get info_ => _i;
+
+  @$core.pragma('dart2js:noInline')
+  static JsError create() => JsError._();
+  JsError createEmptyInstance() => create();
+  static $pb.PbList<JsError> createRepeated() => $pb.PbList<JsError>();
+  @$core.pragma('dart2js:noInline')
+  static JsError getDefault() => _defaultInstance ??= $pb.GeneratedMessage.$_defaultFor<JsError>(create);
+  static JsError? _defaultInstance;
+
+  /// Error class, e.g. `TypeError`
+  @$pb.TagNumber(1)
+  $core.String get name => $_getSZ(0);
+  @$pb.TagNumber(1)
+  set name($core.String v) { $_setString(0, v); }
+  @$pb.TagNumber(1)
+  $core.bool hasName() => $_has(0);
+  @$pb.TagNumber(1)
+  void clearName() => clearField(1);
+
+  @$pb.TagNumber(2)
+  $core.String get message => $_getSZ(1);
+  @$pb.TagNumber(2)
+  set message($core.String v) { $_setString(1, v); }
+  @$pb.TagNumber(2)
+  $core.bool hasMessage() => $_has(1);
+  @$pb.TagNumber(2)
+  void clearMessage() => clearField(2);
+
+  /// `error.stack` as formatted by V8
+  @$pb.TagNumber(3)
+  $core.String get stack => $_getSZ(2);
+  @$pb.TagNumber(3)
+  set stack($core.String v) { $_setString(2, v); }
+  @$pb.TagNumber(3)
+  $core.bool hasStack() => $_has(2);
+  @$pb.TagNumber(3)
+  void clearStack() => clearField(3);
+
+  @$pb.TagNumber(4)
+  $core.List<JsStackFrame> get frames => $_getList(3);
+
+  /// `error.cause`, if set
+  @$pb.TagNumber(5)
+  JsError get cause => $_getN(4);
+  @$pb.TagNumber(5)
+  set cause(JsError v) { setField(5, v); }
+  @$pb.TagNumber(5)
+  $core.bool hasCause() => $_has(4);
+  @$pb.TagNumber(5)
+  void clearCause() => clearField(5);
+  @$pb.TagNumber(5)
+  JsError ensureCause() => $_ensure(4);
+
+  /// MessagePack-encoded custom enumerable properties
+  @$pb.TagNumber(6)
+  $core.List<$core.int> get properties => $_getN(5);
+  @$pb.TagNumber(6)
+  set properties($core.List<$core.int> v) { $_setBytes(5, v); }
+  @$pb.TagNumber(6)
+  $core.bool hasProperties() => $_has(5);
+  @$pb.TagNumber(6)
+  void clearProperties() => clearField(6);
+}
+
 enum DartMessage_Payload {
   data, 
   error, 
@@ -78,6 +292,8 @@ class DartMessage extends $pb.GeneratedMessage {
     $core.bool? done,
     $core.List<$core.int>? data,
     $core.String? error,
+    $core.bool? cancelled,
+    JsError? jsError,
   }) {
     final $result = create();
     if (done != null) {
@@ -89,6 +305,12 @@ class DartMessage extends $pb.GeneratedMessage {
     if (error != null) {
       $result.error = error;
     }
+    if (cancelled != null) {
+      $result.cancelled = cancelled;
+    }
+    if (jsError != null) {
+      $result.jsError = jsError;
+    }
     return $result;
   }
   DartMessage._() : super();
@@ -105,6 +327,8 @@ class DartMessage extends $pb.GeneratedMessage {
     ..aOB(1, _omitFieldNames ? '' : 'done')
     ..a<$core.List<$core.int>>(2, _omitFieldNames ? '' : 'data', $pb.PbFieldType.OY)
     ..aOS(3, _omitFieldNames ? '' : 'error')
+    ..aOB(4, _omitFieldNames ? '' : 'cancelled')
+    ..aOM<JsError>(5, _omitFieldNames ? '' : 'jsError', subBuilder: JsError.create)
     ..hasRequiredFields = false
   ;
 
@@ -158,6 +382,28 @@ class DartMessage extends $pb.GeneratedMessage {
   $core.bool hasError() => $_has(2);
   @$pb.TagNumber(3)
   void clearError() => clearField(3);
+
+  /// Set on the final message of a call cancelled through `cancel_js_call`
+  @$pb.TagNumber(4)
+  $core.bool get cancelled => $_getBF(3);
+  @$pb.TagNumber(4)
+  set cancelled($core.bool v) { $_setBool(3, v); }
+  @$pb.TagNumber(4)
+  $core.bool hasCancelled() => $_has(3);
+  @$pb.TagNumber(4)
+  void clearCancelled() => clearField(4);
+
+  /// Structured form of `error`, set when the error came from JavaScript
+  @$pb.TagNumber(5)
+  JsError get jsError => $_getN(4);
+  @$pb.TagNumber(5)
+  set jsError(JsError v) { setField(5, v); }
+  @$pb.TagNumber(5)
+  $core.bool hasJsError() => $_has(4);
+  @$pb.TagNumber(5)
+  void clearJsError() => clearField(5);
+  @$pb.TagNumber(5)
+  JsError ensureJsError() => $_ensure(4);
 }
 
 /// Message structure for sending data to Dart
@@ -25,6 +25,43 @@ const JsonPayload$json = {
 final $typed_data.Uint8List jsonPayloadDescriptor = $convert.base64Decode(
     'CgtKc29uUGF5bG9hZBISCgRkYXRhGAEgASgMUgRkYXRh');
 
+@$core.Deprecated('Use jsStackFrameDescriptor instead')
+const JsStackFrame$json = {
+  '1': 'JsStackFrame',
+  '2': [
+    {'1': 'function_name', '3': 1, '4': 1, '5': 9, '10': 'functionName'},
+    {'1': 'file_name', '3': 2, '4': 1, '5': 9, '10': 'fileName'},
+    {'1': 'line_number', '3': 3, '4': 1, '5': 5, '10': 'lineNumber'},
+    {'1': 'column_number', '3': 4, '4': 1, '5': 5, '10': 'columnNumber'},
+  ],
+};
+
+/// Descriptor for `JsStackFrame`. Decode as a `google.protobuf.DescriptorProto`.
+final $typed_data.Uint8List jsStackFrameDescriptor = $convert.base64Decode(
+    'CgxKc1N0YWNrRnJhbWUSIwoNZnVuY3Rpb25fbmFtZRgBIAEoCVIMZnVuY3Rpb25OYW1lEhsKCW'
+    'ZpbGVfbmFtZRgCIAEoCVIIZmlsZU5hbWUSHwoLbGluZV9udW1iZXIYAyABKAVSCmxpbmVOdW1i'
+    'ZXISIwoNY29sdW1uX251bWJlchgEIAEoBVIMY29sdW1uTnVtYmVy');
+
+@$core.Deprecated('Use jsErrorDescriptor instead')
+const JsError$json = {
+  '1': 'JsError',
+  '2': [
+    {'1': 'name', '3': 1, '4': 1, '5': 9, '10': 'name'},
+    {'1': 'message', '3': 2, '4': 1, '5': 9, '10': 'message'},
+    {'1': 'stack', '3': 3, '4': 1, '5': 9, '10': 'stack'},
+    {'1': 'frames', '3': 4, '4': 3, '5': 11, '6': '.globe.runtime.JsStackFrame', '10': 'frames'},
+    {'1': 'cause', '3': 5, '4': 1, '5': 11, '6': '.globe.runtime.JsError', '10': 'cause'},
+    {'1': 'properties', '3': 6, '4': 1, '5': 12, '10': 'properties'},
+  ],
+};
+
+/// Descriptor for `JsError`. Decode as a `google.protobuf.DescriptorProto`.
+final $typed_data.Uint8List jsErrorDescriptor = $convert.base64Decode(
+    'CgdKc0Vycm9yEhIKBG5hbWUYASABKAlSBG5hbWUSGAoHbWVzc2FnZRgCIAEoCVIHbWVzc2FnZR'
+    'IUCgVzdGFjaxgDIAEoCVIFc3RhY2sSMwoGZnJhbWVzGAQgAygLMhsuZ2xvYmUucnVudGltZS5K'
+    'c1N0YWNrRnJhbWVSBmZyYW1lcxIsCgVjYXVzZRgFIAEoCzIWLmdsb2JlLnJ1bnRpbWUuSnNFcn'
+    'JvclIFY2F1c2USHgoKcHJvcGVydGllcxgGIAEoDFIKcHJvcGVydGllcw==');
+
 @$core.Deprecated('Use dartMessageDescriptor instead')
 const DartMessage$json = {
   '1': 'DartMessage',
@@ -32,6 +69,8 @@ const DartMessage$json = {
     {'1': 'done', '3': 1, '4': 1, '5': 8, '10': 'done'},
     {'1': 'data', '3': 2, '4': 1, '5': 12, '9': 0, '10': 'data'},
     {'1': 'error', '3': 3, '4': 1, '5': 9, '9': 0, '10': 'error'},
+    {'1': 'cancelled', '3': 4, '4': 1, '5': 8, '10': 'cancelled'},
+    {'1': 'js_error', '3': 5, '4': 1, '5': 11, '6': '.globe.runtime.JsError', '10': 'jsError'},
   ],
   '8': [
     {'1': 'payload'},
@@ -41,7 +80,9 @@ const DartMessage$json = {
 /// Descriptor for `DartMessage`. Decode as a `google.protobuf.DescriptorProto`.
 final $typed_data.Uint8List dartMessageDescriptor = $convert.base64Decode(
     'CgtEYXJ0TWVzc2FnZRISCgRkb25lGAEgASgIUgRkb25lEhQKBGRhdGEYAiABKAxIAFIEZGF0YR'
-    'IWCgVlcnJvchgDIAEoCUgAUgVlcnJvckIJCgdwYXlsb2Fk');
+    'IWCgVlcnJvchgDIAEoCUgAUgVlcnJvchIcCgljYW5jZWxsZWQYBCABKAhSCWNhbmNlbGxlZBIx'
+    'Cghqc19lcnJvchgFIAEoCzIWLmdsb2JlLnJ1bnRpbWUuSnNFcnJvclIHanNFcnJvckIJCgdwYX'
+    'lsb2Fk');
 
 @$core.Deprecated('Use sendValueRequestDescriptor instead')
 const SendValueRequest$json = {
@@ -80,6 +121,8 @@ const $core.Map<$core.String, $core.dynamic> DartJSServiceBase$json = {
 const $core.Map<$core.String, $core.Map<$core.String, $core.dynamic>> DartJSServiceBase$messageJson = {
   '.globe.runtime.SendValueRequest': SendValueRequest$json,
   '.globe.runtime.DartMessage': DartMessage$json,
+  '.globe.runtime.JsError': JsError$json,
+  '.globe.runtime.JsStackFrame': JsStackFrame$json,
   '.globe.runtime.RpcResponse': RpcResponse$json,
 };
 
@@ -13,12 +13,21 @@ import '../generated/dart_runtime_entry.pbserver.dart';
 
 part 'runtime_impl.dart';
 part 'runtime_data.dart';
+part 'runtime_config.dart';
+part 'runtime_error.dart';
 
 /// Callback function for when data is received from the runtime.
 ///
 /// Return `true` to unregister the callback.
 typedef OnFunctionData = bool Function(DartMessage data);
 
+/// A Dart function JavaScript calls with `Dart.invoke(name, ...args)`.
+///
+/// [args] are decoded like [DartMessageValueExtension.value]. The result is
+/// encoded with MessagePack like an [FFIJsonPayload], and a thrown error
+/// rejects the JavaScript promise.
+typedef HostFunction = FutureOr<Object?> Function(List<Object?> args);
+
 /// A base class for modules that can be registered with the runtime.
 sealed class Module {
   final String name;
@@ -30,22 +39,65 @@ sealed class Module {
 
   bool get isReady => _runtime.isModuleRegistered(name);
 
-  void callFunction(
+  /// Whether this module is registered in [runtime].
+  bool isRegisteredIn(GlobeRuntime runtime) => runtime.isModuleRegistered(name);
+
+  /// Calls [function] and returns the identifier of the call, which can be
+  /// passed to [GlobeRuntime.cancelFunction].
+  ///
+  /// See [GlobeRuntime.callFunction] for how [timeout] is applied.
+  int callFunction(
     String function, {
     List<FFIConvertible?> args = const [],
     required OnFunctionData onData,
+    Duration? timeout,
+    GlobeRuntime? runtime,
   }) {
-    return _runtime.callFunction(
+    return (runtime ?? _runtime).callFunction(
       name,
       function: function,
       args: args,
       onData: onData,
+      timeout: timeout,
     );
   }
 
-  FutureOr<void> register({List<FFIConvertible?> args = const []}) async {
-    if (isReady) return;
-    return _runtime.registerModule(this, args: args);
+  /// Calls [function] and returns a stream of its messages, see
+  /// [GlobeRuntime.streamFunction].
+  Stream<DartMessage> streamFunction(
+    String function, {
+    List<FFIConvertible?> args = const [],
+    Duration? timeout,
+    GlobeRuntime? runtime,
+  }) {
+    return (runtime ?? _runtime).streamFunction(
+      name,
+      function: function,
+      args: args,
+      timeout: timeout,
+    );
+  }
+
+  /// Creates an instance of this module in [runtime], or the shared
+  /// instance, see [GlobeRuntime.createModuleInstance].
+  Future<ModuleInstance> createInstance({
+    List<FFIConvertible?> args = const [],
+    GlobeRuntime? runtime,
+  }) {
+    return (runtime ?? _runtime).createModuleInstance(name, args: args);
+  }
+
+  /// Registers this module in [runtime], or the shared instance.
+  ///
+  /// See [GlobeRuntime.registerModule] for how [permissions] are applied.
+  FutureOr<void> register({
+    List<FFIConvertible?> args = const [],
+    GlobeRuntime? runtime,
+    RuntimePermissions? permissions,
+  }) async {
+    final target = runtime ?? _runtime;
+    if (isRegisteredIn(target)) return;
+    return target.registerModule(this, args: args, permissions: permissions);
   }
 }
 
@@ -106,6 +158,50 @@ class InlinedModule extends Module {
   Future<String> get source async => sourceCode;
 }
 
+/// An instance of a registered module with its own state, returned by the
+/// module's `init` for the arguments the instance was created with.
+///
+/// Instances share the module's code and permissions, so one module can
+/// serve e.g. several API keys at the same time.
+class ModuleInstance {
+  final GlobeRuntime runtime;
+  final String moduleName;
+
+  /// Identifies the instance in [runtime].
+  final int id;
+
+  ModuleInstance._(this.runtime, this.moduleName, this.id);
+
+  /// Calls [function] of the module with the state of this instance and
+  /// returns the identifier of the call.
+  ///
+  /// See [GlobeRuntime.callFunction] for how [timeout] is applied.
+  int callFunction(
+    String function, {
+    List<FFIConvertible?> args = const [],
+    required OnFunctionData onData,
+    Duration? timeout,
+  }) {
+    return runtime._instance!.callInstanceFunction(
+      id,
+      function: function,
+      args: args,
+      onData: onData,
+      timeout: timeout,
+    );
+  }
+
+  /// Runs the module's `dispose` hook with the state of this instance and
+  /// forgets the instance.
+  ///
+  /// Throws a [GlobeRuntimeException] with
+  /// [GlobeRuntimeErrorCode.instanceNotFound] if it was disposed before.
+  void dispose() => runtime._instance!.disposeModuleInstance(id);
+
+  @override
+  String toString() => 'ModuleInstance($moduleName, $id)';
+}
+
 interface class GlobeRuntime {
   final _$GlobeRuntimeImpl? _instance;
 
@@ -114,36 +210,173 @@ interface class GlobeRuntime {
   static GlobeRuntime? _cachedInstance;
   static GlobeRuntime get instance {
     if (_cachedInstance != null) return _cachedInstance!;
-    return _cachedInstance = GlobeRuntime._(_$GlobeRuntimeImpl());
+    return _cachedInstance =
+        GlobeRuntime._(_$GlobeRuntimeImpl(const RuntimeConfig()));
   }
 
+  /// Creates a new, independent runtime.
+  ///
+  /// Each runtime has its own JavaScript isolate, module registry and
+  /// message port, configured by [config]. Call [dispose] once it is no
+  /// longer needed.
+  static GlobeRuntime create({RuntimeConfig config = const RuntimeConfig()}) {
+    return GlobeRuntime._(_$GlobeRuntimeImpl(config));
+  }
+
+  /// Registers [module], calling its `init` function with [args].
+  ///
+  /// Completes once `init` settled. Dart keeps running meanwhile, so `init`
+  /// can await host functions, see [registerHostFunction].
+  ///
+  /// With [permissions], code of the module's functions is restricted to
+  /// them in addition to the runtime's [RuntimeConfig.permissions], so a
+  /// module can never be granted more than the runtime.
   FutureOr<void> registerModule(
     Module module, {
     List<FFIConvertible?> args = const [],
+    RuntimePermissions? permissions,
   }) async {
     final source = await module.source;
-    return _instance!.registerModule(module.name, source, args);
+    return _instance!.registerModule(
+      module.name,
+      source,
+      args,
+      permissions: permissions,
+    );
+  }
+
+  /// Creates an instance of the registered [moduleName] with its own state.
+  ///
+  /// The module's `init` runs again with [args] and the value it returns, or
+  /// its promise resolves with, becomes the state the instance's functions
+  /// receive. A failing `init` throws a [GlobeRuntimeException]. Call
+  /// [ModuleInstance.dispose] once the instance is no longer needed.
+  Future<ModuleInstance> createModuleInstance(
+    String moduleName, {
+    List<FFIConvertible?> args = const [],
+  }) async {
+    final id = await _instance!.createModuleInstance(moduleName, args);
+    return ModuleInstance._(this, moduleName, id);
+  }
+
+  /// Registers [function] under [name], replacing any function registered
+  /// under it before, so modules can call it with `Dart.invoke`.
+  void registerHostFunction(String name, HostFunction function) {
+    _instance!._hostFunctions[name] = function;
+  }
+
+  /// Removes the host function registered under [name]. Later calls to it
+  /// are rejected.
+  void unregisterHostFunction(String name) {
+    _instance!._hostFunctions.remove(name);
   }
 
   bool isModuleRegistered(String moduleName) {
     return _instance!.isModuleRegisted(moduleName);
   }
 
-  void callFunction(
+  /// Calls [function] of [moduleName] and returns the identifier of the call.
+  ///
+  /// If the call has not settled within [timeout], or the runtime's
+  /// [RuntimeConfig.defaultTimeout], its `AbortSignal` is aborted, JavaScript
+  /// still running for it is terminated and [OnFunctionData] receives a final
+  /// timeout error.
+  int callFunction(
     String moduleName, {
     required String function,
     List<FFIConvertible?> args = const [],
     required OnFunctionData onData,
+    Duration? timeout,
   }) {
     return _instance!.callFunction(
       moduleName,
       function: function,
       args: args,
       onData: onData,
+      timeout: timeout,
+    );
+  }
+
+  /// Calls [function] of [moduleName] and returns a stream of the messages
+  /// [callFunction] would pass to [OnFunctionData], closed after the final
+  /// one.
+  ///
+  /// Chunks are acknowledged as the stream's listener takes them, so pausing
+  /// the subscription pauses the JavaScript producer once
+  /// [RuntimeConfig.streamWindow] chunks are waiting. Cancelling the
+  /// subscription cancels the call.
+  Stream<DartMessage> streamFunction(
+    String moduleName, {
+    required String function,
+    List<FFIConvertible?> args = const [],
+    Duration? timeout,
+  }) {
+    return _instance!.streamFunction(
+      moduleName,
+      function: function,
+      args: args,
+      timeout: timeout,
+    );
+  }
+
+  /// Calls [method] of the object behind [handle] and returns the identifier
+  /// of the call.
+  ///
+  /// The method runs with `this` set to the object and receives [args] only.
+  /// Its result is delivered like the result of [callFunction]; a released
+  /// handle or a missing method is reported to [onData] as an error.
+  ///
+  /// Throws a [GlobeRuntimeException] with
+  /// [GlobeRuntimeErrorCode.handleNotFound] if [handle] was created by another
+  /// runtime.
+  int callHandleMethod(
+    JsHandle handle,
+    String method, {
+    List<FFIConvertible?> args = const [],
+    required OnFunctionData onData,
+    Duration? timeout,
+  }) {
+    return _instance!.callHandleMethod(
+      handle,
+      method,
+      args: args,
+      onData: onData,
+      timeout: timeout,
     );
   }
 
+  /// Lets the runtime garbage collect the object behind [handle].
+  ///
+  /// Throws a [GlobeRuntimeException] with
+  /// [GlobeRuntimeErrorCode.handleNotFound] if it was released before or was
+  /// created by another runtime.
+  void releaseHandle(JsHandle handle) {
+    return _instance!.releaseHandle(handle);
+  }
+
+  /// The number of handles modules created with `Dart.handle` that were not
+  /// released yet, to find handles that are never released.
+  int get liveHandleCount => _instance!.liveHandleCount;
+
+  /// Cancels the call identified by [messageIdentifier].
+  ///
+  /// The JavaScript function's `AbortSignal` is aborted and [OnFunctionData]
+  /// receives a final message with [DartMessage.cancelled] set.
+  void cancelFunction(int messageIdentifier) {
+    return _instance!.cancelFunction(messageIdentifier);
+  }
+
+  /// Errors the runtime could not report to a call, e.g. errors thrown by
+  /// timers or promise rejections nothing handled, with
+  /// [GlobeRuntimeErrorCode.runtimeError].
+  ///
+  /// The runtime keeps running after reporting them.
+  Stream<GlobeRuntimeException> get errors => _instance!._errors.stream;
+
   String get version => _instance!.getVersion();
 
-  void dispose() => _instance!.dispose();
+  void dispose() {
+    if (identical(this, _cachedInstance)) _cachedInstance = null;
+    _instance!.dispose();
+  }
 }
@@ -0,0 +1,143 @@
+part of 'runtime.dart';
+
+/// Settings a [GlobeRuntime] is created with.
+///
+/// Settings left unset fall back to the runtime's defaults.
+class RuntimeConfig {
+  /// Version of the config format written by [toMsgPack].
+  static const version = 1;
+
+  /// Deadline for calls made without a timeout of their own.
+  final Duration? defaultTimeout;
+
+  /// Deadline for registering a module, covering its evaluation with
+  /// top-level `await` and its `init`, and for creating an instance.
+  /// JavaScript still running at the deadline is terminated. [Duration.zero]
+  /// disables it. Defaults to 30 seconds.
+  final Duration? moduleLoadTimeout;
+
+  /// Chunks a call may stream before `Dart.stream_value` waits for them to be
+  /// delivered to [OnFunctionData]. `0` disables flow control. Defaults to
+  /// 16.
+  final int? streamWindow;
+
+  /// Size the JavaScript heap starts out with, in megabytes.
+  final int? initialHeapSizeMb;
+
+  /// Size the JavaScript heap may grow to, in megabytes.
+  ///
+  /// When it is reached, calls in flight fail with an out-of-memory error
+  /// instead of the process aborting.
+  final int? maxHeapSizeMb;
+
+  /// Replace the isolate after it runs out of memory with a fresh one that
+  /// has the same modules registered, losing any module state.
+  ///
+  /// Modules and instances whose `init` was given streams, callbacks or
+  /// handles are not created again, which is reported on
+  /// [GlobeRuntime.errors].
+  final bool? recycleOnOutOfMemory;
+
+  /// Flags passed to V8, e.g. `--max-old-space-size=512`.
+  ///
+  /// V8 flags apply to the whole process, not just this runtime, and are
+  /// set by the first runtime created. Creating a runtime with flags V8
+  /// doesn't recognize, or with other flags than the first runtime, throws.
+  final List<String>? v8Flags;
+
+  /// File extensions tried when resolving npm packages. Defaults to `.mjs`.
+  final List<String>? moduleExtensions;
+
+  /// `package.json` fields holding a package's entry point, in order of
+  /// preference. Defaults to `module`.
+  final List<String>? moduleMainFields;
+
+  /// Whether TCP, UDP and TLS sockets are available. Defaults to `true`.
+  final bool? enableNet;
+
+  /// Limit of threads used for blocking work such as file system access.
+  /// Must be at least 1.
+  final int? maxBlockingThreads;
+
+  /// What modules are allowed to access. Everything is allowed when unset.
+  final RuntimePermissions? permissions;
+
+  const RuntimeConfig({
+    this.defaultTimeout,
+    this.moduleLoadTimeout,
+    this.streamWindow,
+    this.initialHeapSizeMb,
+    this.maxHeapSizeMb,
+    this.recycleOnOutOfMemory,
+    this.v8Flags,
+    this.moduleExtensions,
+    this.moduleMainFields,
+    this.enableNet,
+    this.maxBlockingThreads,
+    this.permissions,
+  });
+
+  Map<String, dynamic> toMap() => {
+        'version': version,
+        if (defaultTimeout != null)
+          'default_call_timeout_ms': defaultTimeout!.inMilliseconds,
+        if (moduleLoadTimeout != null)
+          'module_load_timeout_ms': moduleLoadTimeout!.inMilliseconds,
+        if (streamWindow != null) 'stream_window': streamWindow,
+        'heap': {
+          if (initialHeapSizeMb != null) 'initial_size_mb': initialHeapSizeMb,
+          if (maxHeapSizeMb != null) 'max_size_mb': maxHeapSizeMb,
+          if (recycleOnOutOfMemory != null)
+            'recycle_on_out_of_memory': recycleOnOutOfMemory,
+        },
+        if (v8Flags != null) 'v8_flags': v8Flags,
+        'module_resolution': {
+          if (moduleExtensions != null) 'extensions': moduleExtensions,
+          if (moduleMainFields != null) 'main_fields': moduleMainFields,
+        },
+        'extensions': {
+          if (enableNet != null) 'net': enableNet,
+        },
+        'tokio': {
+          if (maxBlockingThreads != null)
+            'max_blocking_threads': maxBlockingThreads,
+        },
+        if (permissions != null) 'permissions': permissions!.toMap(),
+      };
+
+  /// Encodes this config the way `init_runtime_with_config` expects it.
+  Uint8List toMsgPack() => msg_parkr.serialize(toMap());
+}
+
+/// Allowlists of what JavaScript running in a runtime may access.
+///
+/// An empty list denies everything of its kind, `*` allows everything.
+/// Denied operations throw a `PermissionDenied` error in JavaScript.
+class RuntimePermissions {
+  /// Hosts, optionally with a port (`host:port`), that `fetch` and sockets
+  /// may connect to.
+  final List<String> net;
+
+  /// Paths that may be read, including the files modules import.
+  final List<String> read;
+
+  /// Environment variables readable through `Deno.env`.
+  final List<String> env;
+
+  /// Origins remote modules may be imported from.
+  final List<String> import;
+
+  const RuntimePermissions({
+    this.net = const [],
+    this.read = const [],
+    this.env = const [],
+    this.import = const [],
+  });
+
+  Map<String, dynamic> toMap() => {
+        'net': net,
+        'read': read,
+        'env': env,
+        'import': import,
+      };
+}
@@ -18,7 +18,21 @@ enum FFITypeId {
   double(3),
   bool(4),
   bytes(5),
-  json_payload(6);
+  json_payload(6),
+  int64(7),
+  int8_list(8),
+  int16_list(9),
+  int32_list(10),
+  int64_list(11),
+  uint16_list(12),
+  uint32_list(13),
+  uint64_list(14),
+  float32_list(15),
+  float64_list(16),
+  external_bytes(17),
+  stream(18),
+  callback(19),
+  js_handle(20);
 
   final int value;
   const FFITypeId(this.value);
@@ -28,9 +42,21 @@ extension FFITypeExtension on Object {
   FFIConvertible get toFFIType => switch (this) {
         String() => FFIString(this as String),
         int() => FFIInt(this as int),
+        BigInt() => FFIBigInt(this as BigInt),
         double() => FFIDouble(this as double),
         bool() => FFIBool(this as bool),
+        Int8List() ||
+        Int16List() ||
+        Int32List() ||
+        Int64List() ||
+        Uint16List() ||
+        Uint32List() ||
+        Uint64List() ||
+        Float32List() ||
+        Float64List() =>
+          FFITypedList(this as TypedData),
         List<int>() => FFIBytes(this as List<int>),
+        Stream<List<int>>() => FFIStream(this as Stream<List<int>>),
         Map() => FFIJsonPayload(this),
         List() => FFIJsonPayload(this),
         Set() => FFIJsonPayload(this),
@@ -49,6 +75,10 @@ class FFIString implements FFIConvertible {
   FFITypeId get typeId => FFITypeId.string;
 }
 
+/// A 64-bit integer.
+///
+/// Arrives in JavaScript as a number when it fits in
+/// `Number.MAX_SAFE_INTEGER` and as a `BigInt` otherwise.
 class FFIInt implements FFIConvertible {
   final int value;
   FFIInt(this.value);
@@ -61,7 +91,19 @@ class FFIInt implements FFIConvertible {
   }
 
   @override
-  FFITypeId get typeId => FFITypeId.integer;
+  FFITypeId get typeId => FFITypeId.int64;
+}
+
+/// A [BigInt] in the signed 64-bit range, passed like [FFIInt].
+class FFIBigInt extends FFIInt {
+  FFIBigInt(BigInt value) : super(_toInt64(value));
+
+  static int _toInt64(BigInt value) {
+    if (!value.isValidInt) {
+      throw ArgumentError.value(value, 'value', 'Does not fit in 64 bits');
+    }
+    return value.toInt();
+  }
 }
 
 class FFIJsonPayload<T> extends FFIBytes {
@@ -113,6 +155,201 @@ class FFIBytes implements FFIConvertible {
   FFITypeId get typeId => FFITypeId.bytes;
 }
 
+/// A numeric list that arrives in JavaScript as the matching TypedArray, e.g.
+/// a [Float64List] as a `Float64Array`.
+///
+/// 64-bit integer lists arrive as `BigInt64Array` and `BigUint64Array`.
+class FFITypedList extends FFIBytes {
+  final TypedData data;
+
+  FFITypedList(this.data)
+      : super(data.buffer.asUint8List(data.offsetInBytes, data.lengthInBytes));
+
+  @override
+  FFITypeId get typeId => switch (data) {
+        Int8List() => FFITypeId.int8_list,
+        Int16List() => FFITypeId.int16_list,
+        Int32List() => FFITypeId.int32_list,
+        Int64List() => FFITypeId.int64_list,
+        Uint16List() => FFITypeId.uint16_list,
+        Uint32List() => FFITypeId.uint32_list,
+        Uint64List() => FFITypeId.uint64_list,
+        Float32List() => FFITypeId.float32_list,
+        Float64List() => FFITypeId.float64_list,
+        _ => throw ArgumentError.value(
+            data, 'data', 'Unsupported typed list ${data.runtimeType}'),
+      };
+}
+
+/// Bytes in native memory that JavaScript reads in place, without a copy.
+///
+/// Use it to pass large buffers such as files or images. JavaScript receives
+/// an `ArrayBuffer` over the memory, so it must not be changed or freed while
+/// the runtime may still use it. Once every call or module that received it
+/// is done with it and V8 has collected the `ArrayBuffer`, [onRelease] runs,
+/// by default freeing the memory with `malloc.free`. Released bytes can't be
+/// passed again.
+class FFIExternalBytes implements FFIConvertible {
+  final Pointer<Uint8> pointer;
+  final int length;
+  final void Function(Pointer<Uint8> pointer) onRelease;
+
+  int _references = 0;
+  bool _released = false;
+
+  FFIExternalBytes.fromPointer(
+    this.pointer,
+    this.length, {
+    this.onRelease = _free,
+  });
+
+  /// Allocates [length] bytes with `malloc`, to be filled through [bytes].
+  factory FFIExternalBytes.allocate(int length) =>
+      FFIExternalBytes.fromPointer(malloc<Uint8>(length), length);
+
+  /// Copies [data] into native memory once, so it isn't copied again when
+  /// passed to JavaScript.
+  factory FFIExternalBytes.fromList(List<int> data) =>
+      FFIExternalBytes.allocate(data.length)..bytes.setAll(0, data);
+
+  static void _free(Pointer<Uint8> pointer) => malloc.free(pointer);
+
+  /// A view of the memory, for filling it before it is passed.
+  Uint8List get bytes => pointer.asTypedList(length);
+
+  /// Whether the runtime is done with the memory and [onRelease] ran.
+  bool get isReleased => _released;
+
+  @override
+  Pointer<Void> toFFI() {
+    if (_released) {
+      throw StateError('External bytes at ${pointer.address} were released');
+    }
+    return pointer.cast();
+  }
+
+  @override
+  FFITypeId get typeId => FFITypeId.external_bytes;
+
+  void _retain() => _references++;
+
+  void _release([int count = 1]) {
+    _references -= count;
+    if (_references > 0 || _released) return;
+    _released = true;
+    onRelease(pointer);
+  }
+}
+
+/// Bytes produced over time, such as an upload or a file being read, that
+/// arrive in JavaScript as a `ReadableStream` of `Uint8Array` chunks.
+///
+/// [source] is listened to once the call is queued and its chunks are
+/// forwarded as they arrive. An error of [source] errors the `ReadableStream`,
+/// and [source] is cancelled when the call finishes first. An [FFIStream] can
+/// only be passed once.
+class FFIStream implements FFIConvertible {
+  final Stream<List<int>> source;
+
+  FFIStream(this.source);
+
+  static int _lastId = 0;
+
+  /// Identifies the stream in `push_stream_chunk` and friends.
+  final int id = ++_lastId;
+
+  StreamSubscription<List<int>>? _subscription;
+
+  @override
+  Pointer<Void> toFFI() {
+    if (_subscription != null) {
+      throw StateError('Stream $id was already passed to the runtime');
+    }
+
+    final ptr = calloc<Int32>();
+    ptr.value = id;
+    return ptr.cast();
+  }
+
+  @override
+  FFITypeId get typeId => FFITypeId.stream;
+}
+
+/// A Dart function that arrives in JavaScript as an async function.
+///
+/// Calling it in JavaScript runs [function] with the arguments, sent like
+/// values of `Dart.send`, and resolves with its result like `Dart.invoke`.
+/// The runtime holds on to [function] until every JavaScript function created
+/// for it was garbage collected.
+class FFICallback implements FFIConvertible {
+  final HostFunction function;
+
+  FFICallback(this.function);
+
+  static int _lastId = 0;
+
+  /// Identifies the function in the runtime's calls to it.
+  final int id = ++_lastId;
+
+  int _references = 0;
+
+  /// Whether JavaScript may still call [function].
+  bool get isRetained => _references > 0;
+
+  @override
+  Pointer<Void> toFFI() {
+    final ptr = calloc<Int32>();
+    ptr.value = id;
+    return ptr.cast();
+  }
+
+  @override
+  FFITypeId get typeId => FFITypeId.callback;
+}
+
+/// A JavaScript object handed to Dart with `Dart.handle`.
+///
+/// The object stays alive in the runtime that created it until
+/// [GlobeRuntime.releaseHandle] is called. Passing the handle as an argument
+/// passes the object itself, and [GlobeRuntime.callHandleMethod] calls its
+/// methods. Both only accept handles of their own runtime.
+class JsHandle implements FFIConvertible {
+  /// Identifies the object in the runtime that created it.
+  final int id;
+
+  /// The runtime that created the handle. Ids of different runtimes overlap.
+  final _$GlobeRuntimeImpl _runtime;
+
+  JsHandle._(this.id, this._runtime);
+
+  bool _released = false;
+
+  /// Whether [GlobeRuntime.releaseHandle] was called with this handle.
+  bool get isReleased => _released;
+
+  @override
+  Pointer<Void> toFFI() {
+    final ptr = calloc<Int32>();
+    ptr.value = id;
+    return ptr.cast();
+  }
+
+  @override
+  FFITypeId get typeId => FFITypeId.js_handle;
+
+  @override
+  bool operator ==(Object other) =>
+      other is JsHandle &&
+      other.id == id &&
+      identical(other._runtime, _runtime);
+
+  @override
+  int get hashCode => Object.hash(id, identityHashCode(_runtime));
+
+  @override
+  String toString() => 'JsHandle($id)';
+}
+
 typedef GetTypeArguments = ({
   Pointer<Pointer<Void>> argPointers,
   Pointer<Int32> typeIds,
@@ -143,6 +380,8 @@ GetTypeArguments getTypeArguments(List<FFIConvertible?> args) {
 
     if (objectAtIndex is FFIBytes) {
       sizes[i] = objectAtIndex.value.length;
+    } else if (objectAtIndex is FFIExternalBytes) {
+      sizes[i] = objectAtIndex.length;
     } else {
       sizes[i] = 0;
     }
@@ -162,3 +401,66 @@ extension MessagePackrExtensionForListInt on List<int> {
     return msg_parkr.deserialize(bytes);
   }
 }
+
+/// First element of the messages `Dart.invoke` and callback arguments post,
+/// see `dart_value.rs`.
+const _hostCallTag = 'globe:host_call';
+
+/// First element of the message releasing an [FFICallback].
+const _callbackReleaseTag = 'globe:release_callback';
+
+/// First element of errors reported on [GlobeRuntime.errors].
+const _runtimeErrorTag = 'globe:runtime_error';
+
+/// Values sent with `Dart.send`, `Dart.stream` and `Dart.stream_end`, keyed by
+/// the message they arrived with.
+final _nativeValues = Expando<_NativeValue>('globe_runtime.nativeValue');
+
+class _NativeValue {
+  final Object? value;
+  const _NativeValue(this.value);
+}
+
+/// Builds the message for a native `[callbackId, done, value]` message
+/// posted by [runtime].
+DartMessage _nativeMessage(
+  _$GlobeRuntimeImpl runtime,
+  bool done,
+  Object? value,
+) {
+  final message = DartMessage(done: done);
+  _nativeValues[message] = _NativeValue(_decodeNativeValue(runtime, value));
+  return message;
+}
+
+/// Turns the lists the runtime encodes objects as, starting with a
+/// [Capability], back into maps, and the objects `Dart.handle` returns into
+/// [JsHandle]s of [runtime].
+///
+/// Maps are the marker followed by pairs of keys and values, so their lists
+/// have an odd length. Handles are the marker followed by the handle id.
+Object? _decodeNativeValue(_$GlobeRuntimeImpl runtime, Object? value) {
+  if (value is! List || value is TypedData) return value;
+
+  if (value.isNotEmpty && value.first is Capability) {
+    if (value.length == 2) return JsHandle._(value[1] as int, runtime);
+
+    return <String, Object?>{
+      for (var i = 1; i + 1 < value.length; i += 2)
+        value[i] as String: _decodeNativeValue(runtime, value[i + 1]),
+    };
+  }
+
+  return [for (final item in value) _decodeNativeValue(runtime, item)];
+}
+
+extension DartMessageValueExtension on DartMessage {
+  /// Whether this message carries a value sent with `Dart.send`,
+  /// `Dart.stream` or `Dart.stream_end`.
+  bool get hasValue => _nativeValues[this] != null;
+
+  /// The value sent with `Dart.send`, `Dart.stream` or `Dart.stream_end`, as
+  /// `null`, [bool], [int], [double], [String], [List], `Map<String, Object?>`,
+  /// a typed list or a [JsHandle].
+  Object? get value => _nativeValues[this]?.value;
+}
@@ -0,0 +1,110 @@
+part of 'runtime.dart';
+
+/// Status codes returned by the native runtime library.
+enum GlobeRuntimeErrorCode {
+  /// A pointer, string or encoded config passed to the runtime was invalid.
+  invalidArgument(1),
+
+  /// The runtime could not be created.
+  initFailed(2),
+
+  /// The runtime does not exist or has been disposed.
+  runtimeNotFound(3),
+
+  /// Loading or initializing a module failed.
+  moduleRegistrationFailed(4),
+
+  /// The module has not been registered.
+  moduleNotFound(5),
+
+  /// The [JsHandle] was released, or was created by another runtime.
+  handleNotFound(6),
+
+  /// The [ModuleInstance] was disposed, or belongs to another runtime.
+  instanceNotFound(7),
+
+  /// An error that doesn't belong to any call, e.g. one thrown by a timer,
+  /// reported on [GlobeRuntime.errors].
+  runtimeError(8),
+
+  /// The runtime panicked. The runtime may be unusable afterwards.
+  panic(255);
+
+  final int value;
+  const GlobeRuntimeErrorCode(this.value);
+
+  static GlobeRuntimeErrorCode? fromValue(int value) {
+    for (final code in values) {
+      if (code.value == value) return code;
+    }
+    return null;
+  }
+}
+
+/// Thrown when the native runtime reports an error.
+class GlobeRuntimeException extends StateError {
+  /// What went wrong, or `null` for a status code this package doesn't know.
+  final GlobeRuntimeErrorCode? code;
+
+  /// The error JavaScript threw, e.g. from a module's `init`, if any.
+  final JsException? jsException;
+
+  GlobeRuntimeException(this.code, String message, {this.jsException})
+      : super(message);
+
+  @override
+  String toString() => 'GlobeRuntimeException(${code?.name}): $message';
+}
+
+/// A JavaScript error reported for a call, built from [DartMessage.jsError].
+///
+/// [name] is the error class, e.g. `TypeError`, which makes it possible to map
+/// JavaScript errors to typed Dart exceptions.
+class JsException implements Exception {
+  final String name;
+  final String message;
+
+  /// The error's `stack` as formatted by V8.
+  final String stack;
+  final List<JsStackFrame> frames;
+
+  /// The error's `cause`, if it had one.
+  final JsException? cause;
+
+  /// Custom enumerable properties of the error, e.g. `code`.
+  final Map<String, dynamic> properties;
+
+  const JsException({
+    required this.name,
+    required this.message,
+    this.stack = '',
+    this.frames = const [],
+    this.cause,
+    this.properties = const {},
+  });
+
+  factory JsException.fromProto(JsError error) {
+    final properties = error.properties.isEmpty
+        ? const <String, dynamic>{}
+        : Map<String, dynamic>.from(error.properties.unpack() as Map);
+
+    return JsException(
+      name: error.name,
+      message: error.message,
+      stack: error.stack,
+      frames: error.frames,
+      cause: error.hasCause() ? JsException.fromProto(error.cause) : null,
+      properties: properties,
+    );
+  }
+
+  @override
+  String toString() => name.isEmpty ? message : '$name: $message';
+}
+
+extension DartMessageErrorExtension on DartMessage {
+  /// The error of this message as a [JsException], or `null` if the message
+  /// carries no structured error.
+  JsException? get jsException =>
+      hasJsError() ? JsException.fromProto(jsError) : null;
+}
@@ -4,19 +4,24 @@ typedef GetRuntimeVersionC = NativeFunction<Pointer<Utf8> Function()>;
 typedef GetRuntimeVersionDart = Pointer<Utf8> Function();
 
 typedef _CallGlobeRuntimeInitFnNative = NativeFunction<
-    Int Function(
+    Uint64 Function(
       Pointer<Void>, // dart API-DL
       Uint64, // dart send port
+      Pointer<Uint8>, // msgpack-encoded config
+      Size, // config length
       Pointer<Pointer<Utf8>>, // error pointer
     )>;
 typedef _CallGlobeRuntimeInitFnDart = int Function(
   Pointer<Void>,
   int,
+  Pointer<Uint8>,
+  int,
   Pointer<Pointer<Utf8>>,
 );
 
 typedef _CallGlobeFunctionNative = NativeFunction<
-    Int Function(
+    Uint8 Function(
+      Uint64, // Runtime handle
       Pointer<Utf8>, // Module name
       Pointer<Utf8>, // Function name
       Int, // Message identifier
@@ -24,9 +29,11 @@ typedef _CallGlobeFunctionNative = NativeFunction<
       Pointer<Int32>, // Argument type IDs
       Pointer<IntPtr>, // Argument sizes (for List<String>, Uint8List)
       Int, // Number of arguments
+      Uint32, // Timeout in milliseconds
       Pointer<Pointer<Utf8>>, // error pointer
     )>;
 typedef _CallGlobeFunctionFnDart = int Function(
+  int,
   Pointer<Utf8>,
   Pointer<Utf8>,
   int,
@@ -34,13 +41,97 @@ typedef _CallGlobeFunctionFnDart = int Function(
   Pointer<Int32>,
   Pointer<IntPtr>,
   int,
+  int,
+  Pointer<Pointer<Utf8>>,
+);
+
+typedef _CallHandleMethodFnNative = NativeFunction<
+    Uint8 Function(
+      Uint64, // Runtime handle
+      Int32, // Handle id
+      Pointer<Utf8>, // Method name
+      Int, // Message identifier
+      Pointer<Pointer<Void>>, // Arguments pointer
+      Pointer<Int32>, // Argument type IDs
+      Pointer<IntPtr>, // Argument sizes (for List<String>, Uint8List)
+      Int, // Number of arguments
+      Uint32, // Timeout in milliseconds
+      Pointer<Pointer<Utf8>>, // error pointer
+    )>;
+typedef _CallHandleMethodFnDart = int Function(
+  int,
+  int,
+  Pointer<Utf8>,
+  int,
+  Pointer<Pointer<Void>>,
+  Pointer<Int32>,
+  Pointer<IntPtr>,
+  int,
+  int,
   Pointer<Pointer<Utf8>>,
 );
 
+typedef _CreateModuleInstanceFnNative = NativeFunction<
+    Uint8 Function(
+      Uint64, // Runtime handle
+      Pointer<Utf8>, // Module name
+      Int32, // Message identifier
+      Pointer<Pointer<Void>>, // Arguments pointer
+      Pointer<Int32>, // Argument type IDs
+      Pointer<IntPtr>, // Argument sizes (for List<String>, Uint8List)
+      Int, // Number of arguments
+      Pointer<Pointer<Utf8>>, // error pointer
+    )>;
+typedef _CreateModuleInstanceFnDart = int Function(
+  int,
+  Pointer<Utf8>,
+  int,
+  Pointer<Pointer<Void>>,
+  Pointer<Int32>,
+  Pointer<IntPtr>,
+  int,
+  Pointer<Pointer<Utf8>>,
+);
+
+typedef _CallInstanceFunctionFnNative = NativeFunction<
+    Uint8 Function(
+      Uint64, // Runtime handle
+      Int32, // Instance id
+      Pointer<Utf8>, // Function name
+      Int, // Message identifier
+      Pointer<Pointer<Void>>, // Arguments pointer
+      Pointer<Int32>, // Argument type IDs
+      Pointer<IntPtr>, // Argument sizes (for List<String>, Uint8List)
+      Int, // Number of arguments
+      Uint32, // Timeout in milliseconds
+      Pointer<Pointer<Utf8>>, // error pointer
+    )>;
+typedef _CallInstanceFunctionFnDart = int Function(
+  int,
+  int,
+  Pointer<Utf8>,
+  int,
+  Pointer<Pointer<Void>>,
+  Pointer<Int32>,
+  Pointer<IntPtr>,
+  int,
+  int,
+  Pointer<Pointer<Utf8>>,
+);
+
+typedef _DisposeModuleInstanceFnNative
+    = NativeFunction<Uint8 Function(Uint64, Int32, Pointer<Pointer<Utf8>>)>;
+typedef _DisposeModuleInstanceFnDart = int Function(
+    int, int, Pointer<Pointer<Utf8>>);
+
 typedef _RegisterModuleFnNative = NativeFunction<
     Uint8 Function(
+      Uint64, // Runtime handle
       Pointer<Utf8>,
       Pointer<Utf8>,
+      Int, // Message identifier
+      Pointer<Uint8>, // msgpack-encoded module permissions
+      Size, // permissions length
       Pointer<Pointer<Utf8>>,
       //
       Pointer<Pointer<Void>>, // Arguments pointer
@@ -49,8 +140,12 @@ typedef _RegisterModuleFnNative = NativeFunction<
       Int, // Number of arguments
     )>;
 typedef _RegisterModuleFnDart = int Function(
+  int,
   Pointer<Utf8>,
   Pointer<Utf8>,
+  int,
+  Pointer<Uint8>,
+  int,
   Pointer<Pointer<Utf8>>,
   //
   Pointer<Pointer<Void>>,
@@ -59,12 +154,78 @@ typedef _RegisterModuleFnDart = int Function(
   int,
 );
 
-typedef _IsModuleRegisteredFnNative
-    = NativeFunction<Uint8 Function(Pointer<Utf8>)>;
-typedef _IsModuleRegisteredFnDart = int Function(Pointer<Utf8>);
+typedef _IsModuleRegisteredFnNative = NativeFunction<
+    Uint8 Function(Uint64, Pointer<Utf8>, Pointer<Pointer<Utf8>>)>;
+typedef _IsModuleRegisteredFnDart = int Function(
+    int, Pointer<Utf8>, Pointer<Pointer<Utf8>>);
+
+typedef _CancelJsCallFnNative
+    = NativeFunction<Uint8 Function(Uint64, Int, Pointer<Pointer<Utf8>>)>;
+typedef _CancelJsCallFnDart = int Function(int, int, Pointer<Pointer<Utf8>>);
+
+typedef _AcknowledgeStreamChunksFnNative = NativeFunction<
+    Uint8 Function(Uint64, Int32, Uint32, Pointer<Pointer<Utf8>>)>;
+typedef _AcknowledgeStreamChunksFnDart = int Function(
+    int, int, int, Pointer<Pointer<Utf8>>);
+
+typedef _PushStreamChunkFnNative = NativeFunction<
+    Uint8 Function(
+        Uint64, Int32, Pointer<Uint8>, Size, Pointer<Pointer<Utf8>>)>;
+typedef _PushStreamChunkFnDart = int Function(
+    int, int, Pointer<Uint8>, int, Pointer<Pointer<Utf8>>);
 
-typedef _DisposeAiFnNative = NativeFunction<Uint8 Function()>;
-typedef _DisposeAiFnDart = int Function();
+typedef _CloseStreamFnNative
+    = NativeFunction<Uint8 Function(Uint64, Int32, Pointer<Pointer<Utf8>>)>;
+typedef _CloseStreamFnDart = int Function(int, int, Pointer<Pointer<Utf8>>);
+
+typedef _ErrorStreamFnNative = NativeFunction<
+    Uint8 Function(Uint64, Int32, Pointer<Utf8>, Pointer<Pointer<Utf8>>)>;
+typedef _ErrorStreamFnDart = int Function(
+    int, int, Pointer<Utf8>, Pointer<Pointer<Utf8>>);
+
+typedef _CompleteHostCallFnNative = NativeFunction<
+    Uint8 Function(
+        Uint64, Int32, Pointer<Uint8>, Size, Pointer<Pointer<Utf8>>)>;
+typedef _CompleteHostCallFnDart = int Function(
+    int, int, Pointer<Uint8>, int, Pointer<Pointer<Utf8>>);
+
+typedef _FailHostCallFnNative = NativeFunction<
+    Uint8 Function(Uint64, Int32, Pointer<Utf8>, Pointer<Pointer<Utf8>>)>;
+typedef _FailHostCallFnDart = int Function(
+    int, int, Pointer<Utf8>, Pointer<Pointer<Utf8>>);
+
+typedef _ReleaseJsHandleFnNative
+    = NativeFunction<Uint8 Function(Uint64, Int32, Pointer<Pointer<Utf8>>)>;
+typedef _ReleaseJsHandleFnDart = int Function(
+    int, int, Pointer<Pointer<Utf8>>);
+
+typedef _GetJsHandleCountFnNative = NativeFunction<
+    Uint8 Function(Uint64, Pointer<Uint32>, Pointer<Pointer<Utf8>>)>;
+typedef _GetJsHandleCountFnDart = int Function(
+    int, Pointer<Uint32>, Pointer<Pointer<Utf8>>);
+
+typedef _DisposeAiFnNative
+    = NativeFunction<Uint8 Function(Uint64, Pointer<Pointer<Utf8>>)>;
+typedef _DisposeAiFnDart = int Function(int, Pointer<Pointer<Utf8>>);
+
+/// Throws a [GlobeRuntimeException] for a non-zero status code [result],
+/// with the message the runtime left in [errorPtr] or [fallback].
+///
+/// Frees [errorPtr] either way.
+void _checkResult(
+  int result,
+  Pointer<Pointer<Utf8>> errorPtr,
+  String fallback,
+) {
+  final Pointer<Utf8> errorMsgPtr = errorPtr.value;
+  calloc.free(errorPtr);
+  if (result == 0) return;
+
+  final errorMgs =
+      errorMsgPtr.address == 0 ? fallback : errorMsgPtr.toDartString();
+
+  throw GlobeRuntimeException(GlobeRuntimeErrorCode.fromValue(result), errorMgs);
+}
 
 void validateRuntimeLibraryExists(String path) {
   if (!File(path).existsSync()) {
@@ -94,6 +255,29 @@ class _$GlobeRuntimeImpl {
   final ReceivePort _receivePort;
   final HashMap<int, OnFunctionData> _callbacks = HashMap();
 
+  /// External bytes the runtime may still use, keyed by address.
+  final HashMap<int, FFIExternalBytes> _externalBytes = HashMap();
+
+  /// Functions JavaScript can call with `Dart.invoke`, keyed by name.
+  final HashMap<String, HostFunction> _hostFunctions = HashMap();
+
+  /// Callback arguments JavaScript may still call, keyed by id.
+  final HashMap<int, FFICallback> _callbackArgs = HashMap();
+
+  /// Streams passed to calls that are still running, keyed by call.
+  final HashMap<int, List<FFIStream>> _inputStreams = HashMap();
+
+  /// Calls whose chunks are acknowledged as the listener of their stream
+  /// takes them, see [streamFunction].
+  final HashSet<int> _listenerPacedCalls = HashSet();
+
+  /// Errors that don't belong to any call, see [GlobeRuntime.errors].
+  final StreamController<GlobeRuntimeException> _errors =
+      StreamController.broadcast();
+
+  /// Opaque handle of the native runtime owned by this instance.
+  late final int _runtimeHandle;
+
   int _messageCount = 0;
 
   static final dylib = () {
@@ -104,11 +288,11 @@ class _$GlobeRuntimeImpl {
   }();
 
   final _globeRuntimeInitFn = dylib
-      .lookup<_CallGlobeRuntimeInitFnNative>('init_runtime')
+      .lookup<_CallGlobeRuntimeInitFnNative>('init_runtime_with_config')
       .asFunction<_CallGlobeRuntimeInitFnDart>();
 
   final _registerModuleFn = dylib
-      .lookup<_RegisterModuleFnNative>('register_module')
+      .lookup<_RegisterModuleFnNative>('register_module_with_permissions')
       .asFunction<_RegisterModuleFnDart>();
 
   final _isModuleRegisteredFn = dylib
@@ -119,6 +303,58 @@ class _$GlobeRuntimeImpl {
       .lookup<_CallGlobeFunctionNative>('call_js_function')
       .asFunction<_CallGlobeFunctionFnDart>();
 
+  final _createModuleInstanceFn = dylib
+      .lookup<_CreateModuleInstanceFnNative>('create_module_instance')
+      .asFunction<_CreateModuleInstanceFnDart>();
+
+  final _callInstanceFunctionFn = dylib
+      .lookup<_CallInstanceFunctionFnNative>('call_js_instance_function')
+      .asFunction<_CallInstanceFunctionFnDart>();
+
+  final _disposeModuleInstanceFn = dylib
+      .lookup<_DisposeModuleInstanceFnNative>('dispose_module_instance')
+      .asFunction<_DisposeModuleInstanceFnDart>();
+
+  final _callHandleMethodFn = dylib
+      .lookup<_CallHandleMethodFnNative>('call_js_handle_method')
+      .asFunction<_CallHandleMethodFnDart>();
+
+  final _releaseJsHandleFn = dylib
+      .lookup<_ReleaseJsHandleFnNative>('release_js_handle')
+      .asFunction<_ReleaseJsHandleFnDart>();
+
+  final _getJsHandleCountFn = dylib
+      .lookup<_GetJsHandleCountFnNative>('get_js_handle_count')
+      .asFunction<_GetJsHandleCountFnDart>();
+
+  final _cancelJsCallFn = dylib
+      .lookup<_CancelJsCallFnNative>('cancel_js_call')
+      .asFunction<_CancelJsCallFnDart>();
+
+  final _acknowledgeStreamChunksFn = dylib
+      .lookup<_AcknowledgeStreamChunksFnNative>('acknowledge_stream_chunks')
+      .asFunction<_AcknowledgeStreamChunksFnDart>();
+
+  final _pushStreamChunkFn = dylib
+      .lookup<_PushStreamChunkFnNative>('push_stream_chunk')
+      .asFunction<_PushStreamChunkFnDart>();
+
+  final _closeStreamFn = dylib
+      .lookup<_CloseStreamFnNative>('close_stream')
+      .asFunction<_CloseStreamFnDart>();
+
+  final _errorStreamFn = dylib
+      .lookup<_ErrorStreamFnNative>('error_stream')
+      .asFunction<_ErrorStreamFnDart>();
+
+  final _completeHostCallFn = dylib
+      .lookup<_CompleteHostCallFnNative>('complete_host_call')
+      .asFunction<_CompleteHostCallFnDart>();
+
+  final _failHostCallFn = dylib
+      .lookup<_FailHostCallFnNative>('fail_host_call')
+      .asFunction<_FailHostCallFnDart>();
+
   final _disposeRuntimeFn = dylib
       .lookup<_DisposeAiFnNative>('dispose_runtime')
       .asFunction<_DisposeAiFnDart>();
@@ -127,37 +363,89 @@ class _$GlobeRuntimeImpl {
   final GetRuntimeVersionDart getRuntimeVersion =
       dylib.lookup<GetRuntimeVersionC>('get_runtime_version').asFunction();
 
-  _$GlobeRuntimeImpl() : _receivePort = ReceivePort("globe_runtime") {
+  _$GlobeRuntimeImpl(RuntimeConfig config)
+      : _receivePort = ReceivePort("globe_runtime") {
+    final configBytes = config.toMsgPack();
+    final configPtr = malloc<Uint8>(configBytes.length);
+    configPtr.asTypedList(configBytes.length).setAll(0, configBytes);
+
     final Pointer<Pointer<Utf8>> errorPtr = calloc();
-    final initialized = _globeRuntimeInitFn.call(
+    _runtimeHandle = _globeRuntimeInitFn.call(
       NativeApi.initializeApiDLData,
       _receivePort.sendPort.nativePort,
+      configPtr,
+      configBytes.length,
       errorPtr,
     );
-    if (initialized != 0) {
+    malloc.free(configPtr);
+
+    if (_runtimeHandle == 0) {
       final Pointer<Utf8> errorMsgPtr = errorPtr.value;
+      calloc.free(errorPtr);
       final errorMgs = errorMsgPtr.address == 0
           ? "Failed to initialize Globe Runtime"
           : errorMsgPtr.toDartString();
 
-      throw StateError(errorMgs);
+      throw GlobeRuntimeException(GlobeRuntimeErrorCode.initFailed, errorMgs);
     }
 
     calloc.free(errorPtr);
 
     _receivePort.listen((data) {
+      // The runtime is done with the external bytes at this address
+      if (data is int) return _releaseExternalBytes(data);
+
       if (data is! List) return;
 
+      // `[tag, requestId, name or callback id, args]`, sent by `Dart.invoke`
+      // and callback arguments
+      if (data.first == _hostCallTag) {
+        _handleHostCall(data[1] as int, data[2] as Object, data[3] as List);
+        return;
+      }
+
+      // JavaScript collected every function created for the callback
+      if (data.first == _callbackReleaseTag) {
+        return _releaseCallback(data[1] as int);
+      }
+
+      // `[tag, code, message]`
+      if (data.first == _runtimeErrorTag) {
+        final code = GlobeRuntimeErrorCode.fromValue(data[1] as int);
+        _errors.add(GlobeRuntimeException(code, data[2] as String));
+        return;
+      }
+
       // callbackId will always be the first element
       final callbackId = data[0] as int;
 
-      final callbackData = DartMessage.fromBuffer(data[1]);
+      // Values sent with `Dart.send` arrive as native objects, other
+      // messages as an encoded `DartMessage`
+      final callbackData = data.length == 3
+          ? _nativeMessage(this, data[1] as bool, data[2])
+          : DartMessage.fromBuffer(data[1]);
 
       final callback = _callbacks[callbackId];
-      if (callback == null) return;
 
       // If the callback returns true, remove it from the list
-      if (callback(callbackData)) _callbacks.remove(callbackId);
+      if (callback != null && callback(callbackData)) {
+        _callbacks.remove(callbackId);
+      }
+
+      // Lets the stream send another chunk, see `RuntimeConfig.streamWindow`.
+      // A chunk is consumed once `onData` handled it, streams of
+      // [streamFunction] acknowledge chunks as their listener takes them.
+      if (!callbackData.done && !_listenerPacedCalls.contains(callbackId)) {
+        _acknowledgeChunks(callbackId, 1);
+      }
+
+      // Streams passed to a finished call no longer need to be read
+      if (callbackData.done) {
+        _listenerPacedCalls.remove(callbackId);
+        for (final stream in _inputStreams.remove(callbackId) ?? const []) {
+          stream._subscription?.cancel();
+        }
+      }
     });
 
     ProcessSignal.sigterm.watch().listen((_) {
@@ -167,99 +455,526 @@ class _$GlobeRuntimeImpl {
 
   String getVersion() {
     final versionPtr = getRuntimeVersion();
+    if (versionPtr == nullptr) {
+      throw GlobeRuntimeException(
+        GlobeRuntimeErrorCode.panic,
+        "Failed to read Globe Runtime version",
+      );
+    }
     return versionPtr.toDartString();
   }
 
   void dispose() {
     _receivePort.close();
-    final result = _disposeRuntimeFn.call();
-    if (result == 0) return;
-    throw StateError("Failed to dispose AI SDK");
+    _errors.close();
+    for (final streams in _inputStreams.values) {
+      for (final stream in streams) {
+        stream._subscription?.cancel();
+      }
+    }
+    _inputStreams.clear();
+
+    final Pointer<Pointer<Utf8>> errorPtr = calloc();
+    final result = _disposeRuntimeFn.call(_runtimeHandle, errorPtr);
+
+    // Releases can no longer arrive, and the runtime no longer uses the bytes
+    for (final bytes in _externalBytes.values) {
+      bytes._release(bytes._references);
+    }
+    _externalBytes.clear();
+    for (final callback in _callbackArgs.values) {
+      callback._references = 0;
+    }
+    _callbackArgs.clear();
+
+    _checkResult(result, errorPtr, "Failed to dispose Globe Runtime");
   }
 
-  void callFunction(
+  // Every external bytes and callback argument is released by the runtime
+  // once, unless the runtime couldn't be found.
+  void _retainArgs(List<FFIConvertible?> args) {
+    for (final arg in args.whereType<FFIExternalBytes>()) {
+      arg._retain();
+      _externalBytes[arg.pointer.address] = arg;
+    }
+    for (final arg in args.whereType<FFICallback>()) {
+      arg._references++;
+      _callbackArgs[arg.id] = arg;
+    }
+  }
+
+  void _releaseUnusedArgs(List<FFIConvertible?> args, int result) {
+    if (result != GlobeRuntimeErrorCode.runtimeNotFound.value) return;
+    for (final arg in args.whereType<FFIExternalBytes>()) {
+      _releaseExternalBytes(arg.pointer.address);
+    }
+    for (final arg in args.whereType<FFICallback>()) {
+      _releaseCallback(arg.id);
+    }
+  }
+
+  void _releaseCallback(int id) {
+    final callback = _callbackArgs[id];
+    if (callback == null) return;
+
+    callback._references--;
+    if (!callback.isRetained) _callbackArgs.remove(id);
+  }
+
+  void _releaseExternalBytes(int address) {
+    final bytes = _externalBytes[address];
+    if (bytes == null) return;
+
+    bytes._release();
+    if (bytes.isReleased) _externalBytes.remove(address);
+  }
+
+  // Forwards the chunks of stream arguments once the call or registration
+  // they were passed to is queued. Like acknowledgements, pushes only fail
+  // once the runtime is disposed, so errors are ignored.
+  void _pipeStreams(List<FFIConvertible?> args, {int? messageIdentifier}) {
+    final streams = args.whereType<FFIStream>().toList();
+    if (streams.isEmpty) return;
+    if (messageIdentifier != null) _inputStreams[messageIdentifier] = streams;
+
+    for (final stream in streams) {
+      stream._subscription = stream.source.listen(
+        (chunk) => _pushStreamChunk(stream.id, chunk),
+        onError: (Object error) {
+          final messagePtr = error.toString().toNativeUtf8();
+          _errorStreamFn(_runtimeHandle, stream.id, messagePtr, nullptr);
+          malloc.free(messagePtr);
+        },
+        onDone: () => _closeStreamFn(_runtimeHandle, stream.id, nullptr),
+        cancelOnError: true,
+      );
+    }
+  }
+
+  void _pushStreamChunk(int streamId, List<int> chunk) {
+    if (chunk.isEmpty) return;
+
+    final chunkPtr = malloc<Uint8>(chunk.length);
+    chunkPtr.asTypedList(chunk.length).setAll(0, chunk);
+    _pushStreamChunkFn(_runtimeHandle, streamId, chunkPtr, chunk.length, nullptr);
+    malloc.free(chunkPtr);
+  }
+
+  int callFunction(
     String moduleName, {
     required String function,
     List<FFIConvertible?> args = const [],
     required OnFunctionData onData,
+    Duration? timeout,
   }) {
     final moduleNamePtr = moduleName.toNativeUtf8();
     final functionNamePtr = function.toNativeUtf8();
-    final arguments = getTypeArguments(args);
 
+    try {
+      return _startCall(
+        args,
+        onData,
+        (messageIdentifier, arguments, errorPtr) => _callGlobeFunction(
+          _runtimeHandle,
+          moduleNamePtr,
+          functionNamePtr,
+          messageIdentifier,
+          arguments.argPointers,
+          arguments.typeIds,
+          arguments.sizes,
+          args.length,
+          timeout?.inMilliseconds ?? 0,
+          errorPtr,
+        ),
+      );
+    } finally {
+      malloc.free(functionNamePtr);
+      malloc.free(moduleNamePtr);
+    }
+  }
+
+  Stream<DartMessage> streamFunction(
+    String moduleName, {
+    required String function,
+    List<FFIConvertible?> args = const [],
+    Duration? timeout,
+  }) {
+    final received = Queue<DartMessage>();
+    late final int messageIdentifier;
+    var finished = false;
+    late final StreamController<DartMessage> controller;
+
+    // Hands received messages to the listener while it is not paused and
+    // lets the call stream as many chunks as it took
+    void deliver() {
+      var consumed = 0;
+      while (received.isNotEmpty &&
+          controller.hasListener &&
+          !controller.isPaused) {
+        final message = received.removeFirst();
+        controller.add(message);
+        if (message.done) {
+          controller.close();
+        } else {
+          consumed++;
+        }
+      }
+      if (consumed > 0) _acknowledgeChunks(messageIdentifier, consumed);
+    }
+
+    controller = StreamController(
+      // So a chunk is only acknowledged once the listener took it
+      sync: true,
+      onListen: () {
+        try {
+          messageIdentifier = callFunction(
+            moduleName,
+            function: function,
+            args: args,
+            timeout: timeout,
+            onData: (message) {
+              finished = message.done;
+              received.add(message);
+              deliver();
+              return message.done;
+            },
+          );
+          _listenerPacedCalls.add(messageIdentifier);
+        } catch (error, stackTrace) {
+          finished = true;
+          controller
+            ..addError(error, stackTrace)
+            ..close();
+        }
+      },
+      // Not from within `resume`, which may run in a listener callback
+      onResume: () => scheduleMicrotask(deliver),
+      onCancel: () {
+        if (!finished) cancelFunction(messageIdentifier);
+      },
+    );
+
+    return controller.stream;
+  }
+
+  int callInstanceFunction(
+    int instanceId, {
+    required String function,
+    List<FFIConvertible?> args = const [],
+    required OnFunctionData onData,
+    Duration? timeout,
+  }) {
+    final functionNamePtr = function.toNativeUtf8();
+
+    try {
+      return _startCall(
+        args,
+        onData,
+        (messageIdentifier, arguments, errorPtr) => _callInstanceFunctionFn(
+          _runtimeHandle,
+          instanceId,
+          functionNamePtr,
+          messageIdentifier,
+          arguments.argPointers,
+          arguments.typeIds,
+          arguments.sizes,
+          args.length,
+          timeout?.inMilliseconds ?? 0,
+          errorPtr,
+        ),
+      );
+    } finally {
+      malloc.free(functionNamePtr);
+    }
+  }
+
+  int callHandleMethod(
+    JsHandle handle,
+    String method, {
+    List<FFIConvertible?> args = const [],
+    required OnFunctionData onData,
+    Duration? timeout,
+  }) {
+    _checkHandleOwner(handle);
+    final methodPtr = method.toNativeUtf8();
+
+    try {
+      return _startCall(
+        args,
+        onData,
+        (messageIdentifier, arguments, errorPtr) => _callHandleMethodFn(
+          _runtimeHandle,
+          handle.id,
+          methodPtr,
+          messageIdentifier,
+          arguments.argPointers,
+          arguments.typeIds,
+          arguments.sizes,
+          args.length,
+          timeout?.inMilliseconds ?? 0,
+          errorPtr,
+        ),
+      );
+    } finally {
+      malloc.free(methodPtr);
+    }
+  }
+
+  // Queues a call with [queue] under a new message identifier, keeping the
+  // arguments Dart shares with the runtime alive for it.
+  int _startCall(
+    List<FFIConvertible?> args,
+    OnFunctionData onData,
+    int Function(
+      int messageIdentifier,
+      GetTypeArguments arguments,
+      Pointer<Pointer<Utf8>> errorPtr,
+    ) queue,
+  ) {
+    final arguments = getTypeArguments(args);
     final Pointer<Pointer<Utf8>> errorPtr = calloc();
 
     _messageCount += 1;
     final int messageIdentifier = _messageCount;
     _callbacks[messageIdentifier] = onData;
 
-    final callResult = _callGlobeFunction(
+    _retainArgs(args);
+    final callResult = queue(messageIdentifier, arguments, errorPtr);
+
+    arguments.free();
+    _releaseUnusedArgs(args, callResult);
+
+    if (callResult != 0) _callbacks.remove(messageIdentifier);
+    _checkResult(callResult, errorPtr, "Failed to call Globe Function");
+    _pipeStreams(args, messageIdentifier: messageIdentifier);
+
+    return messageIdentifier;
+  }
+
+  void releaseHandle(JsHandle handle) {
+    _checkHandleOwner(handle);
+    final Pointer<Pointer<Utf8>> errorPtr = calloc();
+    final result = _releaseJsHandleFn(_runtimeHandle, handle.id, errorPtr);
+    if (result == 0 || result == GlobeRuntimeErrorCode.handleNotFound.value) {
+      handle._released = true;
+    }
+    _checkResult(result, errorPtr, "Failed to release $handle");
+  }
+
+  // This runtime may hold a different object under the id of another
+  // runtime's handle.
+  void _checkHandleOwner(JsHandle handle) {
+    if (identical(handle._runtime, this)) return;
+    throw GlobeRuntimeException(
+      GlobeRuntimeErrorCode.handleNotFound,
+      '$handle belongs to another runtime',
+    );
+  }
+
+  int get liveHandleCount {
+    final countPtr = calloc<Uint32>();
+    final Pointer<Pointer<Utf8>> errorPtr = calloc();
+    final result = _getJsHandleCountFn(_runtimeHandle, countPtr, errorPtr);
+    final count = countPtr.value;
+    calloc.free(countPtr);
+
+    _checkResult(result, errorPtr, "Failed to count JS handles");
+    return count;
+  }
+
+  // Fails only once the runtime is disposed, when acknowledgements no longer
+  // matter, so errors are ignored.
+  void _acknowledgeChunks(int messageIdentifier, int count) {
+    _acknowledgeStreamChunksFn(
+      _runtimeHandle,
+      messageIdentifier,
+      count,
+      nullptr,
+    );
+  }
+
+  // Answers are dropped by the runtime once it is disposed, so errors are
+  // ignored.
+  Future<void> _handleHostCall(int requestId, Object target, List args) async {
+    final Uint8List payload;
+    try {
+      final function = target is int
+          ? _callbackArgs[target]?.function
+          : _hostFunctions[target];
+      if (function == null) {
+        throw StateError('Host function `$target` is not registered');
+      }
+
+      final decodedArgs = _decodeNativeValue(this, args) as List<Object?>;
+      payload = msg_parkr.serialize(await function(decodedArgs));
+    } catch (error) {
+      final messagePtr = error.toString().toNativeUtf8();
+      _failHostCallFn(_runtimeHandle, requestId, messagePtr, nullptr);
+      malloc.free(messagePtr);
+      return;
+    }
+
+    final payloadPtr = malloc<Uint8>(payload.length);
+    payloadPtr.asTypedList(payload.length).setAll(0, payload);
+    _completeHostCallFn(
+      _runtimeHandle,
+      requestId,
+      payloadPtr,
+      payload.length,
+      nullptr,
+    );
+    malloc.free(payloadPtr);
+  }
+
+  void cancelFunction(int messageIdentifier) {
+    if (!_callbacks.containsKey(messageIdentifier)) return;
+
+    final Pointer<Pointer<Utf8>> errorPtr = calloc();
+    final result = _cancelJsCallFn(_runtimeHandle, messageIdentifier, errorPtr);
+    _checkResult(result, errorPtr, "Failed to cancel call $messageIdentifier");
+  }
+
+  // Completes once the module's `init` settled. The runtime posts the outcome
+  // like the result of a call, so Dart can answer `Dart.invoke` meanwhile.
+  Future<void> registerModule(
+    String name,
+    String source,
+    List<FFIConvertible?> args, {
+    RuntimePermissions? permissions,
+  }) {
+    final arguments = getTypeArguments(args);
+
+    final moduleNamePtr = name.toNativeUtf8();
+    final moduleSrcPtr = source.toNativeUtf8();
+
+    final permissionBytes = permissions == null
+        ? null
+        : msg_parkr.serialize(permissions.toMap());
+    final Pointer<Uint8> permissionsPtr = permissionBytes == null
+        ? nullptr
+        : (malloc<Uint8>(permissionBytes.length)
+          ..asTypedList(permissionBytes.length).setAll(0, permissionBytes));
+
+    final Pointer<Pointer<Utf8>> errorPtr = calloc();
+
+    _messageCount += 1;
+    final int messageIdentifier = _messageCount;
+    final completer = Completer<void>();
+    _callbacks[messageIdentifier] = (message) {
+      if (message.hasError()) {
+        completer.completeError(GlobeRuntimeException(
+          GlobeRuntimeErrorCode.moduleRegistrationFailed,
+          message.error,
+          jsException: message.jsException,
+        ));
+      } else {
+        completer.complete();
+      }
+      return true;
+    };
+
+    _retainArgs(args);
+    final result = _registerModuleFn(
+      _runtimeHandle,
       moduleNamePtr,
-      functionNamePtr,
+      moduleSrcPtr,
       messageIdentifier,
+      permissionsPtr,
+      permissionBytes?.length ?? 0,
+      errorPtr,
       arguments.argPointers,
       arguments.typeIds,
       arguments.sizes,
       args.length,
-      errorPtr,
     );
 
-    malloc.free(functionNamePtr);
+    if (permissionsPtr != nullptr) malloc.free(permissionsPtr);
     malloc.free(moduleNamePtr);
+    malloc.free(moduleSrcPtr);
     arguments.free();
+    _releaseUnusedArgs(args, result);
 
-    if (callResult != 0) {
-      final Pointer<Utf8> errorMsgPtr = errorPtr.value;
-      final errorMgs = errorMsgPtr.address == 0
-          ? "Failed to call Globe Function"
-          : errorMsgPtr.toDartString();
-
-      throw StateError(errorMgs);
-    }
+    if (result != 0) _callbacks.remove(messageIdentifier);
+    _checkResult(result, errorPtr, "Failed to register `$name` module");
+    _pipeStreams(args);
 
-    calloc.free(errorPtr);
+    return completer.future;
   }
 
-  FutureOr<void> registerModule(
-    String name,
-    String source,
+  Future<int> createModuleInstance(
+    String moduleName,
     List<FFIConvertible?> args,
-  ) async {
+  ) {
     final arguments = getTypeArguments(args);
-
-    final moduleNamePtr = name.toNativeUtf8();
-    final moduleSrcPtr = source.toNativeUtf8();
-
+    final moduleNamePtr = moduleName.toNativeUtf8();
     final Pointer<Pointer<Utf8>> errorPtr = calloc();
 
-    if (_registerModuleFn(
-          moduleNamePtr,
-          moduleSrcPtr,
-          errorPtr,
-          arguments.argPointers,
-          arguments.typeIds,
-          arguments.sizes,
-          args.length,
-        ) !=
-        0) {
-      final Pointer<Utf8> errorMsgPtr = errorPtr.value;
-      final errorMgs = errorMsgPtr.address == 0
-          ? "Failed to register `$name` module"
-          : errorMsgPtr.toDartString();
+    _messageCount += 1;
+    final int messageIdentifier = _messageCount;
+    final completer = Completer<int>();
+    _callbacks[messageIdentifier] = (message) {
+      if (message.hasError()) {
+        completer.completeError(GlobeRuntimeException(
+          GlobeRuntimeErrorCode.moduleRegistrationFailed,
+          message.error,
+          jsException: message.jsException,
+        ));
+      } else {
+        completer.complete(message.value as int);
+      }
+      return true;
+    };
+
+    // `init` may read its stream arguments before it settles
+    _retainArgs(args);
+    _pipeStreams(args);
+    final result = _createModuleInstanceFn(
+      _runtimeHandle,
+      moduleNamePtr,
+      messageIdentifier,
+      arguments.argPointers,
+      arguments.typeIds,
+      arguments.sizes,
+      args.length,
+      errorPtr,
+    );
+
+    malloc.free(moduleNamePtr);
+    arguments.free();
+    _releaseUnusedArgs(args, result);
 
-      throw StateError(errorMgs);
+    if (result != 0) {
+      _callbacks.remove(messageIdentifier);
+      for (final stream in args.whereType<FFIStream>()) {
+        stream._subscription?.cancel();
+      }
     }
+    _checkResult(result, errorPtr, "Failed to create `$moduleName` instance");
 
-    malloc.free(moduleNamePtr);
-    malloc.free(moduleSrcPtr);
-    calloc.free(errorPtr);
+    return completer.future;
+  }
+
+  void disposeModuleInstance(int instanceId) {
+    final Pointer<Pointer<Utf8>> errorPtr = calloc();
+    final result =
+        _disposeModuleInstanceFn(_runtimeHandle, instanceId, errorPtr);
+    _checkResult(result, errorPtr, "Failed to dispose instance $instanceId");
   }
 
   bool isModuleRegisted(String moduleName) {
     final moduleNamePtr = moduleName.toNativeUtf8();
-    final result = _isModuleRegisteredFn(moduleNamePtr);
+    final Pointer<Pointer<Utf8>> errorPtr = calloc();
+    final result =
+        _isModuleRegisteredFn(_runtimeHandle, moduleNamePtr, errorPtr);
     malloc.free(moduleNamePtr);
-    return result == 0;
+
+    if (result == GlobeRuntimeErrorCode.moduleNotFound.value) {
+      calloc.free(errorPtr);
+      return false;
+    }
+
+    _checkResult(result, errorPtr, "Failed to look up `$moduleName` module");
+    return true;
   }
 }
 
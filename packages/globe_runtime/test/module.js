@@ -1,3 +1,6 @@
+// Chunks `stream_counted` started to send
+let streamedChunks = 0;
+
 const sdk = {
   functions: {
     json_encode: function (_, DartCallbackId) {
@@ -54,9 +57,187 @@ const sdk = {
         Dart.send_error(DartCallbackId, `Fetch failed: ${err.message}`);
       }
     },
+    delayed_hello: function (_, name, delayMs, DartCallbackId) {
+      setTimeout(() => {
+        const greeting = `Hello, ${name}`;
+        Dart.send_value(DartCallbackId, new TextEncoder().encode(greeting));
+      }, delayMs);
+    },
+    wait_for_cancel: function (_, DartCallbackId, signal) {
+      return new Promise((resolve) => {
+        signal.addEventListener("abort", resolve);
+      });
+    },
+    spin_forever: function (_, DartCallbackId) {
+      while (true) {}
+    },
+    spin_for: function (_, durationMs) {
+      const end = Date.now() + durationMs;
+      while (Date.now() < end) {}
+      return "finished";
+    },
+    spin_in_timer: function (_, DartCallbackId) {
+      setTimeout(() => {
+        while (true) {}
+      }, 1);
+    },
+    send_then_spin: function (_, durationMs, DartCallbackId) {
+      Dart.send(DartCallbackId, "sent");
+      const end = Date.now() + durationMs;
+      while (Date.now() < end) {}
+    },
+    exhaust_heap: function (_, DartCallbackId) {
+      const chunks = [];
+      while (true) {
+        chunks.push(new Array(1024 * 1024).fill(chunks.length));
+      }
+    },
+    open_socket: async function (_, port, DartCallbackId) {
+      try {
+        const [rid] = await Deno.core.ops.op_net_connect_tcp({
+          hostname: "127.0.0.1",
+          port: Number(port),
+        });
+        Deno.core.close(rid);
+        Dart.send_value(DartCallbackId, new TextEncoder().encode("connected"));
+      } catch (err) {
+        Dart.send_error(DartCallbackId, `${err.name}: ${err.message}`);
+      }
+    },
+    read_env: function (_, key, DartCallbackId) {
+      try {
+        const value = Deno.env.get(key) ?? "";
+        Dart.send_value(DartCallbackId, new TextEncoder().encode(value));
+      } catch (err) {
+        Dart.send_error(DartCallbackId, `${err.name}: ${err.message}`);
+      }
+    },
     throw_error: function (_, DartCallbackId) {
       throw new Error("This is a simulated error");
     },
+    next_integer: function (_, value, DartCallbackId) {
+      const next = typeof value === "bigint" ? value + 1n : value + 1;
+      const result = JsonPayload.encode({ type: typeof value, next });
+      Dart.send_value(DartCallbackId, result);
+    },
+    describe_typed_array: function (_, array, DartCallbackId) {
+      const result = JsonPayload.encode({
+        type: array.constructor.name,
+        values: Array.from(array),
+      });
+      Dart.send_value(DartCallbackId, result);
+    },
+    increment_bytes: function (_, buffer, DartCallbackId) {
+      const view = new Uint8Array(buffer);
+      for (let i = 0; i < view.length; i++) view[i]++;
+      Dart.send_value(DartCallbackId, JsonPayload.encode(view.length));
+    },
+    native_value: function (_, DartCallbackId) {
+      Dart.send(DartCallbackId, {
+        nothing: null,
+        flag: true,
+        count: 42,
+        big: 2n ** 60n,
+        ratio: 0.5,
+        name: "globe",
+        tags: ["a", "b"],
+        bytes: new Uint8Array([1, 2, 3]),
+        nested: { values: new Float64Array([1.5]) },
+      });
+    },
+    add: function (_, a, b) {
+      return a + b;
+    },
+    greet_later: async function (_, name) {
+      await new Promise((resolve) => setTimeout(resolve, 1));
+      return { greeting: `Hello, ${name}` };
+    },
+    finish_later: async function () {},
+    throw_later: function (_, message) {
+      setTimeout(() => {
+        throw new Error(message);
+      }, 1);
+      return "scheduled";
+    },
+    stream_then_finish_later: async function (_, DartCallbackId) {
+      await Dart.stream(DartCallbackId, "first");
+      setTimeout(() => Dart.stream_end(DartCallbackId, "last"), 10);
+    },
+    map_with: async function (_, items, transform) {
+      try {
+        return await Promise.all(items.map((item) => transform(item)));
+      } catch (error) {
+        return { error: error.message };
+      }
+    },
+    ask_host: async function (_, name, ...args) {
+      try {
+        return { result: await Dart.invoke(name, ...args.slice(0, -2)) };
+      } catch (error) {
+        return { error: error.message };
+      }
+    },
+    make_counter: function (_, start) {
+      return Dart.handle({
+        count: start,
+        add(amount) {
+          this.count += amount;
+          return this.count;
+        },
+      });
+    },
+    forge_handle: function (_, id) {
+      return { $globeHandle: id };
+    },
+    read_counter: function (_, counter) {
+      return counter?.count ?? null;
+    },
+    read_stream: async function (_, stream) {
+      const decoder = new TextDecoder();
+      let text = "";
+      for await (const chunk of stream) {
+        text += decoder.decode(chunk, { stream: true });
+      }
+      return text + decoder.decode();
+    },
+    stream_paced: async function (_, count, DartCallbackId) {
+      for (let i = 0; i < count; i++) {
+        await Dart.stream_value(DartCallbackId, JsonPayload.encode(i));
+      }
+      Dart.stream_value_end(DartCallbackId);
+    },
+    stream_counted: async function (_, count, DartCallbackId) {
+      streamedChunks = 0;
+      for (let i = 0; i < count; i++) {
+        streamedChunks = i + 1;
+        await Dart.stream(DartCallbackId, i);
+      }
+      Dart.stream_end(DartCallbackId);
+    },
+    streamed_chunks: function () {
+      return streamedChunks;
+    },
+    count_up: async function* (_, to) {
+      for (let i = 1; i <= to; i++) yield i;
+    },
+    fail_midway: function* () {
+      yield "first";
+      throw new Error("Stream broke");
+    },
+    stream_text: function (_, text) {
+      return new ReadableStream({
+        start(controller) {
+          for (const word of text.split(" ")) controller.enqueue(word);
+          controller.close();
+        },
+      });
+    },
+    throw_type_error: async function (_, DartCallbackId) {
+      const cause = new RangeError("Value out of range");
+      const error = new TypeError("Invalid input", { cause });
+      error.code = "E_INVALID";
+      throw error;
+    },
   },
 };
 
@@ -1,7 +1,10 @@
 import 'dart:async';
 import 'dart:convert';
+import 'dart:ffi';
 import 'dart:io';
+import 'dart:typed_data';
 
+import 'package:ffi/ffi.dart';
 import 'package:globe_runtime/globe_runtime.dart';
 import 'package:test/test.dart';
 import 'package:path/path.dart' as path;
@@ -63,6 +66,18 @@ void main() {
     expect(module.isReady, isTrue);
   });
 
+  test('should keep modules independent per runtime', () async {
+    final otherRuntime = GlobeRuntime.create();
+    addTearDown(otherRuntime.dispose);
+
+    expect(module.isRegisteredIn(otherRuntime), isFalse);
+
+    await module.register(args: ['Foobar'.toFFIType], runtime: otherRuntime);
+
+    expect(module.isRegisteredIn(otherRuntime), isTrue);
+    expect(otherRuntime, isNot(equals(runtime)));
+  });
+
   test('should call function from module', () async {
     final result = await callJsFunction(
       'say_hello',
@@ -167,15 +182,1554 @@ void main() {
     );
   });
 
-  test('should catch errors from Javascript', () async {
-    try {
-      runtime.callFunction(
-        module.name,
-        function: 'throw_error',
-        onData: (data) => true,
-      );
-    } catch (e) {
-      expect(e, isA<StateError>());
+  test('should not wait for unrelated pending work', () async {
+    final order = <String>[];
+
+    final delayed = callJsFunction(
+      'delayed_hello',
+      args: ['Later'.toFFIType, 500.toFFIType],
+    ).then((data) => order.add(utf8.decode(data)));
+
+    final immediate = callJsFunction(
+      'say_hello',
+      args: ['Now'.toFFIType],
+    ).then((data) => order.add(utf8.decode(data)));
+
+    await Future.wait([delayed, immediate]);
+
+    expect(order, ['Hello, Now', 'Hello, Later']);
+  });
+
+  test('should cancel a running call', () async {
+    final completer = Completer<DartMessage>();
+
+    final callId = module.callFunction(
+      'wait_for_cancel',
+      onData: (data) {
+        completer.complete(data);
+        return true;
+      },
+    );
+    runtime.cancelFunction(callId);
+
+    final message = await completer.future;
+
+    expect(message.cancelled, isTrue);
+    expect(message.done, isTrue);
+  });
+
+  test('should time out a runaway call and stay usable', () async {
+    final completer = Completer<DartMessage>();
+
+    module.callFunction(
+      'spin_forever',
+      timeout: const Duration(milliseconds: 200),
+      onData: (data) {
+        completer.complete(data);
+        return true;
+      },
+    );
+
+    final message = await completer.future;
+    expect(message.done, isTrue);
+    expect(message.error, contains('timed out'));
+
+    final result = await callJsFunction(
+      'say_hello',
+      args: ['After timeout'.toFFIType],
+    ).then(utf8.decode);
+
+    expect(result, 'Hello, After timeout');
+  });
+
+  test('should not terminate another call when a call times out', () async {
+    final timedOut = Completer<DartMessage>();
+    final spinning = Completer<DartMessage>();
+
+    module.callFunction(
+      'wait_for_cancel',
+      timeout: const Duration(milliseconds: 50),
+      onData: (data) {
+        timedOut.complete(data);
+        return true;
+      },
+    );
+    module.callFunction(
+      'spin_for',
+      args: [500.toFFIType],
+      onData: (data) {
+        spinning.complete(data);
+        return true;
+      },
+    );
+
+    expect((await spinning.future).value, 'finished');
+    expect((await timedOut.future).error, contains('timed out'));
+  });
+
+  test('should time out functions that answer from a timer', () async {
+    final completer = Completer<DartMessage>();
+
+    module.callFunction(
+      'delayed_hello',
+      args: ['Timer'.toFFIType, 300.toFFIType],
+      timeout: const Duration(milliseconds: 50),
+      onData: (data) {
+        completer.complete(data);
+        return true;
+      },
+    );
+
+    final message = await completer.future;
+    expect(message.done, isTrue);
+    expect(message.error, contains('timed out'));
+  });
+
+  test('should not report terminated timers as runtime errors', () async {
+    final errors = <GlobeRuntimeException>[];
+    final subscription = runtime.errors.listen(errors.add);
+    addTearDown(subscription.cancel);
+
+    final completer = Completer<DartMessage>();
+    module.callFunction(
+      'spin_in_timer',
+      timeout: const Duration(milliseconds: 50),
+      onData: (data) {
+        completer.complete(data);
+        return true;
+      },
+    );
+
+    expect((await completer.future).error, contains('timed out'));
+    await Future<void>.delayed(const Duration(milliseconds: 300));
+    expect(errors, isEmpty);
+  });
+
+  test('should not report a timeout for calls that already answered',
+      () async {
+    final messages = <DartMessage>[];
+
+    module.callFunction(
+      'send_then_spin',
+      args: [300.toFFIType],
+      timeout: const Duration(milliseconds: 50),
+      onData: (data) {
+        messages.add(data);
+        return false;
+      },
+    );
+
+    await Future<void>.delayed(const Duration(milliseconds: 600));
+    expect(messages, hasLength(1));
+    expect(messages.single.value, 'sent');
+  });
+
+  test('should report running out of memory and recycle the isolate',
+      () async {
+    final limitedRuntime = GlobeRuntime.create(
+      config: const RuntimeConfig(
+        maxHeapSizeMb: 64,
+        recycleOnOutOfMemory: true,
+      ),
+    );
+    addTearDown(limitedRuntime.dispose);
+
+    await module.register(
+      args: ['Foobar'.toFFIType],
+      runtime: limitedRuntime,
+    );
+
+    final completer = Completer<DartMessage>();
+    module.callFunction(
+      'exhaust_heap',
+      runtime: limitedRuntime,
+      onData: (data) {
+        completer.complete(data);
+        return true;
+      },
+    );
+
+    final message = await completer.future;
+    expect(message.error, contains('out of memory'));
+
+    final greeting = Completer<String>();
+    module.callFunction(
+      'say_hello',
+      args: ['Recycled'.toFFIType],
+      runtime: limitedRuntime,
+      onData: (data) {
+        greeting.complete(utf8.decode(data.data));
+        return true;
+      },
+    );
+
+    expect(await greeting.future, 'Hello, Recycled');
+  });
+
+  test('should not register modules again with arguments of the old isolate',
+      () async {
+    final limitedRuntime = GlobeRuntime.create(
+      config: const RuntimeConfig(
+        maxHeapSizeMb: 64,
+        recycleOnOutOfMemory: true,
+      ),
+    );
+    addTearDown(limitedRuntime.dispose);
+    final error = limitedRuntime.errors.first;
+
+    final callbackModule = InlinedModule(
+      name: 'CallbackInitModule',
+      sourceCode: '''
+export default {
+  init(callback) {
+    return { callback };
+  },
+  functions: {
+    exhaust_heap() {
+      const chunks = [];
+      while (true) {
+        chunks.push(new Array(1024 * 1024).fill(chunks.length));
+      }
+    },
+  },
+};
+''',
+    );
+    await callbackModule.register(
+      args: [FFICallback((_) => null)],
+      runtime: limitedRuntime,
+    );
+
+    final completer = Completer<DartMessage>();
+    callbackModule.callFunction(
+      'exhaust_heap',
+      runtime: limitedRuntime,
+      onData: (data) {
+        completer.complete(data);
+        return true;
+      },
+    );
+
+    expect((await completer.future).error, contains('out of memory'));
+    expect(
+      (await error).message,
+      allOf(contains('CallbackInitModule'), contains('replaced isolate')),
+    );
+    expect(limitedRuntime.isModuleRegistered('CallbackInitModule'), isFalse);
+  });
+
+  test('should apply the default timeout from the runtime config', () async {
+    final configuredRuntime = GlobeRuntime.create(
+      config: const RuntimeConfig(
+        defaultTimeout: Duration(milliseconds: 200),
+      ),
+    );
+    addTearDown(configuredRuntime.dispose);
+
+    await module.register(
+      args: ['Foobar'.toFFIType],
+      runtime: configuredRuntime,
+    );
+
+    final completer = Completer<DartMessage>();
+    module.callFunction(
+      'spin_forever',
+      runtime: configuredRuntime,
+      onData: (data) {
+        completer.complete(data);
+        return true;
+      },
+    );
+
+    final message = await completer.future;
+    expect(message.error, contains('timed out'));
+  });
+
+  test('should reject V8 flags other than those of the first runtime', () {
+    // The shared runtime was created without flags
+    expect(
+      () => GlobeRuntime.create(
+        config: const RuntimeConfig(v8Flags: ['--stack-size=2048']),
+      ),
+      throwsA(
+        isA<GlobeRuntimeException>()
+            .having((e) => e.code, 'code', GlobeRuntimeErrorCode.initFailed)
+            .having((e) => e.message, 'message', contains('V8 flags')),
+      ),
+    );
+
+    final sameFlagsRuntime = GlobeRuntime.create(
+      config: const RuntimeConfig(v8Flags: []),
+    );
+    sameFlagsRuntime.dispose();
+  });
+
+  test('should reject a config with an unsupported shape', () {
+    expect(
+      () => GlobeRuntime.create(
+        config: const RuntimeConfig(maxHeapSizeMb: -1),
+      ),
+      throwsStateError,
+    );
+  });
+
+  test('should reject a runtime without blocking threads', () {
+    expect(
+      () => GlobeRuntime.create(
+        config: const RuntimeConfig(maxBlockingThreads: 0),
+      ),
+      throwsA(
+        isA<GlobeRuntimeException>().having(
+          (e) => e.message,
+          'message',
+          contains('max_blocking_threads'),
+        ),
+      ),
+    );
+  });
+
+  group('Permissions', () {
+    late GlobeRuntime restrictedRuntime;
+
+    setUpAll(() async {
+      restrictedRuntime = GlobeRuntime.create(
+        config: const RuntimeConfig(
+          permissions: RuntimePermissions(env: ['PATH']),
+        ),
+      );
+      await module.register(
+        args: ['Foobar'.toFFIType],
+        runtime: restrictedRuntime,
+      );
+    });
+
+    tearDownAll(() => restrictedRuntime.dispose());
+
+    Future<DartMessage> call(String function, List<FFIConvertible> args) {
+      final completer = Completer<DartMessage>();
+      module.callFunction(
+        function,
+        args: args,
+        runtime: restrictedRuntime,
+        onData: (data) {
+          completer.complete(data);
+          return true;
+        },
+      );
+      return completer.future;
+    }
+
+    test('should deny hosts outside the net allowlist', () async {
+      final message = await call('fetch_url', [
+        'https://jsonplaceholder.typicode.com/posts/1'.toFFIType,
+      ]);
+
+      expect(message.error, contains('net access'));
+    });
+
+    test('should only expose allowed environment variables', () async {
+      final allowed = await call('read_env', ['PATH'.toFFIType]);
+      expect(utf8.decode(allowed.data), Platform.environment['PATH']);
+
+      final denied = await call('read_env', ['HOME'.toFFIType]);
+      expect(denied.error, startsWith('PermissionDenied'));
+    });
+
+    test('should scope permissions to a module', () async {
+      final scopedRuntime = GlobeRuntime.create();
+      addTearDown(scopedRuntime.dispose);
+
+      final scopedModule = FileModule(
+        name: 'ScopedTestModule',
+        filePath: module.filePath,
+      );
+      await module.register(
+        args: ['Foobar'.toFFIType],
+        runtime: scopedRuntime,
+      );
+      await scopedModule.register(
+        args: ['Foobar'.toFFIType],
+        runtime: scopedRuntime,
+        permissions: const RuntimePermissions(env: ['PATH']),
+      );
+
+      Future<DartMessage> readHome(Module target) {
+        final completer = Completer<DartMessage>();
+        target.callFunction(
+          'read_env',
+          args: ['HOME'.toFFIType],
+          runtime: scopedRuntime,
+          onData: (data) {
+            completer.complete(data);
+            return true;
+          },
+        );
+        return completer.future;
+      }
+
+      final scopedResult = await readHome(scopedModule);
+      expect(scopedResult.error, startsWith('PermissionDenied'));
+
+      final unscopedResult = await readHome(module);
+      expect(unscopedResult.hasError(), isFalse);
+    });
+
+    test('should not let a module claim the scope of another', () async {
+      final scopedRuntime = GlobeRuntime.create();
+      addTearDown(scopedRuntime.dispose);
+
+      await module.register(
+        args: ['Foobar'.toFFIType],
+        runtime: scopedRuntime,
+      );
+      final impostorModule = InlinedModule(
+        name: 'ImpostorModule',
+        sourceCode: '''
+export default {
+  functions: {
+    read_home: () => {
+      Deno.core.setAsyncContext({ globeModule: "TestModule" });
+      return Deno.env.get("HOME") ?? null;
+    },
+    read_home_without_module: () => {
+      Deno.core.setAsyncContext(undefined);
+      return Deno.env.get("HOME") ?? null;
+    },
+  },
+};
+''',
+      );
+      await impostorModule.register(
+        runtime: scopedRuntime,
+        permissions: const RuntimePermissions(env: ['PATH']),
+      );
+
+      for (final function in ['read_home', 'read_home_without_module']) {
+        final completer = Completer<DartMessage>();
+        impostorModule.callFunction(
+          function,
+          runtime: scopedRuntime,
+          onData: (data) {
+            completer.complete(data);
+            return true;
+          },
+        );
+        expect((await completer.future).error, contains('PermissionDenied'));
+      }
+    });
+
+    test('should scope socket permissions to a module', () async {
+      final server = await ServerSocket.bind(InternetAddress.loopbackIPv4, 0);
+      addTearDown(server.close);
+      server.listen((socket) => socket.destroy());
+
+      final netRuntime = GlobeRuntime.create();
+      addTearDown(netRuntime.dispose);
+
+      final scopedModule = FileModule(
+        name: 'ScopedNetModule',
+        filePath: module.filePath,
+      );
+      await module.register(
+        args: ['Foobar'.toFFIType],
+        runtime: netRuntime,
+      );
+      await scopedModule.register(
+        args: ['Foobar'.toFFIType],
+        runtime: netRuntime,
+        permissions: const RuntimePermissions(net: ['example.com']),
+      );
+
+      Future<DartMessage> openSocket(Module target) {
+        final completer = Completer<DartMessage>();
+        target.callFunction(
+          'open_socket',
+          args: [server.port.toFFIType],
+          runtime: netRuntime,
+          onData: (data) {
+            completer.complete(data);
+            return true;
+          },
+        );
+        return completer.future;
+      }
+
+      final denied = await openSocket(scopedModule);
+      expect(denied.error, startsWith('PermissionDenied'));
+
+      final opened = await openSocket(module);
+      expect(utf8.decode(opened.data), 'connected');
+    });
+
+    test('should hold top-level module code to the module scope', () async {
+      final scopedRuntime = GlobeRuntime.create();
+      addTearDown(scopedRuntime.dispose);
+
+      final envModule = InlinedModule(
+        name: 'TopLevelEnvModule',
+        sourceCode: '''
+const home = await Promise.resolve().then(() => Deno.env.get("HOME"));
+export default { functions: { home: () => home } };
+''',
+      );
+
+      await expectLater(
+        () => envModule.register(
+          runtime: scopedRuntime,
+          permissions: const RuntimePermissions(env: ['PATH']),
+        ),
+        throwsA(
+          isA<GlobeRuntimeException>().having(
+            (e) => e.message,
+            'message',
+            contains('PermissionDenied'),
+          ),
+        ),
+      );
+      expect(scopedRuntime.isModuleRegistered('TopLevelEnvModule'), isFalse);
+
+      await envModule.register(runtime: scopedRuntime);
+      expect(scopedRuntime.isModuleRegistered('TopLevelEnvModule'), isTrue);
+    });
+  });
+
+  group('64-bit integers', () {
+    test('should pass safe integers as numbers', () async {
+      final result = await callJsFunction(
+        'next_integer',
+        args: [42.toFFIType],
+      );
+
+      expect(result.unpack(), {'type': 'number', 'next': 43});
+    });
+
+    test('should pass large integers as BigInt without truncation', () async {
+      const value = 1 << 60;
+      final result = await callJsFunction(
+        'next_integer',
+        args: [value.toFFIType],
+      );
+
+      expect(result.unpack(), {'type': 'bigint', 'next': value + 1});
+    });
+
+    test('should pass BigInt values in the 64-bit range', () async {
+      final result = await callJsFunction(
+        'next_integer',
+        args: [BigInt.from(-(1 << 62)).toFFIType],
+      );
+
+      expect(result.unpack(), {'type': 'bigint', 'next': -(1 << 62) + 1});
+    });
+  });
+
+  group('Typed lists', () {
+    Future<dynamic> describe(TypedData list) async {
+      final result = await callJsFunction(
+        'describe_typed_array',
+        args: [(list as Object).toFFIType],
+      );
+      return result.unpack();
     }
+
+    test('should pass Float64List as Float64Array', () async {
+      expect(
+        await describe(Float64List.fromList([1.5, -2.25])),
+        {'type': 'Float64Array', 'values': [1.5, -2.25]},
+      );
+    });
+
+    test('should pass Int32List as Int32Array', () async {
+      expect(
+        await describe(Int32List.fromList([-1, 2147483647])),
+        {'type': 'Int32Array', 'values': [-1, 2147483647]},
+      );
+    });
+
+    test('should pass a view of a larger buffer', () async {
+      final buffer = Uint16List.fromList([1, 2, 3, 4]);
+      expect(
+        await describe(Uint16List.sublistView(buffer, 1, 3)),
+        {'type': 'Uint16Array', 'values': [2, 3]},
+      );
+    });
+
+    test('should pass Int64List as BigInt64Array', () async {
+      expect(
+        await describe(Int64List.fromList([1 << 60])),
+        {'type': 'BigInt64Array', 'values': [1 << 60]},
+      );
+    });
+  });
+
+  test('should share external bytes with Javascript without copying', () async {
+    final pointer = malloc<Uint8>(3);
+    addTearDown(() => malloc.free(pointer));
+
+    // Freed by the tear down, as the release may arrive after the test
+    final external = FFIExternalBytes.fromPointer(
+      pointer,
+      3,
+      onRelease: (_) {},
+    );
+    external.bytes.setAll(0, [1, 2, 3]);
+
+    final result = await callJsFunction('increment_bytes', args: [external]);
+
+    expect(result.unpack(), 3);
+    expect(external.bytes, [2, 3, 4]);
+  });
+
+  test('should receive native values from Javascript', () async {
+    final completer = Completer<DartMessage>();
+    module.callFunction(
+      'native_value',
+      onData: (data) {
+        completer.complete(data);
+        return true;
+      },
+    );
+
+    final message = await completer.future;
+    expect(message.done, isTrue);
+    expect(message.hasValue, isTrue);
+
+    final value = message.value as Map<String, Object?>;
+    expect(value, {
+      'nothing': null,
+      'flag': true,
+      'count': 42,
+      'big': 1 << 60,
+      'ratio': 0.5,
+      'name': 'globe',
+      'tags': ['a', 'b'],
+      'bytes': [1, 2, 3],
+      'nested': {
+        'values': [1.5],
+      },
+    });
+    expect(value['bytes'], isA<Uint8List>());
+    expect((value['nested'] as Map)['values'], isA<Float64List>());
   });
+
+  group('return values', () {
+    Future<DartMessage> call(String function, List<FFIConvertible> args) {
+      final completer = Completer<DartMessage>();
+      module.callFunction(
+        function,
+        args: args,
+        onData: (data) {
+          completer.complete(data);
+          return true;
+        },
+      );
+      return completer.future;
+    }
+
+    test('should forward a returned value', () async {
+      final message = await call('add', [1.toFFIType, 2.toFFIType]);
+      expect(message.done, isTrue);
+      expect(message.value, 3);
+    });
+
+    test('should forward the value a promise resolves with', () async {
+      final message = await call('greet_later', ['World'.toFFIType]);
+      expect(message.value, {'greeting': 'Hello, World'});
+    });
+
+    test('should end the call when a promise resolves to nothing', () async {
+      final message = await call('finish_later', []);
+      expect(message.done, isTrue);
+      expect(message.hasError(), isFalse);
+      expect(message.value, isNull);
+    });
+
+    test('should keep a call open that streams and returns nothing', () async {
+      final messages = <DartMessage>[];
+      final completer = Completer<void>();
+      module.callFunction(
+        'stream_then_finish_later',
+        onData: (data) {
+          messages.add(data);
+          if (data.done) completer.complete();
+          return data.done;
+        },
+      );
+
+      await completer.future;
+      expect(messages.map((m) => m.value), ['first', 'last']);
+      expect(messages.map((m) => m.done), [false, true]);
+    });
+
+    test('should not forward after the function ended the call', () async {
+      final messages = <DartMessage>[];
+      module.callFunction(
+        'fetch_url',
+        args: ['not a url'.toFFIType],
+        onData: (data) {
+          messages.add(data);
+          return false;
+        },
+      );
+
+      await Future.delayed(const Duration(milliseconds: 200));
+      expect(messages, hasLength(1));
+      expect(messages.single.hasValue, isFalse);
+    });
+  });
+
+  group('streamed results', () {
+    Future<List<DartMessage>> collect(
+      String function,
+      List<FFIConvertible> args,
+    ) {
+      final messages = <DartMessage>[];
+      final completer = Completer<List<DartMessage>>();
+      module.callFunction(
+        function,
+        args: args,
+        onData: (data) {
+          messages.add(data);
+          if (data.done) completer.complete(messages);
+          return data.done;
+        },
+      );
+      return completer.future;
+    }
+
+    test('should stream an async generator', () async {
+      final messages = await collect('count_up', [3.toFFIType]);
+      expect(messages.map((m) => m.value), [1, 2, 3, null]);
+      expect(messages.map((m) => m.done), [false, false, false, true]);
+    });
+
+    test('should stream a ReadableStream', () async {
+      final messages = await collect('stream_text', ['a b'.toFFIType]);
+      expect(messages.map((m) => m.value), ['a', 'b', null]);
+    });
+
+    test('should end the stream with the error it throws', () async {
+      final messages = await collect('fail_midway', []);
+      expect(messages.first.value, 'first');
+      expect(messages.last.jsException?.message, 'Stream broke');
+    });
+  });
+
+  test('should pace streams by the chunks Dart acknowledged', () async {
+    final pacedRuntime = GlobeRuntime.create(
+      config: const RuntimeConfig(streamWindow: 1),
+    );
+    addTearDown(pacedRuntime.dispose);
+    await module.register(args: ['Foobar'.toFFIType], runtime: pacedRuntime);
+
+    final chunks = <int>[];
+    final completer = Completer<void>();
+    module.callFunction(
+      'stream_paced',
+      args: [20.toFFIType],
+      runtime: pacedRuntime,
+      onData: (data) {
+        if (data.hasData()) chunks.add(data.data.unpack());
+        if (data.done) completer.complete();
+        return data.done;
+      },
+    );
+
+    await completer.future.timeout(const Duration(seconds: 5));
+    expect(chunks, List.generate(20, (i) => i));
+  });
+
+  test('should stop streaming while the subscription is paused', () async {
+    final pacedRuntime = GlobeRuntime.create(
+      config: const RuntimeConfig(streamWindow: 4),
+    );
+    addTearDown(pacedRuntime.dispose);
+    await module.register(args: ['Foobar'.toFFIType], runtime: pacedRuntime);
+
+    Future<Object?> streamedChunks() {
+      final completer = Completer<Object?>();
+      module.callFunction(
+        'streamed_chunks',
+        runtime: pacedRuntime,
+        onData: (data) {
+          completer.complete(data.value);
+          return true;
+        },
+      );
+      return completer.future;
+    }
+
+    final chunks = <Object?>[];
+    final done = Completer<void>();
+    final subscription = module
+        .streamFunction(
+          'stream_counted',
+          args: [20.toFFIType],
+          runtime: pacedRuntime,
+        )
+        .listen(
+          (message) {
+            if (!message.done) chunks.add(message.value);
+          },
+          onDone: done.complete,
+        );
+    subscription.pause();
+
+    // Nothing was taken, so JavaScript waits once the window is full
+    await Future.delayed(const Duration(milliseconds: 200));
+    expect(await streamedChunks(), 4);
+    expect(chunks, isEmpty);
+
+    subscription.resume();
+    await done.future.timeout(const Duration(seconds: 5));
+    expect(chunks, List.generate(20, (i) => i));
+  });
+
+  group('input streams', () {
+    Future<DartMessage> readStream(Stream<List<int>> source) {
+      final completer = Completer<DartMessage>();
+      module.callFunction(
+        'read_stream',
+        args: [FFIStream(source)],
+        onData: (data) {
+          completer.complete(data);
+          return true;
+        },
+      );
+      return completer.future;
+    }
+
+    test('should feed a Dart stream into a ReadableStream', () async {
+      final controller = StreamController<List<int>>();
+      final message = readStream(controller.stream);
+
+      controller.add(utf8.encode('Hello, '));
+      await Future.delayed(const Duration(milliseconds: 10));
+      controller.add(utf8.encode('Stream'));
+      await controller.close();
+
+      expect((await message).value, 'Hello, Stream');
+    });
+
+    test('should error the ReadableStream with the Dart error', () async {
+      final source = Stream<List<int>>.error(StateError('Upload failed'));
+      final message = await readStream(source);
+
+      expect(message.jsException?.message, contains('Upload failed'));
+    });
+  });
+
+  group('callback arguments', () {
+    Future<Object?> mapWith(List<int> items, FFICallback callback) {
+      final completer = Completer<Object?>();
+      module.callFunction(
+        'map_with',
+        args: [FFIJsonPayload(items), callback],
+        onData: (data) {
+          completer.complete(data.value);
+          return true;
+        },
+      );
+      return completer.future;
+    }
+
+    test('should call Dart functions passed as arguments', () async {
+      final callback = FFICallback((args) async => (args.first as int) * 10);
+
+      expect(await mapWith([1, 2, 3], callback), [10, 20, 30]);
+      expect(callback.isRetained, isTrue);
+    });
+
+    test('should reject when the Dart function throws', () async {
+      final callback = FFICallback((_) => throw ArgumentError('bad item'));
+
+      expect(
+        await mapWith([1], callback),
+        {'error': contains('bad item')},
+      );
+    });
+  });
+
+  group('host functions', () {
+    Future<Object?> askHost(String name, List<FFIConvertible> args) {
+      final completer = Completer<Object?>();
+      module.callFunction(
+        'ask_host',
+        args: [name.toFFIType, ...args],
+        onData: (data) {
+          completer.complete(data.value);
+          return true;
+        },
+      );
+      return completer.future;
+    }
+
+    test('should answer Dart.invoke with the host function result', () async {
+      runtime.registerHostFunction('lookup', (args) async {
+        return {'key': args.first, 'found': true};
+      });
+      addTearDown(() => runtime.unregisterHostFunction('lookup'));
+
+      expect(
+        await askHost('lookup', ['theme'.toFFIType]),
+        {
+          'result': {'key': 'theme', 'found': true},
+        },
+      );
+    });
+
+    test('should reject Dart.invoke when the host function throws', () async {
+      runtime.registerHostFunction('broken', (_) => throw StateError('nope'));
+      addTearDown(() => runtime.unregisterHostFunction('broken'));
+
+      expect(
+        await askHost('broken', []),
+        {'error': contains('nope')},
+      );
+    });
+
+    test('should reject Dart.invoke for unknown host functions', () async {
+      expect(
+        await askHost('missing', []),
+        {'error': contains('not registered')},
+      );
+    });
+  });
+
+  group('module instances', () {
+    final keyedModule = InlinedModule(
+      name: 'KeyedModule',
+      sourceCode: '''
+export default {
+  init(key) {
+    return { key, calls: 0 };
+  },
+  dispose(state) {
+    Dart.invoke("instance_disposed", state.key);
+  },
+  functions: {
+    whoami(state) {
+      state.calls++;
+      return `\${state.key}:\${state.calls}`;
+    },
+  },
+};
+''',
+    );
+
+    setUpAll(() => keyedModule.register(args: ['module'.toFFIType]));
+
+    Future<Object?> whoami(ModuleInstance instance) {
+      final completer = Completer<Object?>();
+      instance.callFunction(
+        'whoami',
+        onData: (data) {
+          completer.complete(data.hasError() ? data.error : data.value);
+          return true;
+        },
+      );
+      return completer.future;
+    }
+
+    test('should keep the state of each instance apart', () async {
+      final first =
+          await keyedModule.createInstance(args: ['first'.toFFIType]);
+      final second =
+          await keyedModule.createInstance(args: ['second'.toFFIType]);
+      addTearDown(() {
+        first.dispose();
+        second.dispose();
+      });
+
+      expect(await whoami(first), 'first:1');
+      expect(await whoami(first), 'first:2');
+      expect(await whoami(second), 'second:1');
+    });
+
+    test('should run the dispose hook and forget the instance', () async {
+      final disposed = Completer<Object?>();
+      runtime.registerHostFunction('instance_disposed', (args) {
+        disposed.complete(args.first);
+        return null;
+      });
+      addTearDown(() => runtime.unregisterHostFunction('instance_disposed'));
+
+      final instance =
+          await keyedModule.createInstance(args: ['temp'.toFFIType]);
+      instance.dispose();
+
+      expect(await disposed.future, 'temp');
+      expect(await whoami(instance), contains('not found'));
+      expect(
+        instance.dispose,
+        throwsA(
+          isA<GlobeRuntimeException>().having(
+            (e) => e.code,
+            'code',
+            GlobeRuntimeErrorCode.instanceNotFound,
+          ),
+        ),
+      );
+    });
+
+    test('should let instance init functions await host functions', () async {
+      runtime.registerHostFunction('instance_key', (args) async {
+        await Future<void>.delayed(const Duration(milliseconds: 10));
+        return 'host-${args.first}';
+      });
+      addTearDown(() => runtime.unregisterHostFunction('instance_key'));
+
+      final hostKeyedModule = InlinedModule(
+        name: 'HostKeyedModule',
+        sourceCode: '''
+export default {
+  async init(key) {
+    return { key: await Dart.invoke("instance_key", key), calls: 0 };
+  },
+  functions: {
+    whoami(state) {
+      state.calls++;
+      return `\${state.key}:\${state.calls}`;
+    },
+  },
+};
+''',
+      );
+      await hostKeyedModule.register(args: ['module'.toFFIType]);
+
+      final instance =
+          await hostKeyedModule.createInstance(args: ['first'.toFFIType]);
+      addTearDown(instance.dispose);
+
+      expect(await whoami(instance), 'host-first:1');
+    });
+
+    test('should fail instances of unregistered modules', () async {
+      await expectLater(
+        runtime.createModuleInstance('MissingModule'),
+        throwsA(
+          isA<GlobeRuntimeException>().having(
+            (e) => e.code,
+            'code',
+            GlobeRuntimeErrorCode.moduleRegistrationFailed,
+          ),
+        ),
+      );
+    });
+  });
+
+  group('JS handles', () {
+    Future<Object?> valueOf(void Function(OnFunctionData onData) call) {
+      final completer = Completer<Object?>();
+      call((data) {
+        completer.complete(data.hasError() ? data.error : data.value);
+        return true;
+      });
+      return completer.future;
+    }
+
+    Future<JsHandle> makeCounter(int start) async {
+      final handle = await valueOf(
+        (onData) => module.callFunction(
+          'make_counter',
+          args: [start.toFFIType],
+          onData: onData,
+        ),
+      );
+      return handle as JsHandle;
+    }
+
+    test('should call methods of objects returned as handles', () async {
+      final counter = await makeCounter(5);
+      addTearDown(() {
+        if (!counter.isReleased) runtime.releaseHandle(counter);
+      });
+
+      expect(
+        await valueOf(
+          (onData) => runtime.callHandleMethod(
+            counter,
+            'add',
+            args: [3.toFFIType],
+            onData: onData,
+          ),
+        ),
+        8,
+      );
+      expect(
+        await valueOf(
+          (onData) => module.callFunction(
+            'read_counter',
+            args: [counter],
+            onData: onData,
+          ),
+        ),
+        8,
+      );
+    });
+
+    test('should release handles', () async {
+      final liveBefore = runtime.liveHandleCount;
+      final counter = await makeCounter(0);
+      expect(runtime.liveHandleCount, liveBefore + 1);
+
+      runtime.releaseHandle(counter);
+      expect(counter.isReleased, isTrue);
+      expect(runtime.liveHandleCount, liveBefore);
+
+      expect(
+        () => runtime.releaseHandle(counter),
+        throwsA(
+          isA<GlobeRuntimeException>().having(
+            (e) => e.code,
+            'code',
+            GlobeRuntimeErrorCode.handleNotFound,
+          ),
+        ),
+      );
+      expect(
+        await valueOf(
+          (onData) => runtime.callHandleMethod(
+            counter,
+            'add',
+            args: [1.toFFIType],
+            onData: onData,
+          ),
+        ),
+        contains('was released'),
+      );
+    });
+
+    test('should only accept handles of their own runtime', () async {
+      final otherRuntime = GlobeRuntime.create();
+      addTearDown(otherRuntime.dispose);
+      final counter = await makeCounter(0);
+      addTearDown(() => runtime.releaseHandle(counter));
+
+      final throwsHandleNotFound = throwsA(
+        isA<GlobeRuntimeException>().having(
+          (e) => e.code,
+          'code',
+          GlobeRuntimeErrorCode.handleNotFound,
+        ),
+      );
+      expect(() => otherRuntime.releaseHandle(counter), throwsHandleNotFound);
+      expect(
+        () => otherRuntime.callHandleMethod(
+          counter,
+          'add',
+          onData: (_) => true,
+        ),
+        throwsHandleNotFound,
+      );
+      expect(counter.isReleased, isFalse);
+    });
+
+    test('should not turn objects that look like handles into handles',
+        () async {
+      final counter = await makeCounter(0);
+      addTearDown(() => runtime.releaseHandle(counter));
+
+      expect(
+        await valueOf(
+          (onData) => module.callFunction(
+            'forge_handle',
+            args: [counter.id.toFFIType],
+            onData: onData,
+          ),
+        ),
+        {r'$globeHandle': counter.id},
+      );
+    });
+  });
+
+  test('should catch errors from Javascript', () async {
+    await expectLater(
+      callJsFunction('throw_error'),
+      throwsA(contains('This is a simulated error')),
+    );
+  });
+
+  test('should fail a call with an invalid JSON argument', () async {
+    // An array header promising two items, followed by only one
+    final invalid = _InvalidJsonPayload([0x92, 0x01]);
+
+    await expectLater(
+      callJsFunction('say_hello', args: [invalid]),
+      throwsA(contains('Invalid JSON payload in argument 0')),
+    );
+    expect(
+      await callJsFunction('say_hello', args: ['World'.toFFIType]),
+      isNotEmpty,
+    );
+  });
+
+  test('should report errors outside of calls and keep running', () async {
+    final error = runtime.errors.first;
+    final completer = Completer<DartMessage>();
+    module.callFunction(
+      'throw_later',
+      args: ['Timer broke'.toFFIType],
+      onData: (data) {
+        completer.complete(data);
+        return true;
+      },
+    );
+
+    expect((await completer.future).value, 'scheduled');
+    expect(
+      await error.timeout(const Duration(seconds: 5)),
+      isA<GlobeRuntimeException>()
+          .having((e) => e.code, 'code', GlobeRuntimeErrorCode.runtimeError)
+          .having((e) => e.message, 'message', contains('Timer broke')),
+    );
+    expect(
+      await callJsFunction('say_hello', args: ['World'.toFFIType]),
+      isNotEmpty,
+    );
+  });
+
+  test('should report structured errors from Javascript', () async {
+    final completer = Completer<DartMessage>();
+    module.callFunction(
+      'throw_type_error',
+      onData: (data) {
+        completer.complete(data);
+        return true;
+      },
+    );
+
+    final error = (await completer.future).jsException!;
+    expect(error.name, 'TypeError');
+    expect(error.message, 'Invalid input');
+    expect(error.properties, {'code': 'E_INVALID'});
+    expect(error.frames.first.fileName, endsWith('module.js'));
+    expect(error.cause?.name, 'RangeError');
+    expect(error.cause?.message, 'Value out of range');
+  });
+
+  test('should map stack traces through source maps', () async {
+    final mappedModule = FileModule(
+      name: 'SourceMappedModule',
+      filePath: path.join(
+        Directory.current.path,
+        'test',
+        'source_mapped_module.js',
+      ),
+    );
+    await mappedModule.register();
+
+    final completer = Completer<DartMessage>();
+    mappedModule.callFunction(
+      'fail',
+      onData: (data) {
+        completer.complete(data);
+        return true;
+      },
+    );
+
+    final frame = (await completer.future).jsException!.frames.first;
+    expect(frame.fileName, endsWith('source_mapped_module.ts'));
+    expect(frame.lineNumber, 10);
+  });
+
+  test('should evaluate modules using top-level await', () async {
+    final tlaModule = InlinedModule(
+      name: 'TopLevelAwaitModule',
+      sourceCode: '''
+const config = await new Promise((resolve) =>
+  setTimeout(() => resolve({ region: "eu" }), 10)
+);
+
+export default {
+  functions: {
+    region: () => config.region,
+  },
+};
+''',
+    );
+    await tlaModule.register();
+
+    final completer = Completer<Object?>();
+    tlaModule.callFunction(
+      'region',
+      onData: (data) {
+        completer.complete(data.value);
+        return true;
+      },
+    );
+    expect(await completer.future, 'eu');
+  });
+
+  test('should report errors thrown during top-level await', () async {
+    final failingModule = InlinedModule(
+      name: 'FailingTopLevelAwaitModule',
+      sourceCode: '''
+await null;
+throw new Error("config missing");
+
+export default { functions: {} };
+''',
+    );
+
+    await expectLater(
+      () => failingModule.register(),
+      throwsA(
+        isA<GlobeRuntimeException>().having(
+          (e) => e.message,
+          'message',
+          allOf(contains('config missing'), contains('at ')),
+        ),
+      ),
+    );
+  });
+
+  test('should await async init functions', () async {
+    final asyncModule = InlinedModule(
+      name: 'AsyncInitModule',
+      sourceCode: '''
+export default {
+  async init(name) {
+    await new Promise((resolve) => setTimeout(resolve, 10));
+    return { name };
+  },
+  functions: {
+    name: (state) => state.name,
+  },
+};
+''',
+    );
+    await asyncModule.register(args: ['ready'.toFFIType]);
+
+    final completer = Completer<Object?>();
+    asyncModule.callFunction(
+      'name',
+      onData: (data) {
+        completer.complete(data.value);
+        return true;
+      },
+    );
+    expect(await completer.future, 'ready');
+  });
+
+  test('should let async init functions await host functions', () async {
+    runtime.registerHostFunction('load_config', (args) async {
+      await Future<void>.delayed(const Duration(milliseconds: 10));
+      return {'region': args.first};
+    });
+    addTearDown(() => runtime.unregisterHostFunction('load_config'));
+
+    final configuredModule = InlinedModule(
+      name: 'HostConfiguredModule',
+      sourceCode: '''
+export default {
+  async init(region) {
+    return await Dart.invoke("load_config", region);
+  },
+  functions: {
+    region: (state) => state.region,
+  },
+};
+''',
+    );
+    await configuredModule.register(args: ['eu'.toFFIType]);
+
+    final completer = Completer<Object?>();
+    configuredModule.callFunction(
+      'region',
+      onData: (data) {
+        completer.complete(data.value);
+        return true;
+      },
+    );
+    expect(await completer.future, 'eu');
+  });
+
+  test('should answer queries while init awaits a host function', () async {
+    runtime.registerHostFunction('check_registered', (args) {
+      return runtime.isModuleRegistered(args.first as String);
+    });
+    addTearDown(() => runtime.unregisterHostFunction('check_registered'));
+
+    final checkingModule = InlinedModule(
+      name: 'CheckingInitModule',
+      sourceCode: '''
+export default {
+  async init() {
+    return {
+      self: await Dart.invoke("check_registered", "CheckingInitModule"),
+      other: await Dart.invoke("check_registered", "TestModule"),
+    };
+  },
+  functions: {
+    checks: (state) => state,
+  },
+};
+''',
+    );
+    await checkingModule.register();
+
+    final completer = Completer<Object?>();
+    checkingModule.callFunction(
+      'checks',
+      onData: (data) {
+        completer.complete(data.value);
+        return true;
+      },
+    );
+    expect(await completer.future, {'self': false, 'other': true});
+  });
+
+  test('should report async init functions that reject', () async {
+    final rejectingModule = InlinedModule(
+      name: 'RejectingInitModule',
+      sourceCode: '''
+export default {
+  async init() {
+    await null;
+    throw new Error("no connection");
+  },
+  functions: {},
+};
+''',
+    );
+
+    await expectLater(
+      () => rejectingModule.register(),
+      throwsA(
+        isA<GlobeRuntimeException>()
+            .having(
+              (e) => e.code,
+              'code',
+              GlobeRuntimeErrorCode.moduleRegistrationFailed,
+            )
+            .having((e) => e.message, 'message', contains('no connection')),
+      ),
+    );
+    expect(runtime.isModuleRegistered('RejectingInitModule'), isFalse);
+  });
+
+  test('should report a module that fails to register', () async {
+    final brokenModule = InlinedModule(
+      name: 'BrokenModule',
+      sourceCode: 'export default { init() { throw new Error("boom"); } };',
+    );
+
+    await expectLater(
+      () => brokenModule.register(),
+      throwsA(
+        isA<GlobeRuntimeException>()
+            .having(
+              (e) => e.code,
+              'code',
+              GlobeRuntimeErrorCode.moduleRegistrationFailed,
+            )
+            .having((e) => e.message, 'message', contains('boom')),
+      ),
+    );
+
+    expect(runtime.isModuleRegistered('BrokenModule'), isFalse);
+    expect(
+      await callJsFunction('say_hello', args: ['World'.toFFIType]),
+      isNotEmpty,
+    );
+  });
+
+  test('should report what init threw as a JsException', () async {
+    final throwingModule = InlinedModule(
+      name: 'ThrowingInitModule',
+      sourceCode: '''
+export default {
+  init() {
+    throw new TypeError("bad config", { cause: new Error("missing key") });
+  },
+  functions: {},
+};
+''',
+    );
+
+    await expectLater(
+      () => throwingModule.register(),
+      throwsA(
+        isA<GlobeRuntimeException>().having(
+          (e) => e.jsException,
+          'jsException',
+          isA<JsException>()
+              .having((e) => e.name, 'name', 'TypeError')
+              .having((e) => e.message, 'message', 'bad config')
+              .having((e) => e.stack, 'stack', contains('init'))
+              .having((e) => e.cause?.message, 'cause', 'missing key'),
+        ),
+      ),
+    );
+  });
+
+  group('module load timeout', () {
+    late GlobeRuntime limitedRuntime;
+
+    setUp(() {
+      limitedRuntime = GlobeRuntime.create(
+        config: const RuntimeConfig(
+          moduleLoadTimeout: Duration(milliseconds: 200),
+        ),
+      );
+    });
+
+    tearDown(() => limitedRuntime.dispose());
+
+    Future<void> expectTimeout(String name, String sourceCode) async {
+      final spinningModule = InlinedModule(name: name, sourceCode: sourceCode);
+
+      await expectLater(
+        () => spinningModule.register(runtime: limitedRuntime),
+        throwsA(
+          isA<GlobeRuntimeException>().having(
+            (e) => e.message,
+            'message',
+            contains('did not finish within 200 ms'),
+          ),
+        ),
+      );
+      expect(limitedRuntime.isModuleRegistered(name), isFalse);
+
+      // The runtime runs JavaScript again after terminating the module
+      await module.register(
+        args: ['Foobar'.toFFIType],
+        runtime: limitedRuntime,
+      );
+      final completer = Completer<List<int>>();
+      module.callFunction(
+        'say_hello',
+        args: ['World'.toFFIType],
+        runtime: limitedRuntime,
+        onData: (data) {
+          completer.complete(data.data);
+          return true;
+        },
+      );
+      expect(utf8.decode(await completer.future), 'Hello, World');
+    }
+
+    test('should terminate module code that never finishes', () async {
+      await expectTimeout(
+        'SpinningModule',
+        'while (true) {}\nexport default { functions: {} };',
+      );
+    });
+
+    test('should terminate init functions that never return', () async {
+      await expectTimeout(
+        'SpinningInitModule',
+        'export default { init() { while (true) {} }, functions: {} };',
+      );
+    });
+  });
+}
+
+/// Bytes passed as a `JsonPayload` without being valid MessagePack.
+class _InvalidJsonPayload extends FFIBytes {
+  _InvalidJsonPayload(super.value);
+
+  @override
+  FFITypeId get typeId => FFITypeId.json_payload;
 }
@@ -0,0 +1,9 @@
+// Bundled from source_mapped_module.ts, which throws on its 10th line
+export default {
+  functions: {
+    fail: function (_, DartCallbackId) {
+      throw new Error("Failed in the original source");
+    },
+  },
+};
+//# sourceMappingURL=data:application/json;base64,eyJ2ZXJzaW9uIjozLCJmaWxlIjoic291cmNlX21hcHBlZF9tb2R1bGUuanMiLCJzb3VyY2VzIjpbInNvdXJjZV9tYXBwZWRfbW9kdWxlLnRzIl0sIm5hbWVzIjpbXSwibWFwcGluZ3MiOiI7Ozs7QUFTQSJ9
@@ -2,6 +2,11 @@ declare global {
 
   type DartValue = Uint8Array | undefined; 
 
+  /** The reference `Dart.handle` returns, which Dart receives as a `JsHandle`. */
+  interface DartHandle {
+    readonly __dartHandle: unique symbol;
+  }
+
   interface DartGlobal {
     /**
      * Sends data back to Dart from JavaScript.
@@ -40,6 +45,57 @@ declare global {
       callbackId: number,
       data?:DartValue
     ) => boolean;
+
+    /**
+     * Sends a value back to Dart as native Dart objects, ending the call.
+     *
+     * `null`, `undefined`, booleans, numbers, BigInts, strings, arrays, plain
+     * objects and typed arrays are supported. Other values throw a TypeError.
+     *
+     * @param callbackId - A unique identifier for the callback.
+     * @param value - The value to send.
+     * @returns {boolean} - Returns true if the value was sent successfully.
+     */
+    send: (callbackId: number, value: unknown) => boolean;
+
+    /**
+     * Streams a value back to Dart as native Dart objects.
+     *
+     * @param callbackId - A unique identifier for the callback.
+     * @param value - The value to send.
+     * @returns {boolean} - Returns true if the value was sent successfully.
+     */
+    stream: (callbackId: number, value: unknown) => boolean;
+
+    /**
+     * Streams a last value back to Dart as native Dart objects, ending the
+     * call.
+     *
+     * @param callbackId - A unique identifier for the callback.
+     * @param value - The value to send.
+     * @returns {boolean} - Returns true if the value was sent successfully.
+     */
+    stream_end: (callbackId: number, value?: unknown) => boolean;
+
+    /**
+     * Runs a host function registered in Dart with
+     * `GlobeRuntime.registerHostFunction`.
+     *
+     * @param name - The name the host function was registered under.
+     * @param args - Arguments, sent like values of `send`.
+     * @returns {Promise<unknown>} - Resolves with the host function's result,
+     * or rejects with its error.
+     */
+    invoke: (name: string, ...args: unknown[]) => Promise<unknown>;
+
+    /**
+     * Keeps an object alive and hands it to Dart as a `JsHandle`, until Dart
+     * releases it with `GlobeRuntime.releaseHandle`.
+     *
+     * @param value - The object or function to hand to Dart.
+     * @returns An opaque reference to send to Dart, e.g. with `send`.
+     */
+    handle: (value: object) => DartHandle;
   }
 
   const Dart: DartGlobal;
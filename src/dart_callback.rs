@@ -0,0 +1,35 @@
+//! Dart functions passed as arguments, which JS receives as async functions
+//! that call back into Dart.
+
+use std::sync::Arc;
+
+use crate::{dart_api, dart_value};
+
+/// A Dart function registered under `id` on the Dart side.
+///
+/// Every JS function created for it holds a reference, dropped once V8
+/// collected the function. When the last reference is gone, including those
+/// of queued calls and registered modules, Dart is told through
+/// `release_port` that it can forget the function.
+#[derive(Debug)]
+pub struct DartCallback {
+    id: i32,
+    release_port: dart_api::Dart_Port,
+}
+
+impl DartCallback {
+    pub fn new(id: i32, release_port: dart_api::Dart_Port) -> Arc<Self> {
+        Arc::new(DartCallback { id, release_port })
+    }
+
+    pub fn id(&self) -> i32 {
+        self.id
+    }
+}
+
+impl Drop for DartCallback {
+    fn drop(&mut self) {
+        // Fails once Dart closed the port, when releasing no longer matters
+        dart_value::post_callback_release(self.release_port, self.id);
+    }
+}
@@ -0,0 +1,166 @@
+//! Minimal protobuf encoding of the messages declared in
+//! `protos/dart_runtime_entry.proto`, for messages that originate in Rust
+//! rather than in the JS `Dart` API.
+
+use deno_runtime::deno_core::error::JsError as CoreJsError;
+
+/// Mirror of `globe.runtime.DartMessage`.
+#[derive(Debug, Default)]
+pub struct DartMessage {
+    pub done: bool,
+    pub payload: Option<DartMessagePayload>,
+    pub js_error: Option<JsError>,
+}
+
+/// Mirror of `globe.runtime.JsError`.
+#[derive(Debug)]
+pub enum JsError {
+    /// An error raised outside of JavaScript, which only has a `name` and a
+    /// `message`.
+    Raised { name: String, message: String },
+    /// An error JavaScript threw, already encoded with its stack, frames and
+    /// cause.
+    Encoded(Vec<u8>),
+}
+
+impl JsError {
+    /// Encodes an error deno_core reports for JavaScript that threw, e.g.
+    /// while a module was evaluated, like `serializeError` in
+    /// `dart_runtime.ts` encodes the errors of calls.
+    pub fn from_core(error: &CoreJsError) -> Self {
+        JsError::Encoded(encode_core_error(error))
+    }
+}
+
+fn encode_core_error(error: &CoreJsError) -> Vec<u8> {
+    let mut writer = ProtoWriter::default();
+    writer.string(1, error.name.as_deref().unwrap_or_default());
+    writer.string(
+        2,
+        error.message.as_deref().unwrap_or(&error.exception_message),
+    );
+    writer.string(3, error.stack.as_deref().unwrap_or_default());
+
+    for frame in &error.frames {
+        let mut frame_writer = ProtoWriter::default();
+        frame_writer.string(1, frame.function_name.as_deref().unwrap_or_default());
+        frame_writer.string(2, frame.file_name.as_deref().unwrap_or_default());
+        frame_writer.int32(3, frame.line_number.unwrap_or_default() as i32);
+        frame_writer.int32(4, frame.column_number.unwrap_or_default() as i32);
+        writer.bytes(4, &frame_writer.finish());
+    }
+
+    if let Some(cause) = &error.cause {
+        writer.bytes(5, &encode_core_error(cause));
+    }
+
+    writer.finish()
+}
+
+#[derive(Debug)]
+pub enum DartMessagePayload {
+    Error(String),
+}
+
+impl DartMessage {
+    /// A final message without a value.
+    pub fn done() -> Self {
+        DartMessage {
+            done: true,
+            ..Default::default()
+        }
+    }
+
+    /// A final message carrying `error`.
+    pub fn error(error: impl Into<String>) -> Self {
+        DartMessage {
+            done: true,
+            payload: Some(DartMessagePayload::Error(error.into())),
+            js_error: None,
+        }
+    }
+
+    /// A final message carrying `error`, reported to Dart as a JS error
+    /// named `name` so it can be told apart from errors thrown by modules.
+    pub fn js_error(name: &str, error: impl Into<String>, message: &str) -> Self {
+        DartMessage {
+            js_error: Some(JsError::Raised {
+                name: name.to_string(),
+                message: message.to_string(),
+            }),
+            ..Self::error(error)
+        }
+    }
+
+    pub fn encode(&self) -> Vec<u8> {
+        let mut writer = ProtoWriter::default();
+
+        if self.done {
+            writer.bool(1, self.done);
+        }
+
+        if let Some(DartMessagePayload::Error(error)) = &self.payload {
+            writer.bytes(3, error.as_bytes());
+        }
+
+        match &self.js_error {
+            Some(JsError::Raised { name, message }) => {
+                let mut error_writer = ProtoWriter::default();
+                error_writer.string(1, name);
+                error_writer.string(2, message);
+                writer.bytes(5, &error_writer.finish());
+            }
+            Some(JsError::Encoded(js_error)) => writer.bytes(5, js_error),
+            None => {}
+        }
+
+        writer.finish()
+    }
+}
+
+const WIRE_TYPE_VARINT: u32 = 0;
+const WIRE_TYPE_LEN: u32 = 2;
+
+#[derive(Default)]
+struct ProtoWriter {
+    buffer: Vec<u8>,
+}
+
+impl ProtoWriter {
+    fn varint(&mut self, mut value: u64) {
+        while value >= 0x80 {
+            self.buffer.push((value as u8) | 0x80);
+            value >>= 7;
+        }
+        self.buffer.push(value as u8);
+    }
+
+    fn tag(&mut self, field_number: u32, wire_type: u32) {
+        self.varint(((field_number << 3) | wire_type) as u64);
+    }
+
+    fn bool(&mut self, field_number: u32, value: bool) {
+        self.tag(field_number, WIRE_TYPE_VARINT);
+        self.varint(value as u64);
+    }
+
+    fn int32(&mut self, field_number: u32, value: i32) {
+        self.tag(field_number, WIRE_TYPE_VARINT);
+        // Negative values are sign-extended to 64 bits
+        self.varint(value as i64 as u64);
+    }
+
+    fn string(&mut self, field_number: u32, value: &str) {
+        self.bytes(field_number, value.as_bytes());
+    }
+
+    fn bytes(&mut self, field_number: u32, value: &[u8]) {
+        self.tag(field_number, WIRE_TYPE_LEN);
+        self.varint(value.len() as u64);
+        self.buffer.extend_from_slice(value);
+    }
+
+    fn finish(self) -> Vec<u8> {
+        self.buffer
+    }
+}
@@ -1,16 +1,22 @@
+use std::ffi::CString;
+
 use crate::dart_api;
+use crate::dart_message::DartMessage;
+use crate::dart_value::{self, DartValue};
 
 use deno_core::extension;
 use deno_core::op2;
 use deno_core::OpState;
+use deno_error::JsErrorBox;
 
-trait DartJsCommsBridge {
+pub(crate) trait DartJsCommsBridge {
     fn send_to_dart(&self, callback_id: i32, data: &[u8]) -> bool;
 }
 
 #[derive(Clone)]
 pub struct DartRuntimeOptions {
     pub send_port: i64,
+    pub stream_window: u32,
 }
 
 impl DartJsCommsBridge for i64 {
@@ -57,6 +63,11 @@ impl DartJsCommsBridge for i64 {
     }
 }
 
+/// Posts a message built on the Rust side to the Dart callback `callback_id`.
+pub fn send_dart_message(send_port: i64, callback_id: i32, message: &DartMessage) -> bool {
+    send_port.send_to_dart(callback_id, &message.encode())
+}
+
 #[op2(fast)]
 fn op_send_to_dart<FP>(state: &mut OpState, callback_id: i32, #[buffer] data: &[u8]) -> bool
 where
@@ -66,20 +77,116 @@ where
 
     options.send_port.send_to_dart(callback_id, data)
 }
+
+/// Posts `value` to Dart as native Dart objects, see [`dart_value`].
+#[op2]
+fn op_send_value_to_dart(
+    state: &mut OpState,
+    scope: &mut v8::HandleScope,
+    callback_id: i32,
+    value: v8::Local<v8::Value>,
+    done: bool,
+) -> Result<bool, JsErrorBox> {
+    let send_port = state.borrow::<DartRuntimeOptions>().send_port;
+    let value = DartValue::from_v8(scope, value).map_err(JsErrorBox::type_error)?;
+
+    Ok(dart_value::post_value(send_port, callback_id, done, value))
+}
+
+/// Asks Dart to run the host function `name` with `args`, an array. The
+/// answer arrives through `GlobeRuntime.complete_host_call`.
+#[op2]
+fn op_invoke_dart_host(
+    state: &mut OpState,
+    scope: &mut v8::HandleScope,
+    request_id: i32,
+    #[string] name: String,
+    args: v8::Local<v8::Value>,
+) -> Result<bool, JsErrorBox> {
+    let send_port = state.borrow::<DartRuntimeOptions>().send_port;
+    let name = CString::new(name)
+        .map_err(|_| JsErrorBox::type_error("Host function names can't contain NUL"))?;
+    let args = DartValue::from_v8(scope, args).map_err(JsErrorBox::type_error)?;
+
+    Ok(dart_value::post_host_call(
+        send_port,
+        request_id,
+        DartValue::String(name),
+        args,
+    ))
+}
+
+/// Asks Dart to run the callback argument `callback_id` with `args`, an array.
+/// The answer arrives like that of [`op_invoke_dart_host`].
+#[op2]
+fn op_invoke_dart_callback(
+    state: &mut OpState,
+    scope: &mut v8::HandleScope,
+    request_id: i32,
+    callback_id: i32,
+    args: v8::Local<v8::Value>,
+) -> Result<bool, JsErrorBox> {
+    let send_port = state.borrow::<DartRuntimeOptions>().send_port;
+    let args = DartValue::from_v8(scope, args).map_err(JsErrorBox::type_error)?;
+
+    Ok(dart_value::post_host_call(
+        send_port,
+        request_id,
+        DartValue::Int(callback_id.into()),
+        args,
+    ))
+}
+
+/// Creates the object `Dart.handle` returns for `handle_id`, see
+/// [`dart_value::new_handle_ref`].
+#[op2]
+fn op_dart_handle_ref<'s>(
+    scope: &mut v8::HandleScope<'s>,
+    handle_id: i32,
+) -> v8::Local<'s, v8::Object> {
+    dart_value::new_handle_ref(scope, handle_id)
+}
+
+/// Told the message identifier of each call once its JavaScript sent the
+/// final message or was aborted. Runtimes without one ignore the calls' ends.
+pub struct CallEnded(pub Box<dyn Fn(i32)>);
+
+/// Reports the end of a call, see [`CallEnded`].
+#[op2(fast)]
+fn op_dart_call_ended(state: &mut OpState, callback_id: i32) {
+    if let Some(call_ended) = state.try_borrow::<CallEnded>() {
+        (call_ended.0)(callback_id);
+    }
+}
+
+/// Chunks a call may stream before waiting for Dart to acknowledge them.
+#[op2(fast)]
+fn op_dart_stream_window(state: &mut OpState) -> u32 {
+    state.borrow::<DartRuntimeOptions>().stream_window
+}
+
 extension!(
     dart_runtime,
     parameters = [FP: DartJsCommsBridge],
     ops = [
         op_send_to_dart<FP>,
+        op_send_value_to_dart,
+        op_dart_stream_window,
+        op_dart_call_ended,
+        op_invoke_dart_host,
+        op_invoke_dart_callback,
+        op_dart_handle_ref,
     ],
     esm_entry_point = "ext:dart_runtime/dart_runtime.ts",
     esm = [ dir "src", "dart_runtime.ts", "dart_runtime_entry.ts" ],
     options = {
         send_port: i64,
+        stream_window: u32,
     },
     state = |state, options| {
         state.put::<DartRuntimeOptions>(DartRuntimeOptions {
             send_port: options.send_port,
+            stream_window: options.stream_window,
         });
     },
 );
@@ -1,6 +1,8 @@
 import {
   DartMessage,
   DartJSService,
+  JsError,
+  JsStackFrame,
   RpcResponse,
   SendValueRequest,
 } from "./dart_runtime_entry.ts";
@@ -34,8 +36,142 @@ function register_js_module(moduleName: string, moduleFunctions: Record<string,
   });
 }
 
+// In-flight calls started by `call_js_function`, keyed by callback id.
+const _calls = new Map<number, AbortController>();
+
+// Calls that streamed a chunk themselves. Their functions may keep streaming
+// after they return, so only an explicit final message ends them.
+const _streamingCalls = new Set<number>();
+
+// Chunks a call may stream before it waits for Dart to acknowledge them, see
+// `acknowledge_stream_chunks`. `0` disables flow control.
+const STREAM_WINDOW: number = core.ops.op_dart_stream_window();
+
+// Chunks streamed by a call that Dart has not acknowledged yet, and the
+// senders waiting for the window to have room again.
+type StreamCredits = { outstanding: number; waiters: (() => void)[] };
+const _streamCredits = new Map<number, StreamCredits>();
+
+// Forgets a call that sent its final message or was aborted. Senders waiting
+// for credits are released, acknowledgements no longer matter. Rust keeps
+// enforcing the call's deadline until then.
+function endCall(callbackId: number) {
+  if (_calls.delete(callbackId)) core.ops.op_dart_call_ended(callbackId);
+  _streamingCalls.delete(callbackId);
+
+  const credits = _streamCredits.get(callbackId);
+  _streamCredits.delete(callbackId);
+  credits?.waiters.forEach((resolve) => resolve());
+}
+
+// Counts a chunk that was just streamed. Resolves once fewer than
+// `STREAM_WINDOW` chunks are waiting to be acknowledged.
+function takeCredit(callbackId: number): Promise<void> {
+  if (STREAM_WINDOW === 0 || !_calls.has(callbackId)) return Promise.resolve();
+
+  let credits = _streamCredits.get(callbackId);
+  if (!credits) {
+    credits = { outstanding: 0, waiters: [] };
+    _streamCredits.set(callbackId, credits);
+  }
+
+  credits.outstanding++;
+  if (credits.outstanding < STREAM_WINDOW) return Promise.resolve();
+
+  const { waiters } = credits;
+  return new Promise((resolve) => waiters.push(resolve));
+}
+
+function returnCredits(callbackId: number, count: number) {
+  const credits = _streamCredits.get(callbackId);
+  if (!credits) return;
+
+  credits.outstanding = Math.max(0, credits.outstanding - count);
+  if (credits.outstanding >= STREAM_WINDOW) return;
+
+  const waiters = credits.waiters;
+  credits.waiters = [];
+  waiters.forEach((resolve) => resolve());
+}
+
+// Resolves with the result of `sent` once the stream may continue.
+function afterCredit<T>(callbackId: number, sent: Promise<T>): Promise<T> {
+  const credit = takeCredit(callbackId);
+  return sent.then((result) => credit.then(() => result));
+}
+
+// Streams Dart passes as arguments, fed through `push_stream_chunk`,
+// `close_stream` and `error_stream`. Streams leave the map once they end or
+// JS cancels them, later chunks are dropped.
+const _inputStreams = new Map<
+  number,
+  ReadableStreamDefaultController<Uint8Array>
+>();
+
+function takeInputStream(streamId: number) {
+  const controller = _inputStreams.get(streamId);
+  _inputStreams.delete(streamId);
+  return controller;
+}
+
+// `Dart.invoke` requests Dart has not answered yet, keyed by request id.
+type HostCall = {
+  resolve: (value: unknown) => void;
+  reject: (error: Error) => void;
+};
+const _hostCalls = new Map<number, HostCall>();
+let _lastHostCallId = 0;
+
+// Starts a host call posted by `post` and returns the promise its answer
+// settles.
+function callHost(post: (requestId: number) => void): Promise<unknown> {
+  // Request ids are `i32`s in Rust
+  _lastHostCallId = (_lastHostCallId % 0x7fffffff) + 1;
+  const requestId = _lastHostCallId;
+
+  return new Promise((resolve, reject) => {
+    _hostCalls.set(requestId, { resolve, reject });
+    try {
+      post(requestId);
+    } catch (error) {
+      _hostCalls.delete(requestId);
+      reject(error);
+    }
+  });
+}
+
+function takeHostCall(requestId: number) {
+  const hostCall = _hostCalls.get(requestId);
+  _hostCalls.delete(requestId);
+  return hostCall;
+}
+
+// Objects handed to Dart with `Dart.handle`, keyed by handle id, with the
+// module whose permissions their methods run under. They stay alive until
+// Dart releases them with `release_js_handle`.
+type JsHandle = { value: object; moduleName: string | undefined };
+const _handles = new Map<number, JsHandle>();
+let _lastHandleId = 0;
+
+// Module instances created by `create_module_instance`, keyed by instance id.
+// An instance inherits the functions of its module and has its own `state`.
+const _instances = new Map<number, [moduleName: string, instance: object]>();
+
+// The async context of a call, carried across `await` and timer callbacks.
+type CallContext = { callbackId: number; signal: AbortSignal };
+
+function currentCall(): CallContext | undefined {
+  return core.getAsyncContext()?.globeCall;
+}
+
 class DartJSServiceImpl implements DartJSService {
   SendValue(request: SendValueRequest): Promise<RpcResponse> {
+    if (request.message?.done) {
+      endCall(request.callbackId);
+    } else if (_calls.has(request.callbackId)) {
+      _streamingCalls.add(request.callbackId);
+    }
+
     const writer = request.message && DartMessage.encode(request.message);
     const success = core.ops.op_send_to_dart(
       request.callbackId,
@@ -47,33 +183,400 @@ class DartJSServiceImpl implements DartJSService {
 
 const _dartJSService = new DartJSServiceImpl();
 
+// Matches a V8 stack frame line, e.g. `    at fn (file:///mod.js:1:2)`
+const STACK_FRAME_PATTERN = /^\s*at (?:async )?(?:(.+?) \()?(.+?):(\d+):(\d+)\)?$/;
+
+// Causes are followed this deep, so cyclic causes can't recurse forever.
+const MAX_CAUSE_DEPTH = 8;
+
+function parseStackFrames(stack: string): JsStackFrame[] {
+  const frames: JsStackFrame[] = [];
+  for (const line of stack.split("\n")) {
+    const match = STACK_FRAME_PATTERN.exec(line);
+    if (!match) continue;
+
+    frames.push({
+      functionName: match[1] ?? "",
+      fileName: match[2],
+      lineNumber: Number(match[3]),
+      columnNumber: Number(match[4]),
+    });
+  }
+  return frames;
+}
+
+// Packs the own enumerable properties of `error` that survive MessagePack
+// encoding.
+function packErrorProperties(error: object): Uint8Array {
+  const properties: Record<string, unknown> = {};
+  let count = 0;
+
+  for (const [key, value] of Object.entries(error)) {
+    if (key === "cause") continue;
+
+    try {
+      msgPackr.pack(value);
+    } catch {
+      continue;
+    }
+    properties[key] = value;
+    count++;
+  }
+
+  return count > 0 ? msgPackr.pack(properties) : new Uint8Array(0);
+}
+
+function serializeError(error: unknown, depth = 0): JsError {
+  if (!(error instanceof Error)) {
+    return {
+      name: "",
+      message: String(error),
+      stack: "",
+      frames: [],
+      cause: undefined,
+      properties: new Uint8Array(0),
+    };
+  }
+
+  const stack = typeof error.stack === "string" ? error.stack : "";
+  const hasCause = "cause" in error && depth < MAX_CAUSE_DEPTH;
+
+  return {
+    name: error.name,
+    message: error.message,
+    stack,
+    frames: parseStackFrames(stack),
+    cause: hasCause ? serializeError(error.cause, depth + 1) : undefined,
+    properties: packErrorProperties(error),
+  };
+}
+
+// The final message reporting `error` to Dart.
+function errorMessage(error: unknown): DartMessage {
+  const jsError = serializeError(error);
+  return {
+    error: jsError.stack || String(error),
+    done: true,
+    cancelled: false,
+    jsError,
+  };
+}
+
 type DartValue = Uint8Array | undefined; 
 
+// Posts `value` to Dart as native objects instead of a protobuf message, see
+// `dart_value.rs`.
+// A final message ends the call once it was posted, whether or not Dart's
+// port accepted it. Values that can't be converted throw before that, so the
+// call stays open and the error can be reported instead.
+function sendNative(callbackId: number, value: unknown, done: boolean) {
+  const success = core.ops.op_send_value_to_dart(callbackId, value, done);
+  if (done) {
+    endCall(callbackId);
+  } else if (_calls.has(callbackId)) {
+    _streamingCalls.add(callbackId);
+  }
+
+  return Promise.resolve({ success });
+}
+
+function isThenable(value: unknown): value is PromiseLike<unknown> {
+  return typeof (value as PromiseLike<unknown>)?.then === "function";
+}
+
+type Streamable = AsyncIterable<unknown> | Iterable<unknown>;
+
+// Async iterables, including `ReadableStream`s, and iterators such as
+// generators are streamed. Arrays and other plain iterables are sent as
+// values.
+function isStreamable(value: unknown): value is Streamable {
+  if (value === null || typeof value !== "object") return false;
+
+  const candidate = value as Record<PropertyKey, unknown>;
+  return (
+    typeof candidate[Symbol.asyncIterator] === "function" ||
+    (typeof candidate.next === "function" &&
+      typeof candidate[Symbol.iterator] === "function")
+  );
+}
+
+// Sends each chunk of `iterable` as a `done: false` message and ends the call
+// once it is exhausted. Iteration stops early when the call is cancelled or
+// times out, which runs the `finally` blocks of generators and cancels
+// streams.
+async function streamResult(callbackId: number, iterable: Streamable) {
+  for await (const chunk of iterable) {
+    if (!_calls.has(callbackId)) return;
+    await afterCredit(callbackId, sendNative(callbackId, chunk, false));
+  }
+
+  if (_calls.has(callbackId)) {
+    sendNative(callbackId, undefined, true);
+  }
+}
+
 register_js_module("Dart", {
   send_value: (callbackId: number, data: DartValue) => {
-    const message: DartMessage = { data, done: true };
+    const message = DartMessage.fromPartial({ data, done: true });
     return _dartJSService.SendValue({ callbackId, message });
   },
+  // Resolves once Dart has caught up enough for the stream to continue.
   stream_value: (callbackId: number, data: DartValue) => {
-    const message: DartMessage = { data, done: false };
-    return _dartJSService.SendValue({ callbackId, message });
+    const message = DartMessage.fromPartial({ data, done: false });
+    return afterCredit(
+      callbackId,
+      _dartJSService.SendValue({ callbackId, message })
+    );
   },
   stream_value_end: (callbackId: number, data: DartValue) => {
-    const message: DartMessage = { data, done: true };
+    const message = DartMessage.fromPartial({ data, done: true });
     return _dartJSService.SendValue({ callbackId, message });
   },
-  send_error: (callbackId:number, error: string) => {
-    const message: DartMessage = { error, done: true };
+  // `send`, `stream` and `stream_end` deliver null, booleans, numbers,
+  // BigInts, strings, arrays, plain objects and typed arrays as the matching
+  // Dart objects, without MessagePack or protobuf encoding.
+  send: (callbackId: number, value: unknown) => {
+    return sendNative(callbackId, value, true);
+  },
+  stream: (callbackId: number, value: unknown) => {
+    return afterCredit(callbackId, sendNative(callbackId, value, false));
+  },
+  stream_end: (callbackId: number, value?: unknown) => {
+    return sendNative(callbackId, value, true);
+  },
+  // Runs the host function Dart registered as `name`. Arguments are sent like
+  // `Dart.send` values, the result arrives decoded like a `JsonPayload`.
+  invoke: (name: string, ...args: unknown[]): Promise<unknown> => {
+    return callHost((requestId) =>
+      core.ops.op_invoke_dart_host(requestId, name, args)
+    );
+  },
+  // Keeps `value` alive and returns the reference Dart receives as a
+  // `JsHandle`, e.g. `Dart.send(callbackId, Dart.handle(client))`.
+  handle: (value: object) => {
+    const type = typeof value;
+    if (value === null || (type !== "object" && type !== "function")) {
+      throw new TypeError("Only objects and functions can be handed to Dart.");
+    }
+
+    // Handle ids are `i32`s in Rust. Once they wrap around, ids of handles
+    // Dart still holds are skipped.
+    do {
+      _lastHandleId = (_lastHandleId % 0x7fffffff) + 1;
+    } while (_handles.has(_lastHandleId));
+
+    const moduleName = core.ops.op_running_module() ?? undefined;
+    _handles.set(_lastHandleId, { value, moduleName });
+    return core.ops.op_dart_handle_ref(_lastHandleId);
+  },
+  // `error` may be a message or any thrown value. Errors are sent with their
+  // name, stack, cause and custom properties.
+  send_error: (callbackId: number, error: unknown) => {
+    const message =
+      typeof error === "string"
+        ? DartMessage.fromPartial({ error, done: true })
+        : errorMessage(error);
     return _dartJSService.SendValue({ callbackId, message });
   },
 });
 
+register_js_module("GlobeRuntime", {
+  // Entry point used by `call_js_function`. The module function receives the
+  // module state, the call arguments, the callback id and an `AbortSignal`
+  // that fires when Dart cancels the call or it times out.
+  invoke: (
+    callbackId: number,
+    func: Function,
+    state: unknown,
+    ...args: unknown[]
+  ) => {
+    const controller = new AbortController();
+    _calls.set(callbackId, controller);
+
+    // Rust entered a fresh async context for the call, holding the module
+    // whose permissions ops check under a key JS can't reach, see
+    // `permissions.rs`. Rust restores the previous context afterwards.
+    const callContext: CallContext = { callbackId, signal: controller.signal };
+    core.getAsyncContext().globeCall = callContext;
+
+    // Errors of calls that were cancelled, aborted or already finished are
+    // not reported again
+    const reportError = (error: unknown) => {
+      if (!_calls.has(callbackId)) return;
+      _dartJSService.SendValue({ callbackId, message: errorMessage(error) });
+    };
+
+    // The value a function returns, or its promise resolves with, is sent as
+    // the final message unless the function already ended the call itself.
+    // Functions returning `undefined` synchronously may still answer later,
+    // e.g. from a timer, and so may functions that streamed chunks
+    // themselves, so `undefined` only settles promises of other calls.
+    const forwardResult = (value: unknown) => {
+      if (!_calls.has(callbackId)) return;
+      if (value === undefined && _streamingCalls.has(callbackId)) return;
+      if (isStreamable(value)) return streamResult(callbackId, value);
+      sendNative(callbackId, value, true);
+    };
+
+    try {
+      const result = func(state, ...args, callbackId, controller.signal);
+      if (isThenable(result)) {
+        return Promise.resolve(result).then(forwardResult).catch(reportError);
+      }
+
+      if (result === undefined) return Promise.resolve();
+      return Promise.resolve(forwardResult(result)).catch(reportError);
+    } catch (error) {
+      reportError(error);
+      return Promise.resolve();
+    }
+  },
+  cancel: (callbackId: number) => {
+    const controller = _calls.get(callbackId);
+    if (!controller) return false;
+
+    controller.abort(new DOMException("The call was cancelled.", "AbortError"));
+
+    const message = DartMessage.fromPartial({ done: true, cancelled: true });
+    _dartJSService.SendValue({ callbackId, message });
+    return true;
+  },
+  // Answers to `Dart.invoke`, see `complete_js_host_call`.
+  // The request is taken before the payload is decoded, so a payload that
+  // can't be decoded rejects it instead of leaving it pending.
+  complete_host_call: (requestId: number, payload: Uint8Array) => {
+    const hostCall = takeHostCall(requestId);
+    if (!hostCall) return;
+
+    let value: unknown;
+    try {
+      value = payload.length === 0 ? undefined : _unpackr.unpack(payload);
+    } catch (error) {
+      hostCall.reject(error instanceof Error ? error : new Error(String(error)));
+      return;
+    }
+    hostCall.resolve(value);
+  },
+  fail_host_call: (requestId: number, message: string) => {
+    takeHostCall(requestId)?.reject(new Error(message));
+  },
+  // Called for handle arguments, see `FFIArg::Handle`. Released handles pass
+  // `undefined`.
+  resolve_handle: (handleId: number) => {
+    return _handles.get(handleId)?.value;
+  },
+  // Looks up a method for `call_js_handle_method`, adapted to the signature
+  // `invoke` calls module functions with: the object stands in for the module
+  // state and the callback id and signal are dropped.
+  handle_method: (handleId: number, methodName: string) => {
+    const handle = _handles.get(handleId);
+    const method = (handle?.value as Record<string, unknown> | undefined)?.[
+      methodName
+    ];
+    if (!handle || typeof method !== "function") return null;
+
+    const adapted = (self: unknown, ...args: unknown[]) =>
+      method.apply(self, args.slice(0, -2));
+    return [handle.moduleName ?? "", adapted, handle.value];
+  },
+  has_handle: (handleId: number) => {
+    return _handles.has(handleId);
+  },
+  release_handle: (handleId: number) => {
+    return _handles.delete(handleId);
+  },
+  handle_count: () => {
+    return _handles.size;
+  },
+  // The instance registry, see `create_module_instance`.
+  add_instance: (instanceId: number, moduleName: string, instance: object) => {
+    _instances.set(instanceId, [moduleName, instance]);
+  },
+  get_instance: (instanceId: number) => {
+    return _instances.get(instanceId) ?? null;
+  },
+  remove_instance: (instanceId: number) => {
+    const entry = _instances.get(instanceId);
+    _instances.delete(instanceId);
+    return entry ?? null;
+  },
+  // Encodes what a module's `init` threw like the errors of calls, see
+  // `serialize_js_error`.
+  serialize_error: (error: unknown) => {
+    return JsError.encode(serializeError(error)).finish();
+  },
+  // Called for callback arguments, see `FFIArg::Callback`. Calling the
+  // returned function runs the Dart function like `Dart.invoke`.
+  open_callback: (callbackId: number) => {
+    return async (...args: unknown[]) =>
+      callHost((requestId) =>
+        core.ops.op_invoke_dart_callback(requestId, callbackId, args)
+      );
+  },
+  // Called for stream arguments, see `FFIArg::Stream`.
+  open_stream: (streamId: number) => {
+    return new ReadableStream<Uint8Array>({
+      start(controller) {
+        _inputStreams.set(streamId, controller);
+      },
+      cancel() {
+        _inputStreams.delete(streamId);
+      },
+    });
+  },
+  push_stream_chunk: (streamId: number, chunk: Uint8Array) => {
+    _inputStreams.get(streamId)?.enqueue(chunk);
+  },
+  close_stream: (streamId: number) => {
+    takeInputStream(streamId)?.close();
+  },
+  error_stream: (streamId: number, message: string) => {
+    takeInputStream(streamId)?.error(new Error(message));
+  },
+  // Dart consumed `count` more chunks streamed by the call.
+  acknowledge: (callbackId: number, count: number) => {
+    returnCredits(callbackId, count);
+  },
+  // Called when a call fails outside of JavaScript's control, e.g. it timed
+  // out or the heap ran out of memory. Rust reports the failure to Dart, this
+  // only lets the call's pending work know it should stop.
+  abort: (callbackId: number, name: string, reason: string) => {
+    const controller = _calls.get(callbackId);
+    if (!controller) return false;
+
+    endCall(callbackId);
+    controller.abort(new DOMException(reason, name));
+    return true;
+  },
+});
+
+// Fetches started while a call is running are aborted together with the call.
+const _fetch = globalThis.fetch;
+globalThis.fetch = function fetch(
+  input: RequestInfo | URL,
+  init?: RequestInit
+): Promise<Response> {
+  const call = currentCall();
+  if (!call) return _fetch(input, init);
+
+  const signals = [call.signal];
+  if (init?.signal) signals.push(init.signal);
+  else if (input instanceof Request) signals.push(input.signal);
+
+  return _fetch(input, { ...init, signal: AbortSignal.any(signals) });
+};
+
+// 64-bit integers from Dart decode like `FFIInt` arguments: numbers when they
+// are safe integers and `BigInt`s otherwise. `BigInt`s in the 64-bit range
+// encode as integers Dart reads back as `int`.
+const _unpackr = new msgPackr.Unpackr({ int64AsType: "auto" });
+
 register_js_module("JsonPayload", {
   encode: (value: unknown): Uint8Array => {
     return msgPackr.pack(value);
   },
   decode: (value: Uint8Array): any => {
-    return msgPackr.unpack(value);
+    return _unpackr.unpack(value);
   },
 });
 
@@ -18,10 +18,36 @@ export interface JsonPayload {
   data: Uint8Array;
 }
 
+/** A frame of a JavaScript stack trace */
+export interface JsStackFrame {
+  functionName: string;
+  fileName: string;
+  lineNumber: number;
+  columnNumber: number;
+}
+
+/** A JavaScript error thrown by a module function or passed to `Dart.send_error` */
+export interface JsError {
+  /** Error class, e.g. `TypeError` */
+  name: string;
+  message: string;
+  /** `error.stack` as formatted by V8 */
+  stack: string;
+  frames: JsStackFrame[];
+  /** `error.cause`, if set */
+  cause: JsError | undefined;
+  /** MessagePack-encoded custom enumerable properties */
+  properties: Uint8Array;
+}
+
 export interface DartMessage {
   done: boolean;
   data?: Uint8Array | undefined;
   error?: string | undefined;
+  /** Set on the final message of a call cancelled through `cancel_js_call` */
+  cancelled: boolean;
+  /** Structured form of `error`, set when the error came from JavaScript */
+  jsError: JsError | undefined;
 }
 
 /** Message structure for sending data to Dart */
@@ -103,8 +129,297 @@ export const JsonPayload: MessageFns<JsonPayload> = {
   },
 };
 
+function createBaseJsStackFrame(): JsStackFrame {
+  return { functionName: "", fileName: "", lineNumber: 0, columnNumber: 0 };
+}
+
+export const JsStackFrame: MessageFns<JsStackFrame> = {
+  encode(
+    message: JsStackFrame,
+    writer: BinaryWriter = new BinaryWriter()
+  ): BinaryWriter {
+    if (message.functionName !== "") {
+      writer.uint32(10).string(message.functionName);
+    }
+    if (message.fileName !== "") {
+      writer.uint32(18).string(message.fileName);
+    }
+    if (message.lineNumber !== 0) {
+      writer.uint32(24).int32(message.lineNumber);
+    }
+    if (message.columnNumber !== 0) {
+      writer.uint32(32).int32(message.columnNumber);
+    }
+    return writer;
+  },
+
+  decode(input: BinaryReader | Uint8Array, length?: number): JsStackFrame {
+    const reader =
+      input instanceof BinaryReader ? input : new BinaryReader(input);
+    let end = length === undefined ? reader.len : reader.pos + length;
+    const message = createBaseJsStackFrame();
+    while (reader.pos < end) {
+      const tag = reader.uint32();
+      switch (tag >>> 3) {
+        case 1: {
+          if (tag !== 10) {
+            break;
+          }
+
+          message.functionName = reader.string();
+          continue;
+        }
+        case 2: {
+          if (tag !== 18) {
+            break;
+          }
+
+          message.fileName = reader.string();
+          continue;
+        }
+        case 3: {
+          if (tag !== 24) {
+            break;
+          }
+
+          message.lineNumber = reader.int32();
+          continue;
+        }
+        case 4: {
+          if (tag !== 32) {
+            break;
+          }
+
+          message.columnNumber = reader.int32();
+          continue;
+        }
+      }
+      if ((tag & 7) === 4 || tag === 0) {
+        break;
+      }
+      reader.skip(tag & 7);
+    }
+    return message;
+  },
+
+  fromJSON(object: any): JsStackFrame {
+    return {
+      functionName: isSet(object.functionName)
+        ? globalThis.String(object.functionName)
+        : "",
+      fileName: isSet(object.fileName)
+        ? globalThis.String(object.fileName)
+        : "",
+      lineNumber: isSet(object.lineNumber)
+        ? globalThis.Number(object.lineNumber)
+        : 0,
+      columnNumber: isSet(object.columnNumber)
+        ? globalThis.Number(object.columnNumber)
+        : 0,
+    };
+  },
+
+  toJSON(message: JsStackFrame): unknown {
+    const obj: any = {};
+    if (message.functionName !== "") {
+      obj.functionName = message.functionName;
+    }
+    if (message.fileName !== "") {
+      obj.fileName = message.fileName;
+    }
+    if (message.lineNumber !== 0) {
+      obj.lineNumber = Math.round(message.lineNumber);
+    }
+    if (message.columnNumber !== 0) {
+      obj.columnNumber = Math.round(message.columnNumber);
+    }
+    return obj;
+  },
+
+  create<I extends Exact<DeepPartial<JsStackFrame>, I>>(
+    base?: I
+  ): JsStackFrame {
+    return JsStackFrame.fromPartial(base ?? ({} as any));
+  },
+  fromPartial<I extends Exact<DeepPartial<JsStackFrame>, I>>(
+    object: I
+  ): JsStackFrame {
+    const message = createBaseJsStackFrame();
+    message.functionName = object.functionName ?? "";
+    message.fileName = object.fileName ?? "";
+    message.lineNumber = object.lineNumber ?? 0;
+    message.columnNumber = object.columnNumber ?? 0;
+    return message;
+  },
+};
+
+function createBaseJsError(): JsError {
+  return {
+    name: "",
+    message: "",
+    stack: "",
+    frames: [],
+    cause: undefined,
+    properties: new Uint8Array(0),
+  };
+}
+
+export const JsError: MessageFns<JsError> = {
+  encode(
+    message: JsError,
+    writer: BinaryWriter = new BinaryWriter()
+  ): BinaryWriter {
+    if (message.name !== "") {
+      writer.uint32(10).string(message.name);
+    }
+    if (message.message !== "") {
+      writer.uint32(18).string(message.message);
+    }
+    if (message.stack !== "") {
+      writer.uint32(26).string(message.stack);
+    }
+    for (const v of message.frames) {
+      JsStackFrame.encode(v!, writer.uint32(34).fork()).join();
+    }
+    if (message.cause !== undefined) {
+      JsError.encode(message.cause, writer.uint32(42).fork()).join();
+    }
+    if (message.properties.length !== 0) {
+      writer.uint32(50).bytes(message.properties);
+    }
+    return writer;
+  },
+
+  decode(input: BinaryReader | Uint8Array, length?: number): JsError {
+    const reader =
+      input instanceof BinaryReader ? input : new BinaryReader(input);
+    let end = length === undefined ? reader.len : reader.pos + length;
+    const message = createBaseJsError();
+    while (reader.pos < end) {
+      const tag = reader.uint32();
+      switch (tag >>> 3) {
+        case 1: {
+          if (tag !== 10) {
+            break;
+          }
+
+          message.name = reader.string();
+          continue;
+        }
+        case 2: {
+          if (tag !== 18) {
+            break;
+          }
+
+          message.message = reader.string();
+          continue;
+        }
+        case 3: {
+          if (tag !== 26) {
+            break;
+          }
+
+          message.stack = reader.string();
+          continue;
+        }
+        case 4: {
+          if (tag !== 34) {
+            break;
+          }
+
+          message.frames.push(JsStackFrame.decode(reader, reader.uint32()));
+          continue;
+        }
+        case 5: {
+          if (tag !== 42) {
+            break;
+          }
+
+          message.cause = JsError.decode(reader, reader.uint32());
+          continue;
+        }
+        case 6: {
+          if (tag !== 50) {
+            break;
+          }
+
+          message.properties = reader.bytes();
+          continue;
+        }
+      }
+      if ((tag & 7) === 4 || tag === 0) {
+        break;
+      }
+      reader.skip(tag & 7);
+    }
+    return message;
+  },
+
+  fromJSON(object: any): JsError {
+    return {
+      name: isSet(object.name) ? globalThis.String(object.name) : "",
+      message: isSet(object.message) ? globalThis.String(object.message) : "",
+      stack: isSet(object.stack) ? globalThis.String(object.stack) : "",
+      frames: globalThis.Array.isArray(object?.frames)
+        ? object.frames.map((e: any) => JsStackFrame.fromJSON(e))
+        : [],
+      cause: isSet(object.cause) ? JsError.fromJSON(object.cause) : undefined,
+      properties: isSet(object.properties)
+        ? bytesFromBase64(object.properties)
+        : new Uint8Array(0),
+    };
+  },
+
+  toJSON(message: JsError): unknown {
+    const obj: any = {};
+    if (message.name !== "") {
+      obj.name = message.name;
+    }
+    if (message.message !== "") {
+      obj.message = message.message;
+    }
+    if (message.stack !== "") {
+      obj.stack = message.stack;
+    }
+    if (message.frames?.length) {
+      obj.frames = message.frames.map((e) => JsStackFrame.toJSON(e));
+    }
+    if (message.cause !== undefined) {
+      obj.cause = JsError.toJSON(message.cause);
+    }
+    if (message.properties.length !== 0) {
+      obj.properties = base64FromBytes(message.properties);
+    }
+    return obj;
+  },
+
+  create<I extends Exact<DeepPartial<JsError>, I>>(base?: I): JsError {
+    return JsError.fromPartial(base ?? ({} as any));
+  },
+  fromPartial<I extends Exact<DeepPartial<JsError>, I>>(object: I): JsError {
+    const message = createBaseJsError();
+    message.name = object.name ?? "";
+    message.message = object.message ?? "";
+    message.stack = object.stack ?? "";
+    message.frames =
+      object.frames?.map((e) => JsStackFrame.fromPartial(e)) || [];
+    message.cause =
+      object.cause !== undefined && object.cause !== null
+        ? JsError.fromPartial(object.cause)
+        : undefined;
+    message.properties = object.properties ?? new Uint8Array(0);
+    return message;
+  },
+};
+
 function createBaseDartMessage(): DartMessage {
-  return { done: false, data: undefined, error: undefined };
+  return {
+    done: false,
+    data: undefined,
+    error: undefined,
+    cancelled: false,
+    jsError: undefined,
+  };
 }
 
 export const DartMessage: MessageFns<DartMessage> = {
@@ -121,6 +436,12 @@ export const DartMessage: MessageFns<DartMessage> = {
     if (message.error !== undefined) {
       writer.uint32(26).string(message.error);
     }
+    if (message.cancelled !== false) {
+      writer.uint32(32).bool(message.cancelled);
+    }
+    if (message.jsError !== undefined) {
+      JsError.encode(message.jsError, writer.uint32(42).fork()).join();
+    }
     return writer;
   },
 
@@ -156,6 +477,22 @@ export const DartMessage: MessageFns<DartMessage> = {
           message.error = reader.string();
           continue;
         }
+        case 4: {
+          if (tag !== 32) {
+            break;
+          }
+
+          message.cancelled = reader.bool();
+          continue;
+        }
+        case 5: {
+          if (tag !== 42) {
+            break;
+          }
+
+          message.jsError = JsError.decode(reader, reader.uint32());
+          continue;
+        }
       }
       if ((tag & 7) === 4 || tag === 0) {
         break;
@@ -170,6 +507,12 @@ export const DartMessage: MessageFns<DartMessage> = {
       done: isSet(object.done) ? globalThis.Boolean(object.done) : false,
       data: isSet(object.data) ? bytesFromBase64(object.data) : undefined,
       error: isSet(object.error) ? globalThis.String(object.error) : undefined,
+      cancelled: isSet(object.cancelled)
+        ? globalThis.Boolean(object.cancelled)
+        : false,
+      jsError: isSet(object.jsError)
+        ? JsError.fromJSON(object.jsError)
+        : undefined,
     };
   },
 
@@ -184,6 +527,12 @@ export const DartMessage: MessageFns<DartMessage> = {
     if (message.error !== undefined) {
       obj.error = message.error;
     }
+    if (message.cancelled !== false) {
+      obj.cancelled = message.cancelled;
+    }
+    if (message.jsError !== undefined) {
+      obj.jsError = JsError.toJSON(message.jsError);
+    }
     return obj;
   },
 
@@ -197,6 +546,11 @@ export const DartMessage: MessageFns<DartMessage> = {
     message.done = object.done ?? false;
     message.data = object.data ?? undefined;
     message.error = object.error ?? undefined;
+    message.cancelled = object.cancelled ?? false;
+    message.jsError =
+      object.jsError !== undefined && object.jsError !== null
+        ? JsError.fromPartial(object.jsError)
+        : undefined;
     return message;
   },
 };
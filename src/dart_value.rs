@@ -0,0 +1,500 @@
+//! Conversion of JS values into `Dart_CObject` graphs, which Dart's
+//! `ReceivePort` receives as native Dart objects without any decoding.
+//!
+//! `Dart_CObject` has no map type, so objects are sent as a list that starts
+//! with a [`MAP_MARKER`] capability followed by alternating keys and values.
+//! The objects `Dart.handle` returns are sent as a [`HANDLE_MARKER`]
+//! capability followed by the handle id. JS values never produce a
+//! capability, so the markers can't be confused with a list and Dart turns
+//! these lists back into maps and `JsHandle`s.
+
+use std::ffi::CString;
+
+use crate::{dart_api, ffi_error::ErrorCode};
+
+/// Id of the capability that marks a list as an encoded map.
+pub const MAP_MARKER: i64 = 0;
+
+/// Id of the capability that marks a list as a `JsHandle`. Dart can't read
+/// capability ids and tells handles apart from maps by their even length.
+pub const HANDLE_MARKER: i64 = 1;
+
+/// Private key the handle id is kept under on the objects `Dart.handle`
+/// returns, so JS can neither read nor forge it.
+const HANDLE_ID_KEY: &str = "globe:handle_id";
+
+/// First element of host call requests, which callback messages never start
+/// with as they begin with the callback id.
+const HOST_CALL_TAG: &str = "globe:host_call";
+
+/// First element of the message releasing a Dart callback argument.
+const CALLBACK_RELEASE_TAG: &str = "globe:release_callback";
+
+/// First element of errors that don't belong to any call.
+const RUNTIME_ERROR_TAG: &str = "globe:runtime_error";
+
+/// Objects nested deeper than this are rejected, which also stops cycles.
+const MAX_DEPTH: usize = 64;
+
+/// Largest integer a JS number holds exactly, `Number.MAX_SAFE_INTEGER`.
+const MAX_SAFE_INTEGER: f64 = 9007199254740991.0;
+
+/// A JS value copied out of V8, ready to be posted to Dart.
+#[derive(Debug)]
+pub enum DartValue {
+    Null,
+    Bool(bool),
+    Int(i64),
+    Double(f64),
+    String(CString),
+    List(Vec<DartValue>),
+    Map(Vec<(CString, DartValue)>),
+    TypedData(dart_api::Dart_TypedData_Type, Vec<u8>),
+    /// An object returned by `Dart.handle`, see [`new_handle_ref`].
+    Handle(i32),
+}
+
+impl DartValue {
+    /// Copies `value`, failing for values Dart has no counterpart for, such as
+    /// functions and symbols.
+    pub fn from_v8(
+        scope: &mut v8::HandleScope,
+        value: v8::Local<v8::Value>,
+    ) -> Result<Self, String> {
+        Self::from_v8_at_depth(scope, value, 0)
+    }
+
+    fn from_v8_at_depth(
+        scope: &mut v8::HandleScope,
+        value: v8::Local<v8::Value>,
+        depth: usize,
+    ) -> Result<Self, String> {
+        if depth > MAX_DEPTH {
+            return Err(format!(
+                "Value is nested more than {} levels deep or is cyclic",
+                MAX_DEPTH
+            ));
+        }
+
+        if value.is_null_or_undefined() {
+            return Ok(DartValue::Null);
+        }
+
+        if value.is_boolean() {
+            return Ok(DartValue::Bool(value.boolean_value(scope)));
+        }
+
+        if value.is_number() {
+            let number = value.number_value(scope).unwrap_or(f64::NAN);
+            if number.fract() == 0.0 && number.abs() <= MAX_SAFE_INTEGER {
+                return Ok(DartValue::Int(number as i64));
+            }
+            return Ok(DartValue::Double(number));
+        }
+
+        if let Ok(big_int) = v8::Local::<v8::BigInt>::try_from(value) {
+            return match big_int.i64_value() {
+                (int, true) => Ok(DartValue::Int(int)),
+                (_, false) => Err("BigInt does not fit in 64 bits".to_string()),
+            };
+        }
+
+        if value.is_string() {
+            return to_c_string(value.to_rust_string_lossy(scope)).map(DartValue::String);
+        }
+
+        if let Ok(view) = v8::Local::<v8::ArrayBufferView>::try_from(value) {
+            let mut bytes = vec![0; view.byte_length()];
+            view.copy_contents(&mut bytes);
+            return Ok(DartValue::TypedData(typed_data_type(value), bytes));
+        }
+
+        if let Ok(buffer) = v8::Local::<v8::ArrayBuffer>::try_from(value) {
+            let length = buffer.byte_length();
+            let view = v8::Uint8Array::new(scope, buffer, 0, length)
+                .ok_or_else(|| "Failed to read ArrayBuffer".to_string())?;
+            let mut bytes = vec![0; length];
+            view.copy_contents(&mut bytes);
+            return Ok(DartValue::TypedData(dart_api::Dart_TypedData_kUint8, bytes));
+        }
+
+        if let Ok(array) = v8::Local::<v8::Array>::try_from(value) {
+            let mut items = Vec::with_capacity(array.length() as usize);
+            for i in 0..array.length() {
+                let item = array
+                    .get_index(scope, i)
+                    .unwrap_or_else(|| v8::undefined(scope).into());
+                items.push(Self::from_v8_at_depth(scope, item, depth + 1)?);
+            }
+            return Ok(DartValue::List(items));
+        }
+
+        if value.is_function()
+            || value.is_symbol()
+            || value.is_promise()
+            || value.is_map()
+            || value.is_set()
+            || value.is_date()
+        {
+            return Err(format!("Cannot send a {} to Dart", value.type_repr()));
+        }
+
+        let object = value
+            .to_object(scope)
+            .ok_or_else(|| "Cannot send value to Dart".to_string())?;
+        if let Some(handle_id) = handle_id(scope, object) {
+            return Ok(DartValue::Handle(handle_id));
+        }
+
+        let args = v8::GetPropertyNamesArgs {
+            mode: v8::KeyCollectionMode::OwnOnly,
+            property_filter: v8::PropertyFilter::ONLY_ENUMERABLE | v8::PropertyFilter::SKIP_SYMBOLS,
+            index_filter: v8::IndexFilter::IncludeIndices,
+            key_conversion: v8::KeyConversionMode::ConvertToString,
+        };
+        let keys = object
+            .get_own_property_names(scope, args)
+            .ok_or_else(|| "Failed to read object properties".to_string())?;
+
+        let mut entries = Vec::with_capacity(keys.length() as usize);
+        for i in 0..keys.length() {
+            let Some(key) = keys.get_index(scope, i) else {
+                continue;
+            };
+            let item = object
+                .get(scope, key)
+                .unwrap_or_else(|| v8::undefined(scope).into());
+            let key = to_c_string(key.to_rust_string_lossy(scope))?;
+            entries.push((key, Self::from_v8_at_depth(scope, item, depth + 1)?));
+        }
+
+        Ok(DartValue::Map(entries))
+    }
+}
+
+/// Creates the object `Dart.handle` returns for the handle `handle_id`, which
+/// is sent to Dart as a `JsHandle`.
+pub fn new_handle_ref<'s>(
+    scope: &mut v8::HandleScope<'s>,
+    handle_id: i32,
+) -> v8::Local<'s, v8::Object> {
+    let handle_ref = v8::Object::new(scope);
+    let key = handle_id_key(scope);
+    let handle_id = v8::Integer::new(scope, handle_id);
+    handle_ref.set_private(scope, key, handle_id.into());
+    handle_ref
+}
+
+fn handle_id(scope: &mut v8::HandleScope, object: v8::Local<v8::Object>) -> Option<i32> {
+    let key = handle_id_key(scope);
+    let handle_id = object.get_private(scope, key)?;
+    handle_id
+        .is_int32()
+        .then(|| handle_id.int32_value(scope))
+        .flatten()
+}
+
+fn handle_id_key<'s>(scope: &mut v8::HandleScope<'s>) -> v8::Local<'s, v8::Private> {
+    let name = v8::String::new(scope, HANDLE_ID_KEY).unwrap();
+    v8::Private::for_api(scope, Some(name))
+}
+
+fn to_c_string(string: String) -> Result<CString, String> {
+    CString::new(string).map_err(|_| "Cannot send a string containing NUL to Dart".to_string())
+}
+
+fn typed_data_type(value: v8::Local<v8::Value>) -> dart_api::Dart_TypedData_Type {
+    if value.is_int8_array() {
+        dart_api::Dart_TypedData_kInt8
+    } else if value.is_uint8_clamped_array() {
+        dart_api::Dart_TypedData_kUint8Clamped
+    } else if value.is_int16_array() {
+        dart_api::Dart_TypedData_kInt16
+    } else if value.is_uint16_array() {
+        dart_api::Dart_TypedData_kUint16
+    } else if value.is_int32_array() {
+        dart_api::Dart_TypedData_kInt32
+    } else if value.is_uint32_array() {
+        dart_api::Dart_TypedData_kUint32
+    } else if value.is_big_int64_array() {
+        dart_api::Dart_TypedData_kInt64
+    } else if value.is_big_uint64_array() {
+        dart_api::Dart_TypedData_kUint64
+    } else if value.is_float32_array() {
+        dart_api::Dart_TypedData_kFloat32
+    } else if value.is_float64_array() {
+        dart_api::Dart_TypedData_kFloat64
+    } else {
+        // `Uint8Array`, `DataView` and anything else arrive as bytes
+        dart_api::Dart_TypedData_kUint8
+    }
+}
+
+/// Posts `[callback_id, done, value]` to `send_port`.
+pub fn post_value(send_port: i64, callback_id: i32, done: bool, value: DartValue) -> bool {
+    post_list(
+        send_port,
+        vec![
+            DartValue::Int(callback_id.into()),
+            DartValue::Bool(done),
+            value,
+        ],
+    )
+}
+
+/// Posts `[HOST_CALL_TAG, request_id, target, args]`, asking Dart to run the
+/// host function named `target`, or the callback argument with the id
+/// `target`, and answer through `complete_host_call`.
+pub fn post_host_call(send_port: i64, request_id: i32, target: DartValue, args: DartValue) -> bool {
+    let tag = CString::new(HOST_CALL_TAG).unwrap();
+
+    post_list(
+        send_port,
+        vec![
+            DartValue::String(tag),
+            DartValue::Int(request_id.into()),
+            target,
+            args,
+        ],
+    )
+}
+
+/// Posts `[CALLBACK_RELEASE_TAG, callback_id]`, telling Dart that JS no longer
+/// uses the callback argument `callback_id`.
+pub fn post_callback_release(send_port: i64, callback_id: i32) -> bool {
+    let tag = CString::new(CALLBACK_RELEASE_TAG).unwrap();
+
+    post_list(
+        send_port,
+        vec![DartValue::String(tag), DartValue::Int(callback_id.into())],
+    )
+}
+
+/// Posts `[RUNTIME_ERROR_TAG, code, message]`, reporting an error no call or
+/// request could be failed with, e.g. an uncaught error of the event loop.
+pub fn post_runtime_error(send_port: i64, message: &str) -> bool {
+    let tag = CString::new(RUNTIME_ERROR_TAG).unwrap();
+    let message = CString::new(message.replace('\0', "")).unwrap();
+
+    post_list(
+        send_port,
+        vec![
+            DartValue::String(tag),
+            DartValue::Int(ErrorCode::RuntimeError as i64),
+            DartValue::String(message),
+        ],
+    )
+}
+
+fn post_list(send_port: i64, items: Vec<DartValue>) -> bool {
+    let mut message = DartValue::List(items);
+    let mut arena = CObjectArena::default();
+    let message = arena.build(&mut message);
+
+    unsafe { dart_api::Dart_PostCObject(send_port, message) }
+}
+
+/// Owns the nodes of a `Dart_CObject` graph until it has been posted, which
+/// copies it.
+#[derive(Default)]
+struct CObjectArena {
+    objects: Vec<Box<dart_api::Dart_CObject>>,
+    lists: Vec<Vec<*mut dart_api::Dart_CObject>>,
+}
+
+impl CObjectArena {
+    fn object(
+        &mut self,
+        type_: dart_api::Dart_CObject_Type,
+        value: dart_api::_Dart_CObject__bindgen_ty_1,
+    ) -> *mut dart_api::Dart_CObject {
+        let mut object = Box::new(dart_api::Dart_CObject { type_, value });
+        let ptr = &mut *object as *mut dart_api::Dart_CObject;
+        self.objects.push(object);
+        ptr
+    }
+
+    fn list(
+        &mut self,
+        items: &mut Vec<*mut dart_api::Dart_CObject>,
+    ) -> *mut dart_api::Dart_CObject {
+        let mut items = std::mem::take(items);
+        let value = dart_api::_Dart_CObject__bindgen_ty_1 {
+            as_array: dart_api::_Dart_CObject__bindgen_ty_1__bindgen_ty_3 {
+                length: items.len() as isize,
+                values: items.as_mut_ptr(),
+            },
+        };
+        self.lists.push(items);
+        self.object(dart_api::Dart_CObject_kArray, value)
+    }
+
+    // Strings and bytes are borrowed from `value`, which outlives the post
+    fn build(&mut self, value: &mut DartValue) -> *mut dart_api::Dart_CObject {
+        use dart_api::_Dart_CObject__bindgen_ty_1 as Value;
+
+        match value {
+            DartValue::Null => self.object(dart_api::Dart_CObject_kNull, Value { as_int64: 0 }),
+            DartValue::Bool(bool) => {
+                self.object(dart_api::Dart_CObject_kBool, Value { as_bool: *bool })
+            }
+            DartValue::Int(int) => match i32::try_from(*int) {
+                Ok(int) => self.object(dart_api::Dart_CObject_kInt32, Value { as_int32: int }),
+                Err(_) => self.object(dart_api::Dart_CObject_kInt64, Value { as_int64: *int }),
+            },
+            DartValue::Double(double) => {
+                self.object(dart_api::Dart_CObject_kDouble, Value { as_double: *double })
+            }
+            DartValue::String(string) => self.object(
+                dart_api::Dart_CObject_kString,
+                Value {
+                    as_string: string.as_ptr() as *mut _,
+                },
+            ),
+            DartValue::List(items) => {
+                let mut items = items.iter_mut().map(|item| self.build(item)).collect();
+                self.list(&mut items)
+            }
+            DartValue::Map(entries) => {
+                let marker = self.object(
+                    dart_api::Dart_CObject_kCapability,
+                    Value {
+                        as_capability: dart_api::_Dart_CObject__bindgen_ty_1__bindgen_ty_2 {
+                            id: MAP_MARKER,
+                        },
+                    },
+                );
+                let mut items = vec![marker];
+                for (key, item) in entries.iter_mut() {
+                    items.push(self.object(
+                        dart_api::Dart_CObject_kString,
+                        Value {
+                            as_string: key.as_ptr() as *mut _,
+                        },
+                    ));
+                    items.push(self.build(item));
+                }
+                self.list(&mut items)
+            }
+            DartValue::Handle(handle_id) => {
+                let marker = self.object(
+                    dart_api::Dart_CObject_kCapability,
+                    Value {
+                        as_capability: dart_api::_Dart_CObject__bindgen_ty_1__bindgen_ty_2 {
+                            id: HANDLE_MARKER,
+                        },
+                    },
+                );
+                let handle_id = self.object(
+                    dart_api::Dart_CObject_kInt32,
+                    Value {
+                        as_int32: *handle_id,
+                    },
+                );
+                self.list(&mut vec![marker, handle_id])
+            }
+            DartValue::TypedData(type_, bytes) => {
+                let element_size = match *type_ {
+                    dart_api::Dart_TypedData_kInt16 | dart_api::Dart_TypedData_kUint16 => 2,
+                    dart_api::Dart_TypedData_kInt32
+                    | dart_api::Dart_TypedData_kUint32
+                    | dart_api::Dart_TypedData_kFloat32 => 4,
+                    dart_api::Dart_TypedData_kInt64
+                    | dart_api::Dart_TypedData_kUint64
+                    | dart_api::Dart_TypedData_kFloat64 => 8,
+                    _ => 1,
+                };
+                self.object(
+                    dart_api::Dart_CObject_kTypedData,
+                    Value {
+                        as_typed_data: dart_api::_Dart_CObject__bindgen_ty_1__bindgen_ty_4 {
+                            type_: *type_,
+                            length: (bytes.len() / element_size) as isize,
+                            values: bytes.as_mut_ptr(),
+                        },
+                    },
+                )
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use deno_core::JsRuntime;
+
+    use super::*;
+
+    /// Evaluates `source` and converts its completion value.
+    fn convert(source: &'static str) -> Result<DartValue, String> {
+        let mut runtime = JsRuntime::new(Default::default());
+        let value = runtime.execute_script("<test>", source).unwrap();
+        let scope = &mut runtime.handle_scope();
+        let value = v8::Local::new(scope, value);
+        DartValue::from_v8(scope, value)
+    }
+
+    #[test]
+    fn accepts_values_nested_up_to_the_limit() {
+        let value = convert("let v = []; for (let i = 0; i < 64; i++) v = [v]; v");
+        assert!(matches!(value, Ok(DartValue::List(_))));
+    }
+
+    #[test]
+    fn rejects_values_nested_past_the_limit() {
+        let error = convert("let v = []; for (let i = 0; i < 65; i++) v = [v]; v").unwrap_err();
+        assert!(error.contains("nested more than 64 levels"), "{}", error);
+    }
+
+    #[test]
+    fn rejects_cyclic_values() {
+        let error = convert("const o = {}; o.self = o; o").unwrap_err();
+        assert!(error.contains("cyclic"), "{}", error);
+    }
+
+    #[test]
+    fn converts_big_ints_that_fit_in_64_bits() {
+        assert!(matches!(
+            convert("2n ** 63n - 1n"),
+            Ok(DartValue::Int(i64::MAX))
+        ));
+        assert!(matches!(
+            convert("-(2n ** 63n)"),
+            Ok(DartValue::Int(i64::MIN))
+        ));
+    }
+
+    #[test]
+    fn rejects_big_ints_past_64_bits() {
+        for source in ["2n ** 63n", "-(2n ** 63n) - 1n"] {
+            let error = convert(source).unwrap_err();
+            assert_eq!(error, "BigInt does not fit in 64 bits");
+        }
+    }
+
+    #[test]
+    fn keeps_unsafe_integers_as_doubles() {
+        assert!(matches!(
+            convert("Number.MAX_SAFE_INTEGER"),
+            Ok(DartValue::Int(9007199254740991))
+        ));
+        assert!(matches!(
+            convert("Number.MAX_SAFE_INTEGER + 1"),
+            Ok(DartValue::Double(_))
+        ));
+    }
+
+    #[test]
+    fn only_converts_handle_refs_into_handles() {
+        let forged = convert("({ $globeHandle: 42 })");
+        assert!(matches!(forged, Ok(DartValue::Map(entries)) if entries.len() == 1));
+
+        let mut runtime = JsRuntime::new(Default::default());
+        let scope = &mut runtime.handle_scope();
+        let handle_ref = new_handle_ref(scope, 42);
+        assert!(matches!(
+            DartValue::from_v8(scope, handle_ref.into()),
+            Ok(DartValue::Handle(42))
+        ));
+    }
+}
@@ -0,0 +1,78 @@
+//! Byte arguments backed by memory Dart allocated, which V8 reads in place
+//! instead of copying.
+
+use std::{ffi::c_void, ptr::NonNull, sync::Arc};
+
+use crate::dart_api;
+
+/// Memory owned by Dart, shared with every `ArrayBuffer` created over it.
+///
+/// Once the last reference is gone, i.e. V8 collected every `ArrayBuffer`
+/// over it and no queued call or registered module holds it anymore, the
+/// buffer's address is posted to `release_port` so Dart can free it.
+#[derive(Debug)]
+pub struct ExternalBuffer {
+    data: NonNull<u8>,
+    len: usize,
+    release_port: dart_api::Dart_Port,
+}
+
+// Dart doesn't touch the memory until it is released
+unsafe impl Send for ExternalBuffer {}
+unsafe impl Sync for ExternalBuffer {}
+
+impl ExternalBuffer {
+    /// Takes over `len` bytes at `data` until Dart is notified through
+    /// `release_port`.
+    ///
+    /// # Safety
+    ///
+    /// `data` must point to `len` bytes that stay valid and unchanged until
+    /// the release is posted.
+    pub unsafe fn new(
+        data: *mut u8,
+        len: usize,
+        release_port: dart_api::Dart_Port,
+    ) -> Option<Arc<Self>> {
+        Some(Arc::new(ExternalBuffer {
+            data: NonNull::new(data)?,
+            len,
+            release_port,
+        }))
+    }
+
+    /// Creates an `ArrayBuffer` over the memory, which keeps the buffer alive
+    /// until it is garbage collected.
+    pub fn to_array_buffer<'s>(
+        self: &Arc<Self>,
+        scope: &mut v8::HandleScope<'s>,
+    ) -> v8::Local<'s, v8::ArrayBuffer> {
+        let deleter_data = Arc::into_raw(self.clone()) as *mut c_void;
+        let backing_store = unsafe {
+            v8::ArrayBuffer::new_backing_store_from_ptr(
+                self.data.as_ptr() as *mut c_void,
+                self.len,
+                release_backing_store,
+                deleter_data,
+            )
+        };
+
+        v8::ArrayBuffer::with_backing_store(scope, &backing_store.make_shared())
+    }
+}
+
+impl Drop for ExternalBuffer {
+    fn drop(&mut self) {
+        // Fails once Dart closed the port, Dart then frees the memory itself
+        unsafe { dart_api::Dart_PostInteger(self.release_port, self.data.as_ptr() as i64) };
+    }
+}
+
+// Called by V8, possibly on another thread, once a backing store is collected
+unsafe extern "C" fn release_backing_store(
+    _data: *mut c_void,
+    _len: usize,
+    deleter_data: *mut c_void,
+) {
+    drop(Arc::from_raw(deleter_data as *const ExternalBuffer));
+}
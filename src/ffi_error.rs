@@ -0,0 +1,79 @@
+//! Status codes returned across the FFI boundary.
+
+use std::{
+    any::Any,
+    fmt::Display,
+    panic::{self, AssertUnwindSafe},
+};
+
+/// Status returned by the exported functions.
+///
+/// Anything but [`ErrorCode::Ok`] comes with a message written to the
+/// export's `error` out-parameter.
+#[repr(u8)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorCode {
+    Ok = 0,
+    /// A pointer argument was null or not valid UTF-8, or a config or
+    /// permission descriptor could not be decoded.
+    InvalidArgument = 1,
+    /// The Dart API could not be initialized or the runtime failed to start.
+    InitFailed = 2,
+    /// No runtime exists for the handle, it has been disposed or its thread
+    /// stopped.
+    RuntimeNotFound = 3,
+    /// The module failed to load, does not have the expected shape or its
+    /// `init` threw, also when creating an instance of it. Registrations and
+    /// instance creations post their failure to Dart instead of returning it.
+    ModuleRegistrationFailed = 4,
+    /// The module is not registered.
+    ModuleNotFound = 5,
+    /// The JS handle was released or never existed.
+    HandleNotFound = 6,
+    /// The module instance was disposed or never existed.
+    InstanceNotFound = 7,
+    /// Never returned. The code of errors that don't belong to any call,
+    /// which are posted to Dart instead, see `dart_value::post_runtime_error`.
+    RuntimeError = 8,
+    /// A panic was caught before it could unwind into Dart.
+    Panic = 255,
+}
+
+#[derive(Debug)]
+pub struct FfiError {
+    pub code: ErrorCode,
+    pub message: String,
+}
+
+impl FfiError {
+    pub fn new(code: ErrorCode, message: impl Display) -> Self {
+        FfiError {
+            code,
+            message: message.to_string(),
+        }
+    }
+}
+
+/// Runs `f`, turning a panic into an [`ErrorCode::Panic`] error, as unwinding
+/// into Dart would abort the process.
+pub fn catch_panic<T>(f: impl FnOnce() -> Result<T, FfiError>) -> Result<T, FfiError> {
+    panic::catch_unwind(AssertUnwindSafe(f)).unwrap_or_else(|payload| {
+        Err(FfiError::new(
+            ErrorCode::Panic,
+            format!(
+                "Error: Globe Runtime panicked: {}",
+                panic_message(payload.as_ref())
+            ),
+        ))
+    })
+}
+
+fn panic_message(payload: &(dyn Any + Send)) -> &str {
+    if let Some(message) = payload.downcast_ref::<&str>() {
+        message
+    } else if let Some(message) = payload.downcast_ref::<String>() {
+        message
+    } else {
+        "unknown panic"
+    }
+}
@@ -1,8 +1,13 @@
 use std::{
+    borrow::Cow,
+    cell::RefCell,
+    collections::HashMap,
     env,
     path::{Path, PathBuf},
+    rc::Rc,
 };
 
+use base64::Engine;
 use deno_core::{
     error::ModuleLoaderError, futures::FutureExt, url::Url, ModuleLoadResponse, ModuleLoader,
     ModuleSource, ModuleSourceCode, ModuleSpecifier, ModuleType, RequestedModuleType,
@@ -10,8 +15,11 @@ use deno_core::{
 };
 
 use deno_error::JsErrorBox;
+use deno_runtime::deno_permissions::{CheckSpecifierKind, PermissionsContainer};
 use node_resolve::Resolver;
 
+use crate::runtime_config::ModuleResolutionConfig;
+
 #[derive(Debug, thiserror::Error, deno_error::JsError)]
 #[class(inherit)]
 #[error("Failed to load {specifier}")]
@@ -21,8 +29,101 @@ pub struct LoadFailedError {
     #[inherit]
     source: std::io::Error,
 }
+
+/// Source maps of the loaded modules, keyed by module URL.
+///
+/// V8 asks the module loader for them to rewrite stack traces to the original
+/// sources.
+#[derive(Clone)]
+pub struct SourceMaps {
+    maps: Rc<RefCell<HashMap<String, Vec<u8>>>>,
+    permissions: PermissionsContainer,
+}
+
+impl SourceMaps {
+    /// Source map files are only read where `permissions` would let the
+    /// module loader read.
+    pub fn new(permissions: PermissionsContainer) -> Self {
+        SourceMaps {
+            maps: Default::default(),
+            permissions,
+        }
+    }
+
+    /// Remembers the source map of the module `specifier` with `code`, either
+    /// inlined in its `sourceMappingURL` comment, the file that comment points
+    /// to, or a sibling `.map` file. Files outside the allowed ones are
+    /// ignored.
+    pub fn register(&self, specifier: &str, code: &str) {
+        let Ok(url) = Url::parse(specifier) else {
+            return;
+        };
+
+        if let Some(source_map) = find_source_map(&url, code, &self.permissions) {
+            self.maps
+                .borrow_mut()
+                .insert(specifier.to_string(), source_map);
+        }
+    }
+
+    fn get(&self, specifier: &str) -> Option<Vec<u8>> {
+        self.maps.borrow().get(specifier).cloned()
+    }
+}
+
+fn find_source_map(url: &Url, code: &str, permissions: &PermissionsContainer) -> Option<Vec<u8>> {
+    let source_mapping_url = code.lines().rev().find_map(|line| {
+        line.trim()
+            .strip_prefix("//# sourceMappingURL=")
+            .or_else(|| line.trim().strip_prefix("//@ sourceMappingURL="))
+    });
+
+    match source_mapping_url {
+        Some(data_url) if data_url.starts_with("data:") => decode_data_url(data_url),
+        Some(map_url) => read_file_url(&url.join(map_url).ok()?, permissions),
+        None => read_file_url(&Url::parse(&format!("{}.map", url)).ok()?, permissions),
+    }
+}
+
+fn decode_data_url(data_url: &str) -> Option<Vec<u8>> {
+    let (media_type, data) = data_url.strip_prefix("data:")?.split_once(',')?;
+
+    if media_type.ends_with(";base64") {
+        base64::engine::general_purpose::STANDARD.decode(data).ok()
+    } else {
+        Some(data.as_bytes().to_vec())
+    }
+}
+
+/// Reads the file at `url`, checked like the loader checks modules.
+fn read_file_url(url: &Url, permissions: &PermissionsContainer) -> Option<Vec<u8>> {
+    let path = url.to_file_path().ok()?;
+    permissions
+        .check_specifier(url, CheckSpecifierKind::Static)
+        .ok()?;
+    std::fs::read(path).ok()
+}
+
 /// Combined module loader that handles both NPM packages and file system imports
-pub struct NpmFsModuleLoader;
+pub struct NpmFsModuleLoader {
+    resolution: ModuleResolutionConfig,
+    permissions: PermissionsContainer,
+    source_maps: SourceMaps,
+}
+
+impl NpmFsModuleLoader {
+    pub fn new(
+        resolution: ModuleResolutionConfig,
+        permissions: PermissionsContainer,
+        source_maps: SourceMaps,
+    ) -> Self {
+        NpmFsModuleLoader {
+            resolution,
+            permissions,
+            source_maps,
+        }
+    }
+}
 
 impl ModuleLoader for NpmFsModuleLoader {
     fn resolve(
@@ -34,7 +135,7 @@ impl ModuleLoader for NpmFsModuleLoader {
         if is_file_import(specifier) {
             return Ok(deno_core::resolve_import(specifier, referrer)?);
         }
-        resolve_npm(specifier, referrer)
+        resolve_npm(specifier, referrer, &self.resolution)
     }
 
     fn load(
@@ -45,7 +146,14 @@ impl ModuleLoader for NpmFsModuleLoader {
         requested_module_type: RequestedModuleType,
     ) -> ModuleLoadResponse {
         let module_specifier = module_specifier.clone();
+        let permissions = self.permissions.clone();
+        let source_maps = self.source_maps.clone();
         let fut = async move {
+            // Files need `read` access, remote modules `import` access
+            permissions
+                .check_specifier(&module_specifier, CheckSpecifierKind::Static)
+                .map_err(JsErrorBox::from_err)?;
+
             let path = module_specifier.to_file_path().map_err(|_| {
                 JsErrorBox::generic(format!(
                     "Provided module specifier \"{module_specifier}\" is not a file URL."
@@ -66,6 +174,10 @@ impl ModuleLoader for NpmFsModuleLoader {
                     source,
                 })
             })?;
+            if module_type == ModuleType::JavaScript {
+                source_maps.register(module_specifier.as_str(), &String::from_utf8_lossy(&code));
+            }
+
             let module = ModuleSource::new(
                 module_type,
                 ModuleSourceCode::Bytes(code.into_boxed_slice().into()),
@@ -78,6 +190,10 @@ impl ModuleLoader for NpmFsModuleLoader {
 
         ModuleLoadResponse::Async(fut)
     }
+
+    fn get_source_map(&self, file_name: &str) -> Option<Cow<[u8]>> {
+        self.source_maps.get(file_name).map(Cow::Owned)
+    }
 }
 
 fn is_file_import(specifier: &str) -> bool {
@@ -104,7 +220,11 @@ fn determine_module_type(path: &Path, requested_module_type: &RequestedModuleTyp
     }
 }
 
-fn resolve_npm(specifier: &str, referrer: &str) -> Result<ModuleSpecifier, ModuleLoaderError> {
+fn resolve_npm(
+    specifier: &str,
+    referrer: &str,
+    resolution: &ModuleResolutionConfig,
+) -> Result<ModuleSpecifier, ModuleLoaderError> {
     let referrer_url =
         Url::parse(referrer).map_err(|_| JsErrorBox::generic("Invalid referrer URL"))?;
     let referrer_path = referrer_url
@@ -117,8 +237,8 @@ fn resolve_npm(specifier: &str, referrer: &str) -> Result<ModuleSpecifier, Modul
         .unwrap_or_else(|| env::current_dir().expect("Failed to get current directory"));
 
     let resolved_path = Resolver::default()
-        .with_extensions([".mjs"])
-        .with_main_fields(["module"])
+        .with_extensions(resolution.extensions.iter().map(String::as_str))
+        .with_main_fields(resolution.main_fields.iter().map(String::as_str))
         .with_basedir(current_dir)
         .resolve(specifier);
 
@@ -1,64 +1,221 @@
 use std::{
+    cell::{Cell, RefCell},
     ffi::{c_char, c_void, CStr},
+    fmt::Display,
+    future::Future,
+    ptr::NonNull,
     rc::Rc,
-    sync::Arc,
+    sync::{Arc, Mutex},
 };
 
 use deno_runtime::{
     deno_console,
-    deno_core::{extension, JsRuntime, RuntimeOptions},
+    deno_core::{
+        error::CoreError, extension, op2, v8_set_flags, JsRuntime, ModuleSpecifier, OpState,
+        RuntimeOptions,
+    },
     deno_fetch, deno_net,
-    deno_permissions::PermissionsContainer,
+    deno_permissions::{
+        PermissionCheckError, Permissions, PermissionsContainer, PermissionsOptions,
+    },
     deno_telemetry, deno_url, deno_web, deno_webidl,
     ops::{self},
     permissions::RuntimePermissionDescriptorParser,
 };
 
-use crate::{dart_runtime::dart_runtime, js_resolver::NpmFsModuleLoader};
+use crate::{
+    dart_callback::DartCallback,
+    dart_message::{DartMessage, JsError},
+    dart_runtime::dart_runtime,
+    external_buffer::ExternalBuffer,
+    js_resolver::{NpmFsModuleLoader, SourceMaps},
+    permissions::{module_context_key, ScopedPermissions},
+    runtime_config::{PermissionsConfig, RuntimeConfig},
+};
 
-#[derive(Debug)]
-pub struct JsFunctionArgs {
-    pub args: *const *const c_void,
-    pub type_ids: *const i32,
-    pub sizes: *const isize,
-    pub count: i32,
+/// The V8 flags of the process, set by the first runtime created.
+static V8_FLAGS: Mutex<Option<Vec<String>>> = Mutex::new(None);
+
+/// Passes `flags` to V8 if no runtime was created yet. V8 only reads flags
+/// before it is initialized by the first `JsRuntime`, so later runtimes must
+/// ask for the same flags. Flags with one V8 doesn't recognize are not
+/// recorded, so a runtime can be created again with fixed flags.
+pub fn set_v8_flags(flags: &[String]) -> Result<(), String> {
+    let mut process_flags = V8_FLAGS.lock().unwrap();
+
+    if let Some(process_flags) = process_flags.as_ref() {
+        if process_flags.as_slice() != flags {
+            return Err(format!(
+                "Error: V8 flags {:?} differ from the flags {:?} of the first runtime, \
+                 which apply to the whole process",
+                flags, process_flags
+            ));
+        }
+        return Ok(());
+    }
+
+    if !flags.is_empty() {
+        // The first argument stands in for the program name and is skipped
+        let args = std::iter::once(String::new())
+            .chain(flags.iter().cloned())
+            .collect();
+        let unrecognized: Vec<_> = v8_set_flags(args).into_iter().skip(1).collect();
+        if !unrecognized.is_empty() {
+            return Err(format!(
+                "Error: Unrecognized V8 flags: {}",
+                unrecognized.join(" ")
+            ));
+        }
+    }
+
+    *process_flags = Some(flags.to_vec());
+    Ok(())
 }
 
-pub fn get_runtime(send_port: i64) -> JsRuntime {
+/// Builds the permissions described by `config`, granting everything when
+/// there is none.
+pub fn create_permissions(
+    config: Option<&PermissionsConfig>,
+) -> Result<PermissionsContainer, String> {
     let permission_desc_parser = Arc::new(RuntimePermissionDescriptorParser::new(
         sys_traits::impls::RealSys,
     ));
-    let permissions = PermissionsContainer::allow_all(permission_desc_parser);
 
-    let extensions = vec![
-        deno_permissions_worker::init_ops_and_esm(permissions, false),
+    let Some(config) = config else {
+        return Ok(PermissionsContainer::allow_all(permission_desc_parser));
+    };
+
+    let options = PermissionsOptions {
+        allow_net: allowlist(&config.net),
+        allow_read: allowlist(&config.read),
+        allow_env: allowlist(&config.env),
+        allow_import: allowlist(&config.import),
+        prompt: false,
+        ..Default::default()
+    };
+
+    let permissions = Permissions::from_options(permission_desc_parser.as_ref(), &options)
+        .map_err(|e| format!("Error: Invalid permissions: {}", e))?;
+
+    Ok(PermissionsContainer::new(
+        permission_desc_parser,
+        permissions,
+    ))
+}
+
+/// Permissions denying everything, without prompting.
+pub fn deny_all_permissions() -> PermissionsContainer {
+    let permission_desc_parser = Arc::new(RuntimePermissionDescriptorParser::new(
+        sys_traits::impls::RealSys,
+    ));
+    PermissionsContainer::new(permission_desc_parser, Permissions::none_without_prompt())
+}
+
+/// Maps an allowlist from the config to `deno_permissions`, where `None`
+/// denies everything and an empty list allows everything.
+fn allowlist(entries: &[String]) -> Option<Vec<String>> {
+    if entries.is_empty() {
+        None
+    } else if entries.iter().any(|entry| entry == "*") {
+        Some(Vec::new())
+    } else {
+        Some(entries.to_vec())
+    }
+}
+
+pub fn get_runtime(
+    send_port: i64,
+    config: &RuntimeConfig,
+    permissions: PermissionsContainer,
+) -> JsRuntime {
+    let source_maps = SourceMaps::new(permissions.clone());
+    let module_loader = NpmFsModuleLoader::new(
+        config.module_resolution.clone(),
+        permissions.clone(),
+        source_maps.clone(),
+    );
+
+    let scoped_permissions = ScopedPermissions::new(permissions.clone(), deny_all_permissions());
+
+    let mut extensions = vec![
+        deno_permissions_worker::init_ops_and_esm(permissions, scoped_permissions, false),
         deno_telemetry::deno_telemetry::init_ops_and_esm(),
         deno_webidl::deno_webidl::init_ops_and_esm(),
         deno_console::deno_console::init_ops_and_esm(),
         deno_url::deno_url::init_ops_and_esm(),
-        deno_web::deno_web::init_ops_and_esm::<PermissionsContainer>(
+        deno_web::deno_web::init_ops_and_esm::<ScopedPermissions>(
             Default::default(),
             Default::default(),
         ),
-        deno_net::deno_net::init_ops_and_esm::<PermissionsContainer>(None, None),
-        deno_fetch::deno_fetch::init_ops_and_esm::<PermissionsContainer>(Default::default()),
+    ];
+
+    if config.extensions.net {
+        extensions.push(deno_net::deno_net::init_ops_and_esm::<ScopedPermissions>(
+            None, None,
+        ));
+    }
+
+    extensions.extend([
+        deno_fetch::deno_fetch::init_ops_and_esm::<ScopedPermissions>(Default::default()),
         js_runtime::init_ops_and_esm(),
         bufbuild::init_ops_and_esm(),
         js_msg_packr::init_ops_and_esm(),
-        dart_runtime::init_ops_and_esm::<i64>(send_port),
-    ];
+        dart_runtime::init_ops_and_esm::<i64>(send_port, config.stream_window),
+    ]);
 
     let platform = v8::new_default_platform(0, false).make_shared();
 
-    JsRuntime::new(RuntimeOptions {
-        module_loader: Some(Rc::new(NpmFsModuleLoader)),
+    let create_params = config.max_heap_size().map(|max_heap_size| {
+        v8::CreateParams::default().heap_limits(config.initial_heap_size(), max_heap_size)
+    });
+
+    let mut runtime = JsRuntime::new(RuntimeOptions {
+        module_loader: Some(Rc::new(module_loader)),
         extension_transpiler: Some(Rc::new(|specifier, source| {
             deno_runtime::transpile::maybe_transpile_source(specifier, source)
         })),
         extensions,
         v8_platform: Some(platform),
+        create_params,
         ..Default::default()
-    })
+    });
+
+    let isolate = NonNull::from(&mut **runtime.v8_isolate());
+    {
+        let op_state = runtime.op_state();
+        let mut op_state = op_state.borrow_mut();
+        op_state.borrow_mut::<ScopedPermissions>().attach(isolate);
+        op_state.put(source_maps);
+    }
+
+    runtime
+}
+
+/// Terminates execution when the heap gets close to its limit and sets
+/// `out_of_memory`, instead of letting V8 abort the whole process.
+pub fn add_heap_limit_guard(runtime: &mut JsRuntime, out_of_memory: Rc<Cell<bool>>) {
+    let isolate_handle = runtime.v8_isolate().thread_safe_handle();
+
+    runtime.add_near_heap_limit_callback(move |current_limit, _initial_limit| {
+        out_of_memory.set(true);
+        isolate_handle.terminate_execution();
+
+        // Give the terminated script room to unwind
+        current_limit * 2
+    });
+}
+
+/// Undoes the headroom granted by [`add_heap_limit_guard`] and collects as
+/// much garbage as possible, so the runtime can carry on after running out of
+/// memory.
+pub fn reset_heap_limit(
+    runtime: &mut JsRuntime,
+    config: &RuntimeConfig,
+    out_of_memory: Rc<Cell<bool>>,
+) {
+    runtime.remove_near_heap_limit_callback(config.max_heap_size().unwrap_or(0));
+    runtime.v8_isolate().low_memory_notification();
+    add_heap_limit_guard(runtime, out_of_memory);
 }
 
 pub fn get_js_function(
@@ -67,7 +224,17 @@ pub fn get_js_function(
     function: &str,
 ) -> Result<(v8::Global<v8::Function>, v8::Global<v8::Value>), String> {
     let module_obj = get_js_module(scope, module)?;
+    get_object_function(scope, module_obj, module, function)
+}
 
+/// Looks up `function` and the `state` of a module object, or of an instance
+/// inheriting the functions of its module.
+fn get_object_function(
+    scope: &mut v8::HandleScope,
+    module_obj: v8::Local<v8::Object>,
+    module: &str,
+    function: &str,
+) -> Result<(v8::Global<v8::Function>, v8::Global<v8::Value>), String> {
     let state_key = v8::String::new(scope, "state").unwrap();
     let state_value = module_obj
         .get(scope, state_key.into())
@@ -117,8 +284,765 @@ pub fn get_js_module<'a>(
     }
 }
 
+/// Loads `source` as an ES module and registers its default export as
+/// `module_name` on `globalThis`. With `permissions`, code of the module runs
+/// restricted to them on top of the runtime's.
+///
+/// A source map of `source`, inline or next to `file_path`, is used to report
+/// errors against the original sources.
+///
+/// The module graph is evaluated to completion, so top-level await has
+/// settled before the default export is read. An async `init` is awaited as
+/// well, and the module is registered with the value its promise resolves
+/// with as state. Both are waited for with `driver`, the caller bounds how
+/// long that may take.
+pub async fn register_module(
+    runtime: &mut JsRuntime,
+    driver: &mut impl LoadDriver,
+    module_name: &str,
+    file_path: String,
+    source: String,
+    init_args: &[FFIArg],
+    permissions: Option<&PermissionsConfig>,
+) -> Result<(), LoadError> {
+    let module_scope = permissions
+        .map(|p| create_permissions(Some(p)))
+        .transpose()?;
+    let scope_rollback = ModuleScopeRollback::set(runtime, module_name, module_scope);
+    runtime
+        .op_state()
+        .borrow()
+        .borrow::<SourceMaps>()
+        .register(&file_path, &source);
+
+    let specifier = ModuleSpecifier::parse(&file_path)
+        .map_err(|e| format!("Error loading module: {}, {}", module_name, e))?;
+    let module_id = runtime
+        .load_side_es_module_from_code(&specifier, source)
+        .await
+        .map_err(|e| {
+            LoadError::from(e).context(format_args!("Error loading module: {}, ", module_name))
+        })?;
+
+    // The module body runs in the module's async context, which its
+    // top-level await continuations inherit, so it is held to the module's
+    // permissions like its functions. Top-level await only settles while the
+    // event loop runs.
+    let evaluation = {
+        let previous_context = {
+            let scope = &mut runtime.handle_scope();
+            let module_name = v8::String::new(scope, module_name)
+                .ok_or_else(|| format!("Error: Invalid module name '{}'", module_name))?;
+            let previous_context = enter_module_context(scope, module_name);
+            v8::Global::new(scope, previous_context)
+        };
+        let evaluation = runtime.mod_evaluate(module_id);
+        let scope = &mut runtime.handle_scope();
+        let previous_context = v8::Local::new(scope, previous_context);
+        scope.set_continuation_preserved_embedder_data(previous_context);
+        evaluation
+    };
+    driver
+        .drive(runtime, Box::pin(evaluation))
+        .await
+        .map_err(|e| e.context(format_args!("Error evaluating module: {}, ", module_name)))?;
+
+    let module_object = runtime
+        .get_module_namespace(module_id)
+        .map_err(|e| format!("Error loading module: {}, {}", module_name, e))?;
+
+    let (module_object, state) = {
+        let scope = &mut runtime.handle_scope();
+
+        let local = v8::Local::new(scope, module_object);
+        let obj = local
+            .to_object(scope)
+            .ok_or_else(|| format!("Error loading module: {}", module_name))?;
+        let key = v8::String::new(scope, "default").unwrap();
+
+        // Check if the module exports a default function
+        let value = obj
+            .get(scope, key.into())
+            .filter(|value| value.is_object())
+            .ok_or_else(|| "Module does not export a default function".to_string())?;
+
+        let module_name = v8::String::new(scope, module_name)
+            .ok_or_else(|| format!("Error: Invalid module name '{}'", module_name))?;
+        let default_object = value.to_object(scope).unwrap();
+
+        // only run init function if it exists
+        let init_key = v8::String::new(scope, "init").unwrap();
+        let init_function = default_object
+            .get(scope, init_key.into())
+            .and_then(|value| v8::Local::<v8::Function>::try_from(value).ok());
+
+        let dispose_key = v8::String::new(scope, "dispose").unwrap();
+        let dispose_function = default_object
+            .get(scope, dispose_key.into())
+            .and_then(|value| v8::Local::<v8::Function>::try_from(value).ok());
+
+        let functions_key = v8::String::new(scope, "functions").unwrap();
+        let functions_object = default_object
+            .get(scope, functions_key.into())
+            .and_then(|value| v8::Local::<v8::Object>::try_from(value).ok())
+            .ok_or_else(|| "Module does not export a `functions` object".to_string())?;
+
+        let module_object =
+            create_module_object(scope, init_function, dispose_function, functions_object)?;
+        let state = run_module_init(scope, module_name, init_function, init_args)?;
+
+        (
+            v8::Global::new(scope, module_object),
+            v8::Global::new(scope, state),
+        )
+    };
+
+    let state = settle_module_state(runtime, driver, state).await?;
+
+    // Put module on globalThis
+    let scope = &mut runtime.handle_scope();
+    let module_object = v8::Local::new(scope, module_object);
+    let state = v8::Local::new(scope, state);
+    let state_key = v8::String::new(scope, "state").unwrap();
+    module_object.set(scope, state_key.into(), state);
+
+    let module_name = v8::String::new(scope, module_name).unwrap();
+    let global = scope.get_current_context().global(scope);
+    global.set(scope, module_name.into(), module_object.into());
+
+    scope_rollback.commit();
+    Ok(())
+}
+
+/// Why registering a module or creating an instance failed.
+///
+/// Errors thrown by module code keep their structured form, so Dart reports
+/// them like the errors of calls.
+#[derive(Debug)]
+pub struct LoadError {
+    message: String,
+    js_error: Option<JsError>,
+}
+
+impl LoadError {
+    /// Prefixes the message with what failed.
+    fn context(self, context: impl Display) -> Self {
+        LoadError {
+            message: format!("{}{}", context, self.message),
+            ..self
+        }
+    }
+
+    /// The final message reporting the failure to Dart.
+    pub fn into_dart_message(self) -> DartMessage {
+        DartMessage {
+            js_error: self.js_error,
+            ..DartMessage::error(self.message)
+        }
+    }
+}
+
+impl From<String> for LoadError {
+    fn from(message: String) -> Self {
+        LoadError {
+            message,
+            js_error: None,
+        }
+    }
+}
+
+impl From<CoreError> for LoadError {
+    fn from(error: CoreError) -> Self {
+        let js_error = match &error {
+            CoreError::Js(js_error) => Some(JsError::from_core(js_error)),
+            _ => None,
+        };
+        LoadError {
+            message: error.to_string(),
+            js_error,
+        }
+    }
+}
+
+impl Display for LoadError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+/// Waits for the parts of a module load that run with the event loop:
+/// top-level await and async `init` functions.
+pub trait LoadDriver {
+    /// Runs the event loop until `load` completes. Errors of the event loop
+    /// fail the load.
+    async fn drive<T, E: Into<LoadError>>(
+        &mut self,
+        runtime: &mut JsRuntime,
+        load: impl Future<Output = Result<T, E>> + Unpin,
+    ) -> Result<T, LoadError>;
+}
+
+/// Sets the permission scope of a module being registered, and restores the
+/// scope it had before when the registration fails or is abandoned, e.g.
+/// because it timed out.
+struct ModuleScopeRollback {
+    op_state: Rc<RefCell<OpState>>,
+    module_name: String,
+    previous: Option<Option<PermissionsContainer>>,
+}
+
+impl ModuleScopeRollback {
+    fn set(
+        runtime: &mut JsRuntime,
+        module_name: &str,
+        scope: Option<PermissionsContainer>,
+    ) -> Self {
+        let op_state = runtime.op_state();
+        let previous = op_state
+            .borrow_mut()
+            .borrow_mut::<ScopedPermissions>()
+            .set_module_scope(module_name, scope);
+
+        ModuleScopeRollback {
+            op_state,
+            module_name: module_name.to_string(),
+            previous: Some(previous),
+        }
+    }
+
+    /// Keeps the new scope.
+    fn commit(mut self) {
+        self.previous = None;
+    }
+}
+
+impl Drop for ModuleScopeRollback {
+    fn drop(&mut self) {
+        if let Some(previous) = self.previous.take() {
+            self.op_state
+                .borrow_mut()
+                .borrow_mut::<ScopedPermissions>()
+                .set_module_scope(&self.module_name, previous);
+        }
+    }
+}
+
+/// Creates an instance of the registered `module_name` under `instance_id`,
+/// with its own state returned by the module's `init` for `init_args`.
+///
+/// Like for [`register_module`], an async `init` is awaited first.
+pub async fn create_module_instance(
+    runtime: &mut JsRuntime,
+    driver: &mut impl LoadDriver,
+    module_name: &str,
+    instance_id: i32,
+    init_args: &[FFIArg],
+) -> Result<(), LoadError> {
+    let state = {
+        let scope = &mut runtime.handle_scope();
+
+        let module_obj = get_js_module(scope, module_name)?;
+        let module_name_value = v8::String::new(scope, module_name)
+            .ok_or_else(|| format!("Error: Invalid module name '{}'", module_name))?;
+        let init_function = get_module_hook(scope, module_obj, INIT_HOOK);
+        let state = run_module_init(scope, module_name_value, init_function, init_args)?;
+        v8::Global::new(scope, state)
+    };
+
+    let state = settle_module_state(runtime, driver, state).await?;
+
+    let scope = &mut runtime.handle_scope();
+    let module_obj = get_js_module(scope, module_name)?;
+    let module_name_value = v8::String::new(scope, module_name).unwrap();
+
+    // Functions are inherited from the module, only the state is the
+    // instance's own
+    let instance = v8::Object::new(scope);
+    instance.set_prototype(scope, module_obj.into());
+    let state = v8::Local::new(scope, state);
+    let state_key = v8::String::new(scope, "state").unwrap();
+    instance.set(scope, state_key.into(), state);
+
+    let add_instance = get_runtime_function(scope, "add_instance")?;
+    let receiver = v8::undefined(scope).into();
+    let args = [
+        v8::Integer::new(scope, instance_id).into(),
+        module_name_value.into(),
+        instance.into(),
+    ];
+    add_instance
+        .call(scope, receiver, &args)
+        .ok_or_else(|| format!("Error: Failed to add instance {}", instance_id))?;
+
+    Ok(())
+}
+
+/// Runs `init_function` with `init_args`, returning the module state.
+/// Modules without `init` have an `undefined` state.
+fn run_module_init<'s>(
+    scope: &mut v8::HandleScope<'s>,
+    module_name: v8::Local<'s, v8::String>,
+    init_function: Option<v8::Local<'s, v8::Function>>,
+    init_args: &[FFIArg],
+) -> Result<v8::Local<'s, v8::Value>, LoadError> {
+    let Some(init_function) = init_function else {
+        return Ok(v8::undefined(scope).into());
+    };
+
+    let v8_args = ffi_args_to_v8_args(scope, init_args)?;
+    run_module_hook(scope, module_name, init_function, &v8_args)
+        .map_err(|e| e.context("Error: Module `init` threw: "))
+}
+
+/// Waits for the promise an async `init` returned, running the event loop,
+/// and returns the value it resolved with. Other states are returned as is.
+async fn settle_module_state(
+    runtime: &mut JsRuntime,
+    driver: &mut impl LoadDriver,
+    state: v8::Global<v8::Value>,
+) -> Result<v8::Global<v8::Value>, LoadError> {
+    let is_promise = {
+        let scope = &mut runtime.handle_scope();
+        v8::Local::new(scope, &state).is_promise()
+    };
+    if !is_promise {
+        return Ok(state);
+    }
+
+    let resolved = runtime.resolve(state.clone());
+    let result = driver.drive(runtime, Box::pin(resolved)).await;
+    result.map_err(|e| {
+        // The value `init` rejected with is reported like a thrown one
+        let scope = &mut runtime.handle_scope();
+        let state = v8::Local::new(scope, &state);
+        let reason = v8::Local::<v8::Promise>::try_from(state)
+            .ok()
+            .filter(|promise| promise.state() == v8::PromiseState::Rejected)
+            .map(|promise| promise.result(scope));
+        let js_error = reason.and_then(|reason| serialize_js_error(scope, reason));
+
+        LoadError {
+            js_error: js_error.or(e.js_error),
+            ..e
+        }
+        .context("Error: Module `init` rejected: ")
+    })
+}
+
+/// Forgets the instance `instance_id`, running the `dispose` hook of its
+/// module with the instance's state.
+///
+/// The instance is forgotten even when the hook throws.
+pub fn dispose_module_instance(runtime: &mut JsRuntime, instance_id: i32) -> Result<(), String> {
+    let scope = &mut runtime.handle_scope();
+
+    let remove_instance = get_runtime_function(scope, "remove_instance")?;
+    let receiver = v8::undefined(scope).into();
+    let args = [v8::Integer::new(scope, instance_id).into()];
+    let Some((module_name, instance)) = remove_instance
+        .call(scope, receiver, &args)
+        .and_then(|entry| read_instance_entry(scope, entry))
+    else {
+        return Ok(());
+    };
+
+    // Hooks are kept on the module the instance inherits from
+    let dispose_function = instance
+        .get_prototype(scope)
+        .and_then(|module| v8::Local::<v8::Object>::try_from(module).ok())
+        .and_then(|module| get_module_hook(scope, module, DISPOSE_HOOK));
+    if let Some(dispose_function) = dispose_function {
+        let state_key = v8::String::new(scope, "state").unwrap();
+        let state = instance
+            .get(scope, state_key.into())
+            .unwrap_or_else(|| v8::undefined(scope).into());
+        run_module_hook(scope, module_name, dispose_function, &[state])
+            .map_err(|e| format!("Error: Module `dispose` threw: {}", e))?;
+    }
+
+    Ok(())
+}
+
+/// Looks up `function` of the instance `instance_id`, returning the instance's
+/// module, the function and the instance's state.
+fn get_js_instance_function(
+    scope: &mut v8::HandleScope,
+    instance_id: i32,
+    function: &str,
+) -> Result<(String, v8::Global<v8::Function>, v8::Global<v8::Value>), String> {
+    let get_instance = get_runtime_function(scope, "get_instance")?;
+    let receiver = v8::undefined(scope).into();
+    let args = [v8::Integer::new(scope, instance_id).into()];
+    let (module_name, instance) = get_instance
+        .call(scope, receiver, &args)
+        .and_then(|entry| read_instance_entry(scope, entry))
+        .ok_or_else(|| format!("Error: Module instance {} not found", instance_id))?;
+
+    let module_name = module_name.to_rust_string_lossy(scope);
+    let (function, state) = get_object_function(scope, instance, &module_name, function)?;
+    Ok((module_name, function, state))
+}
+
+/// Reads the `[moduleName, instance]` entries of `GlobeRuntime`'s instance
+/// registry.
+fn read_instance_entry<'s>(
+    scope: &mut v8::HandleScope<'s>,
+    entry: v8::Local<'s, v8::Value>,
+) -> Option<(v8::Local<'s, v8::String>, v8::Local<'s, v8::Object>)> {
+    let entry = v8::Local::<v8::Array>::try_from(entry).ok()?;
+    let module_name = entry.get_index(scope, 0)?;
+    let instance = entry.get_index(scope, 1)?;
+
+    Some((
+        v8::Local::<v8::String>::try_from(module_name).ok()?,
+        v8::Local::<v8::Object>::try_from(instance).ok()?,
+    ))
+}
+
+/// What a call started by [`call_js_function`] runs.
+#[derive(Debug)]
+pub enum CallTarget {
+    /// A function of a registered module.
+    Function {
+        module_name: String,
+        function_name: String,
+    },
+    /// A function of a module instance created by [`create_module_instance`].
+    InstanceFunction {
+        instance_id: i32,
+        function_name: String,
+    },
+    /// A method of an object handed to Dart with `Dart.handle`.
+    Method { handle_id: i32, method_name: String },
+}
+
+/// Starts `target` through `GlobeRuntime.invoke`, in the async context of the
+/// module the target belongs to.
+///
+/// Module functions receive the module state, `args`, `message_identifier`
+/// and an `AbortSignal` that is aborted by [`cancel_js_call`], methods only
+/// receive `args`. The returned future resolves once the promise returned by
+/// the function settles; it only makes progress while the event loop is
+/// being polled.
+pub fn call_js_function(
+    runtime: &mut JsRuntime,
+    target: &CallTarget,
+    message_identifier: i32,
+    args: &[FFIArg],
+) -> Result<impl Future<Output = Result<v8::Global<v8::Value>, impl Display>>, String> {
+    let (invoke_function, v8_args, previous_context) = {
+        let scope = &mut runtime.handle_scope();
+
+        let (module, js_function, module_state) = match target {
+            CallTarget::Function {
+                module_name,
+                function_name,
+            } => {
+                let (js_function, module_state) =
+                    get_js_function(scope, module_name, function_name)?;
+                (module_name.clone(), js_function, module_state)
+            }
+            CallTarget::InstanceFunction {
+                instance_id,
+                function_name,
+            } => get_js_instance_function(scope, *instance_id, function_name)?,
+            CallTarget::Method {
+                handle_id,
+                method_name,
+            } => get_js_handle_method(scope, *handle_id, method_name)?,
+        };
+        let module = module.as_str();
+        let invoke_function = get_runtime_function(scope, "invoke")?;
+
+        let msg_id_value: v8::Local<v8::Value> = v8::Integer::new(scope, message_identifier).into();
+        let module_value = v8::String::new(scope, module)
+            .ok_or_else(|| format!("Error: Invalid module name '{}'", module))?;
+        let function_value: v8::Local<v8::Value> = v8::Local::new(scope, js_function).into();
+
+        let mut v8_args = vec![
+            v8::Global::new(scope, msg_id_value),
+            v8::Global::new(scope, function_value),
+            module_state,
+        ];
+
+        for arg in ffi_args_to_v8_args(scope, args)? {
+            v8_args.push(v8::Global::new(scope, arg));
+        }
+
+        let previous_context = enter_module_context(scope, module_value);
+        (
+            v8::Global::new(scope, invoke_function),
+            v8_args,
+            v8::Global::new(scope, previous_context),
+        )
+    };
+
+    // The function runs up to its first `await` here, work it starts keeps
+    // the module's context
+    let call = runtime.call_with_args(&invoke_function, &v8_args);
+    let scope = &mut runtime.handle_scope();
+    let previous_context = v8::Local::new(scope, previous_context);
+    scope.set_continuation_preserved_embedder_data(previous_context);
+
+    Ok(call)
+}
+
+/// Looks up `method` of the object behind `handle_id`, returning the module
+/// that created the handle, the method adapted to the module function
+/// signature and the object.
+fn get_js_handle_method(
+    scope: &mut v8::HandleScope,
+    handle_id: i32,
+    method: &str,
+) -> Result<(String, v8::Global<v8::Function>, v8::Global<v8::Value>), String> {
+    let not_found = || {
+        format!(
+            "Error: JS handle {} was released or has no method `{}`",
+            handle_id, method
+        )
+    };
+
+    let handle_method = get_runtime_function(scope, "handle_method")?;
+    let receiver = v8::undefined(scope).into();
+    let args = [
+        v8::Integer::new(scope, handle_id).into(),
+        v8::String::new(scope, method).ok_or_else(not_found)?.into(),
+    ];
+    let result = handle_method
+        .call(scope, receiver, &args)
+        .and_then(|value| v8::Local::<v8::Array>::try_from(value).ok())
+        .ok_or_else(not_found)?;
+
+    // `[moduleName, method, object]`
+    let module = result
+        .get_index(scope, 0)
+        .map(|value| value.to_rust_string_lossy(scope))
+        .ok_or_else(not_found)?;
+    let function = result
+        .get_index(scope, 1)
+        .and_then(|value| v8::Local::<v8::Function>::try_from(value).ok())
+        .ok_or_else(not_found)?;
+    let object = result.get_index(scope, 2).ok_or_else(not_found)?;
+
+    Ok((
+        module,
+        v8::Global::new(scope, function),
+        v8::Global::new(scope, object),
+    ))
+}
+
+/// Forgets the object behind `handle_id`, returning whether it existed.
+pub fn release_js_handle(runtime: &mut JsRuntime, handle_id: i32) -> Result<bool, String> {
+    call_runtime_function_with(runtime, "release_handle", handle_id, vec![], |_, result| {
+        result.is_true()
+    })
+}
+
+/// Whether the object behind `handle_id` has not been released.
+pub fn has_js_handle(runtime: &mut JsRuntime, handle_id: i32) -> Result<bool, String> {
+    call_runtime_function_with(runtime, "has_handle", handle_id, vec![], |_, result| {
+        result.is_true()
+    })
+}
+
+/// Number of objects handed to Dart with `Dart.handle` that have not been
+/// released.
+pub fn count_js_handles(runtime: &mut JsRuntime) -> Result<u32, String> {
+    call_runtime_function_with(runtime, "handle_count", 0, vec![], |scope, result| {
+        result.uint32_value(scope).unwrap_or(0)
+    })
+}
+
+/// Aborts the signal of the call identified by `message_identifier` and sends
+/// its final, cancelled message to Dart. Unknown or finished calls are ignored.
+pub fn cancel_js_call(runtime: &mut JsRuntime, message_identifier: i32) -> Result<(), String> {
+    call_runtime_function(runtime, "cancel", message_identifier, vec![])
+}
+
+/// Returns `count` credits to the stream of the call identified by
+/// `message_identifier`, letting it send that many more chunks.
+pub fn acknowledge_js_chunks(
+    runtime: &mut JsRuntime,
+    message_identifier: i32,
+    count: u32,
+) -> Result<(), String> {
+    call_runtime_function(
+        runtime,
+        "acknowledge",
+        message_identifier,
+        vec![RuntimeFunctionArg::Number(count as f64)],
+    )
+}
+
+/// Enqueues `chunk` in the `ReadableStream` passed to JS for `stream_id`.
+/// Chunks of unknown or cancelled streams are dropped.
+pub fn push_js_stream_chunk(
+    runtime: &mut JsRuntime,
+    stream_id: i32,
+    chunk: Vec<u8>,
+) -> Result<(), String> {
+    call_runtime_function(
+        runtime,
+        "push_stream_chunk",
+        stream_id,
+        vec![RuntimeFunctionArg::Bytes(chunk)],
+    )
+}
+
+/// Resolves the `Dart.invoke` promise of `request_id` with the msgpack-encoded
+/// `result`, or rejects it with the error message. Unknown requests are
+/// ignored.
+pub fn complete_js_host_call(
+    runtime: &mut JsRuntime,
+    request_id: i32,
+    result: Result<Vec<u8>, String>,
+) -> Result<(), String> {
+    match result {
+        Ok(payload) => call_runtime_function(
+            runtime,
+            "complete_host_call",
+            request_id,
+            vec![RuntimeFunctionArg::Bytes(payload)],
+        ),
+        Err(message) => call_runtime_function(
+            runtime,
+            "fail_host_call",
+            request_id,
+            vec![RuntimeFunctionArg::String(&message)],
+        ),
+    }
+}
+
+/// Closes the `ReadableStream` passed to JS for `stream_id`, or errors it with
+/// `message`.
+pub fn close_js_stream(
+    runtime: &mut JsRuntime,
+    stream_id: i32,
+    message: Option<&str>,
+) -> Result<(), String> {
+    match message {
+        Some(message) => call_runtime_function(
+            runtime,
+            "error_stream",
+            stream_id,
+            vec![RuntimeFunctionArg::String(message)],
+        ),
+        None => call_runtime_function(runtime, "close_stream", stream_id, vec![]),
+    }
+}
+
+/// Aborts the `AbortSignal` of a call that failed outside of JavaScript's
+/// control with a `DOMException` named `name`. Reporting the failure to Dart
+/// is up to the caller.
+///
+/// Returns whether the call was still pending, `false` once it sent its final
+/// message or was cancelled.
+pub fn abort_js_call(
+    runtime: &mut JsRuntime,
+    message_identifier: i32,
+    name: &str,
+    reason: &str,
+) -> Result<bool, String> {
+    call_runtime_function_with(
+        runtime,
+        "abort",
+        message_identifier,
+        vec![
+            RuntimeFunctionArg::String(name),
+            RuntimeFunctionArg::String(reason),
+        ],
+        |_, result| result.is_true(),
+    )
+}
+
+/// Arguments passed to a `GlobeRuntime` function after the call's or
+/// stream's id.
+enum RuntimeFunctionArg<'a> {
+    String(&'a str),
+    Number(f64),
+    /// Passed as a `Uint8Array`.
+    Bytes(Vec<u8>),
+}
+
+fn call_runtime_function(
+    runtime: &mut JsRuntime,
+    function: &str,
+    id: i32,
+    extra_args: Vec<RuntimeFunctionArg>,
+) -> Result<(), String> {
+    call_runtime_function_with(runtime, function, id, extra_args, |_, _| ())
+}
+
+/// Like [`call_runtime_function`], mapping the function's return value with
+/// `map`.
+fn call_runtime_function_with<T>(
+    runtime: &mut JsRuntime,
+    function: &str,
+    id: i32,
+    extra_args: Vec<RuntimeFunctionArg>,
+    map: impl FnOnce(&mut v8::HandleScope, v8::Local<v8::Value>) -> T,
+) -> Result<T, String> {
+    let scope = &mut runtime.handle_scope();
+    let runtime_function = get_runtime_function(scope, function)?;
+
+    let receiver = v8::undefined(scope).into();
+    let mut args = vec![v8::Integer::new(scope, id).into()];
+    for arg in extra_args {
+        args.push(match arg {
+            RuntimeFunctionArg::String(string) => v8::String::new(scope, string).unwrap().into(),
+            RuntimeFunctionArg::Number(number) => v8::Number::new(scope, number).into(),
+            RuntimeFunctionArg::Bytes(bytes) => {
+                let length = bytes.len();
+                let buffer = parse_byte_data(scope, bytes);
+                v8::Uint8Array::new(scope, buffer, 0, length)
+                    .map_or_else(|| v8::undefined(scope).into(), Into::into)
+            }
+        });
+    }
+
+    let result = runtime_function
+        .call(scope, receiver, &args)
+        .ok_or_else(|| format!("Error: Runtime function '{}' failed for {}", function, id))?;
+
+    Ok(map(scope, result))
+}
+
+/// Looks up a function of the internal `GlobeRuntime` object declared in
+/// `dart_runtime.ts`.
+fn get_runtime_function<'a>(
+    scope: &mut v8::HandleScope<'a>,
+    function: &str,
+) -> Result<v8::Local<'a, v8::Function>, String> {
+    let runtime_obj = get_js_module(scope, "GlobeRuntime")?;
+
+    let function_key = v8::String::new(scope, function).unwrap();
+    runtime_obj
+        .get(scope, function_key.into())
+        .and_then(|value| v8::Local::<v8::Function>::try_from(value).ok())
+        .ok_or_else(|| format!("Error: Runtime function '{}' not found", function))
+}
+
+/// Backs `Deno.env.get`, limited to the variables the `env` permission allows.
+#[op2]
+#[string]
+fn op_env_get(
+    state: &mut OpState,
+    #[string] key: String,
+) -> Result<Option<String>, PermissionCheckError> {
+    state.borrow_mut::<ScopedPermissions>().check_env(&key)?;
+    Ok(std::env::var(key).ok())
+}
+
+/// The module of the running JavaScript, as the permission checks see it.
+#[op2]
+#[string]
+fn op_running_module(state: &mut OpState) -> Option<String> {
+    state.borrow::<ScopedPermissions>().running_module()
+}
+
 extension!(
     js_runtime,
+    ops = [op_env_get, op_running_module],
     esm_entry_point = "ext:js_runtime/js_runtime.ts",
     esm = [dir "src", "js_runtime.ts"],
 );
@@ -126,10 +1050,12 @@ extension!(
 extension!(deno_permissions_worker,
   options = {
     permissions: PermissionsContainer,
+    scoped_permissions: ScopedPermissions,
     enable_testing_features: bool,
   },
   state = |state, options| {
     state.put::<PermissionsContainer>(options.permissions);
+    state.put::<ScopedPermissions>(options.scoped_permissions);
     state.put(ops::TestingFeaturesEnabled(options.enable_testing_features));
   },
 );
@@ -227,6 +1153,20 @@ pub enum FFITypeId {
     Bool = 4,
     Bytes = 5,
     JsonPayload = 6,
+    Int64 = 7,
+    Int8List = 8,
+    Int16List = 9,
+    Int32List = 10,
+    Int64List = 11,
+    Uint16List = 12,
+    Uint32List = 13,
+    Uint64List = 14,
+    Float32List = 15,
+    Float64List = 16,
+    ExternalBytes = 17,
+    Stream = 18,
+    Callback = 19,
+    JsHandle = 20,
 }
 
 impl FFITypeId {
@@ -240,90 +1180,324 @@ impl FFITypeId {
             4 => Some(FFITypeId::Bool),
             5 => Some(FFITypeId::Bytes),
             6 => Some(FFITypeId::JsonPayload),
+            7 => Some(FFITypeId::Int64),
+            8 => Some(FFITypeId::Int8List),
+            9 => Some(FFITypeId::Int16List),
+            10 => Some(FFITypeId::Int32List),
+            11 => Some(FFITypeId::Int64List),
+            12 => Some(FFITypeId::Uint16List),
+            13 => Some(FFITypeId::Uint32List),
+            14 => Some(FFITypeId::Uint64List),
+            15 => Some(FFITypeId::Float32List),
+            16 => Some(FFITypeId::Float64List),
+            17 => Some(FFITypeId::ExternalBytes),
+            18 => Some(FFITypeId::Stream),
+            19 => Some(FFITypeId::Callback),
+            20 => Some(FFITypeId::JsHandle),
+            _ => None,
+        }
+    }
+
+    /// The JS typed array a list type arrives as, `None` for other types.
+    pub fn typed_array_kind(self) -> Option<TypedArrayKind> {
+        match self {
+            FFITypeId::Int8List => Some(TypedArrayKind::Int8),
+            FFITypeId::Int16List => Some(TypedArrayKind::Int16),
+            FFITypeId::Int32List => Some(TypedArrayKind::Int32),
+            FFITypeId::Int64List => Some(TypedArrayKind::BigInt64),
+            FFITypeId::Uint16List => Some(TypedArrayKind::Uint16),
+            FFITypeId::Uint32List => Some(TypedArrayKind::Uint32),
+            FFITypeId::Uint64List => Some(TypedArrayKind::BigUint64),
+            FFITypeId::Float32List => Some(TypedArrayKind::Float32),
+            FFITypeId::Float64List => Some(TypedArrayKind::Float64),
             _ => None,
         }
     }
 }
 
-pub fn c_args_to_v8_args_global(
-    scope: &mut v8::HandleScope,
+/// Element type of a numeric list passed as a typed array view.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TypedArrayKind {
+    Int8,
+    Int16,
+    Int32,
+    BigInt64,
+    Uint16,
+    Uint32,
+    BigUint64,
+    Float32,
+    Float64,
+}
+
+impl TypedArrayKind {
+    pub fn element_size(self) -> usize {
+        match self {
+            TypedArrayKind::Int8 => 1,
+            TypedArrayKind::Int16 | TypedArrayKind::Uint16 => 2,
+            TypedArrayKind::Int32 | TypedArrayKind::Uint32 | TypedArrayKind::Float32 => 4,
+            TypedArrayKind::BigInt64 | TypedArrayKind::BigUint64 | TypedArrayKind::Float64 => 8,
+        }
+    }
+}
+
+/// An owned copy of a single argument received over FFI.
+///
+/// Arguments are copied out of Dart memory before a call is queued on the
+/// runtime thread, so Dart can release its buffers as soon as the export
+/// returns.
+#[derive(Debug, Clone)]
+pub enum FFIArg {
+    Null,
+    Undefined,
+    String(String),
+    Integer(i32),
+    Int64(i64),
+    Double(f64),
+    Bool(bool),
+    Bytes(Vec<u8>),
+    JsonPayload(Vec<u8>),
+    /// The raw bytes of a numeric list, in native byte order.
+    TypedArray(TypedArrayKind, Vec<u8>),
+    /// Bytes in Dart memory, passed to JS without a copy.
+    ExternalBytes(Arc<ExternalBuffer>),
+    /// The id of a stream Dart feeds with [`push_js_stream_chunk`], passed
+    /// to JS as a `ReadableStream`.
+    Stream(i32),
+    /// A Dart function, passed to JS as an async function calling it.
+    Callback(Arc<DartCallback>),
+    /// The id of an object handed to Dart with `Dart.handle`, passed back to
+    /// JS as that object.
+    Handle(i32),
+}
+
+impl FFIArg {
+    /// Whether the argument refers to something living in the isolate it was
+    /// first passed to, so a recycled isolate can't be given it again.
+    pub fn is_isolate_bound(&self) -> bool {
+        matches!(
+            self,
+            FFIArg::Stream(_) | FFIArg::Callback(_) | FFIArg::Handle(_)
+        )
+    }
+}
+
+/// Copies `count` C arguments into owned [`FFIArg`]s.
+///
+/// External byte arguments are not copied, Dart is notified through
+/// `release_port` once they are no longer used.
+///
+/// # Safety
+///
+/// `args`, `type_ids` and `sizes` must each point to `count` valid elements.
+pub unsafe fn read_c_args(
     args: *const *const c_void,
     type_ids: *const i32,
     sizes: *const isize,
     count: i32,
-) -> Vec<v8::Global<v8::Value>> {
-    let mut v8_args = Vec::new();
+    release_port: i64,
+) -> Vec<FFIArg> {
+    let mut ffi_args = Vec::new();
 
-    for i in 0..count as usize {
-        let arg_ptr = unsafe { *args.add(i) };
-        let type_id = unsafe { *type_ids.add(i) };
-        let size = unsafe { *sizes.add(i) };
+    for i in 0..count.max(0) as usize {
+        let arg_ptr = *args.add(i);
+        let type_id = *type_ids.add(i);
+        let size = *sizes.add(i);
 
         if arg_ptr.is_null() {
-            let null_value: v8::Local<v8::Value> = v8::null(scope).into();
-            v8_args.push(v8::Global::new(scope, null_value));
+            ffi_args.push(FFIArg::Null);
             continue;
         }
 
-        let ffi_type = FFITypeId::from_i32(type_id);
-
-        let v8_value: v8::Local<v8::Value> = match ffi_type {
+        let ffi_arg = match FFITypeId::from_i32(type_id) {
             Some(FFITypeId::String) => {
                 // ✅ String (Pointer to UTF-8)
-                let c_str = unsafe { CStr::from_ptr(arg_ptr as *const c_char) };
+                let c_str = CStr::from_ptr(arg_ptr as *const c_char);
                 match c_str.to_str() {
-                    Ok(string) => v8::String::new(scope, string).unwrap().into(),
-                    Err(_) => v8::undefined(scope).into(),
+                    Ok(string) => FFIArg::String(string.to_string()),
+                    Err(_) => FFIArg::Undefined,
                 }
             }
-            Some(FFITypeId::Integer) => {
-                let int_value = unsafe { *(arg_ptr as *const i32) };
-                v8::Integer::new(scope, int_value).into()
-            }
-            Some(FFITypeId::Double) => {
-                let float_value = unsafe { *(arg_ptr as *const f64) };
-                v8::Number::new(scope, float_value).into()
-            }
-            Some(FFITypeId::Bool) => {
-                let bool_value = arg_ptr as usize != 0;
-                v8::Boolean::new(scope, bool_value).into()
-            }
-            Some(FFITypeId::Bytes) => {
-                parse_byte_data(scope, arg_ptr as *const u8, size as usize).into()
+            Some(FFITypeId::Integer) => FFIArg::Integer(*(arg_ptr as *const i32)),
+            Some(FFITypeId::Int64) => FFIArg::Int64(*(arg_ptr as *const i64)),
+            Some(FFITypeId::Double) => FFIArg::Double(*(arg_ptr as *const f64)),
+            Some(FFITypeId::Bool) => FFIArg::Bool(arg_ptr as usize != 0),
+            Some(FFITypeId::Stream) => FFIArg::Stream(*(arg_ptr as *const i32)),
+            Some(FFITypeId::Callback) => {
+                FFIArg::Callback(DartCallback::new(*(arg_ptr as *const i32), release_port))
             }
+            Some(FFITypeId::JsHandle) => FFIArg::Handle(*(arg_ptr as *const i32)),
+            Some(FFITypeId::Bytes) => FFIArg::Bytes(read_byte_data(arg_ptr as *const u8, size)),
             Some(FFITypeId::JsonPayload) => {
-                parse_json_payload_bytes(scope, arg_ptr as *const u8, size as usize).into()
+                FFIArg::JsonPayload(read_byte_data(arg_ptr as *const u8, size))
+            }
+            Some(FFITypeId::ExternalBytes) => {
+                match ExternalBuffer::new(arg_ptr as *mut u8, size.max(0) as usize, release_port) {
+                    Some(buffer) => FFIArg::ExternalBytes(buffer),
+                    None => FFIArg::Undefined,
+                }
             }
-            _ => v8::undefined(scope).into(),
+            // `size` is the length in bytes for typed lists
+            Some(type_id) => match type_id.typed_array_kind() {
+                Some(kind) => FFIArg::TypedArray(kind, read_byte_data(arg_ptr as *const u8, size)),
+                None => FFIArg::Undefined,
+            },
+            None => FFIArg::Undefined,
         };
 
-        let global_value = v8::Global::new(scope, v8_value);
-        v8_args.push(global_value);
+        ffi_args.push(ffi_arg);
     }
 
-    v8_args
+    ffi_args
+}
+
+unsafe fn read_byte_data(arg_ptr: *const u8, size: isize) -> Vec<u8> {
+    std::slice::from_raw_parts(arg_ptr, size.max(0) as usize).to_vec()
+}
+
+/// Converts `args` to JS values, failing for arguments JS can't represent,
+/// such as a `JsonPayload` that doesn't decode.
+pub fn ffi_args_to_v8_args<'s>(
+    scope: &mut v8::HandleScope<'s>,
+    args: &[FFIArg],
+) -> Result<Vec<v8::Local<'s, v8::Value>>, String> {
+    let mut v8_args = Vec::new();
+
+    for (index, arg) in args.iter().enumerate() {
+        let v8_value: v8::Local<v8::Value> = match arg {
+            FFIArg::Null => v8::null(scope).into(),
+            FFIArg::Undefined => v8::undefined(scope).into(),
+            FFIArg::String(string) => v8::String::new(scope, string)
+                .ok_or_else(|| format!("Error: Argument {} is too long for a JS string", index))?
+                .into(),
+            FFIArg::Integer(int_value) => v8::Integer::new(scope, *int_value).into(),
+            FFIArg::Int64(int_value) => int64_to_v8(scope, *int_value),
+            FFIArg::Double(float_value) => v8::Number::new(scope, *float_value).into(),
+            FFIArg::Bool(bool_value) => v8::Boolean::new(scope, *bool_value).into(),
+            FFIArg::Bytes(bytes) => parse_byte_data(scope, bytes.clone()).into(),
+            FFIArg::JsonPayload(bytes) => parse_json_payload_bytes(scope, bytes.clone())
+                .map_err(|e| format!("Error: Invalid JSON payload in argument {}: {}", index, e))?,
+            FFIArg::TypedArray(kind, bytes) => parse_typed_array(scope, *kind, bytes.clone()),
+            FFIArg::ExternalBytes(buffer) => buffer.to_array_buffer(scope).into(),
+            FFIArg::Stream(stream_id) => open_js_stream(scope, *stream_id),
+            FFIArg::Callback(callback) => open_js_callback(scope, callback),
+            FFIArg::Handle(handle_id) => resolve_js_handle(scope, *handle_id),
+        };
+
+        v8_args.push(v8_value);
+    }
+
+    Ok(v8_args)
+}
+
+/// Largest integer a JS number holds exactly, `Number.MAX_SAFE_INTEGER`.
+const MAX_SAFE_INTEGER: i64 = (1 << 53) - 1;
+
+// 64-bit integers become numbers when they fit exactly and `BigInt`s otherwise
+fn int64_to_v8<'a>(scope: &mut v8::HandleScope<'a>, value: i64) -> v8::Local<'a, v8::Value> {
+    if (-MAX_SAFE_INTEGER..=MAX_SAFE_INTEGER).contains(&value) {
+        v8::Number::new(scope, value as f64).into()
+    } else {
+        v8::BigInt::new_from_i64(scope, value).into()
+    }
 }
 
 fn parse_byte_data<'a>(
     scope: &mut v8::HandleScope<'a>,
-    arg_ptr: *const u8,
-    size: usize,
+    bytes: Vec<u8>,
 ) -> v8::Local<'a, v8::ArrayBuffer> {
-    let byte_array = unsafe { std::slice::from_raw_parts(arg_ptr, size) };
-
-    let v8_array =
-        v8::ArrayBuffer::new_backing_store_from_boxed_slice(byte_array.to_vec().into_boxed_slice());
+    let v8_array = v8::ArrayBuffer::new_backing_store_from_boxed_slice(bytes.into_boxed_slice());
     let v8_shared_array = v8_array.make_shared();
     let v8_buffer = v8::ArrayBuffer::with_backing_store(scope, &v8_shared_array);
     v8_buffer
 }
 
-fn parse_json_payload_bytes<'a>(
+fn parse_typed_array<'a>(
+    scope: &mut v8::HandleScope<'a>,
+    kind: TypedArrayKind,
+    bytes: Vec<u8>,
+) -> v8::Local<'a, v8::Value> {
+    let length = bytes.len() / kind.element_size();
+    let buffer = parse_byte_data(scope, bytes);
+
+    let typed_array: Option<v8::Local<v8::TypedArray>> = match kind {
+        TypedArrayKind::Int8 => v8::Int8Array::new(scope, buffer, 0, length).map(Into::into),
+        TypedArrayKind::Int16 => v8::Int16Array::new(scope, buffer, 0, length).map(Into::into),
+        TypedArrayKind::Int32 => v8::Int32Array::new(scope, buffer, 0, length).map(Into::into),
+        TypedArrayKind::BigInt64 => {
+            v8::BigInt64Array::new(scope, buffer, 0, length).map(Into::into)
+        }
+        TypedArrayKind::Uint16 => v8::Uint16Array::new(scope, buffer, 0, length).map(Into::into),
+        TypedArrayKind::Uint32 => v8::Uint32Array::new(scope, buffer, 0, length).map(Into::into),
+        TypedArrayKind::BigUint64 => {
+            v8::BigUint64Array::new(scope, buffer, 0, length).map(Into::into)
+        }
+        TypedArrayKind::Float32 => v8::Float32Array::new(scope, buffer, 0, length).map(Into::into),
+        TypedArrayKind::Float64 => v8::Float64Array::new(scope, buffer, 0, length).map(Into::into),
+    };
+
+    typed_array.map_or_else(|| v8::undefined(scope).into(), Into::into)
+}
+
+// Creates the `ReadableStream` chunks pushed to `stream_id` are enqueued in
+fn open_js_stream<'a>(scope: &mut v8::HandleScope<'a>, stream_id: i32) -> v8::Local<'a, v8::Value> {
+    let Ok(open_stream) = get_runtime_function(scope, "open_stream") else {
+        return v8::undefined(scope).into();
+    };
+
+    let receiver = v8::undefined(scope).into();
+    let args = [v8::Integer::new(scope, stream_id).into()];
+    open_stream
+        .call(scope, receiver, &args)
+        .unwrap_or_else(|| v8::undefined(scope).into())
+}
+
+// The object behind `handle_id`, `undefined` once it was released
+fn resolve_js_handle<'a>(
+    scope: &mut v8::HandleScope<'a>,
+    handle_id: i32,
+) -> v8::Local<'a, v8::Value> {
+    let Ok(resolve_handle) = get_runtime_function(scope, "resolve_handle") else {
+        return v8::undefined(scope).into();
+    };
+
+    let receiver = v8::undefined(scope).into();
+    let args = [v8::Integer::new(scope, handle_id).into()];
+    resolve_handle
+        .call(scope, receiver, &args)
+        .unwrap_or_else(|| v8::undefined(scope).into())
+}
+
+// Creates the async function calling `callback`, which keeps it alive until
+// the function is garbage collected
+fn open_js_callback<'a>(
     scope: &mut v8::HandleScope<'a>,
-    arg_ptr: *const u8,
-    size: usize,
+    callback: &Arc<DartCallback>,
 ) -> v8::Local<'a, v8::Value> {
-    let v8_buffer = parse_byte_data(scope, arg_ptr, size);
+    let Ok(open_callback) = get_runtime_function(scope, "open_callback") else {
+        return v8::undefined(scope).into();
+    };
+
+    let receiver = v8::undefined(scope).into();
+    let args = [v8::Integer::new(scope, callback.id()).into()];
+    let Some(function) = open_callback.call(scope, receiver, &args) else {
+        return v8::undefined(scope).into();
+    };
+
+    // Runs once the function is collected or the isolate is dropped, even
+    // though the weak handle itself isn't kept
+    let reference = callback.clone();
+    v8::Weak::with_guaranteed_finalizer(scope, function, Box::new(move || drop(reference)));
+
+    function
+}
+
+/// Decodes `bytes` with `JsonPayload.decode`, returning the message of the
+/// exception it throws for invalid payloads.
+fn parse_json_payload_bytes<'a>(
+    scope: &mut v8::HandleScope<'a>,
+    bytes: Vec<u8>,
+) -> Result<v8::Local<'a, v8::Value>, String> {
+    let v8_buffer = parse_byte_data(scope, bytes);
 
     // call JsonPayload.decode() from the runtime add pass the v8_buffer as an argument
     let json_payload = v8::String::new(scope, "JsonPayload").unwrap();
@@ -331,111 +1505,46 @@ fn parse_json_payload_bytes<'a>(
         .get_current_context()
         .global(scope)
         .get(scope, json_payload.into())
-        .unwrap();
+        .and_then(|value| value.to_object(scope))
+        .ok_or_else(|| "JsonPayload is not available".to_string())?;
 
     let decode_function = v8::String::new(scope, "decode").unwrap();
-    let decode_function_value = json_payload_value
-        .to_object(scope)
-        .unwrap()
+    let decode_function = json_payload_value
         .get(scope, decode_function.into())
-        .unwrap();
-    let decode_function = v8::Local::<v8::Function>::try_from(decode_function_value).unwrap();
-    let args = vec![v8_buffer.into()];
-    let result = decode_function
-        .call(scope, json_payload_value.into(), &args)
-        .unwrap();
-    let result = v8::Local::<v8::Value>::try_from(result).unwrap();
-    result
-}
-
-pub fn c_args_to_v8_args_local<'s>(
-    scope: &mut v8::HandleScope<'s>,
-    args: *const *const c_void,
-    type_ids: *const i32,
-    sizes: *const isize,
-    count: i32,
-) -> Vec<v8::Local<'s, v8::Value>> {
-    let mut v8_args = Vec::new();
+        .and_then(|value| v8::Local::<v8::Function>::try_from(value).ok())
+        .ok_or_else(|| "JsonPayload.decode is not available".to_string())?;
 
-    for i in 0..count as usize {
-        let arg_ptr = unsafe { *args.add(i) };
-        let type_id = unsafe { *type_ids.add(i) };
-        let size = unsafe { *sizes.add(i) };
-
-        if arg_ptr.is_null() {
-            let null_value: v8::Local<v8::Value> = v8::null(scope).into();
-            v8_args.push(null_value);
-            continue;
-        }
-
-        let ffi_type = FFITypeId::from_i32(type_id);
-
-        let v8_value: v8::Local<v8::Value> = match ffi_type {
-            Some(FFITypeId::String) => {
-                let c_str = unsafe { CStr::from_ptr(arg_ptr as *const c_char) };
-                match c_str.to_str() {
-                    Ok(string) => v8::String::new(scope, string).unwrap().into(),
-                    Err(_) => v8::undefined(scope).into(),
-                }
-            }
-            Some(FFITypeId::Integer) => {
-                let int_value = unsafe { *(arg_ptr as *const i32) };
-                v8::Integer::new(scope, int_value).into()
-            }
-            Some(FFITypeId::Double) => {
-                let float_value = unsafe { *(arg_ptr as *const f64) };
-                v8::Number::new(scope, float_value).into()
-            }
-            Some(FFITypeId::Bool) => {
-                let bool_value = arg_ptr as usize != 0;
-                v8::Boolean::new(scope, bool_value).into()
-            }
-            Some(FFITypeId::Bytes) => {
-                parse_byte_data(scope, arg_ptr as *const u8, size as usize).into()
-            }
-            Some(FFITypeId::JsonPayload) => {
-                parse_json_payload_bytes(scope, arg_ptr as *const u8, size as usize).into()
-            }
-            _ => v8::undefined(scope).into(),
-        };
+    let tc_scope = &mut v8::TryCatch::new(scope);
+    let args = vec![v8_buffer.into()];
+    let result = decode_function.call(tc_scope, json_payload_value.into(), &args);
 
-        v8_args.push(v8_value);
+    match result {
+        Some(result) => Ok(result),
+        None => Err(tc_scope
+            .exception()
+            .map(|e| e.to_rust_string_lossy(tc_scope))
+            .unwrap_or_else(|| "execution terminated".to_string())),
     }
-
-    v8_args
 }
 
-pub fn register_js_module<'s>(
+/// Creates the object a module is registered as, holding its functions and,
+/// for its instances, its `init` and `dispose` hooks. The module's `state`
+/// is set once `init` has run.
+fn create_module_object<'s>(
     scope: &mut v8::HandleScope<'s>,
-    module_name: v8::Local<'s, v8::String>,
     init_function: Option<v8::Local<'s, v8::Function>>,
+    dispose_function: Option<v8::Local<'s, v8::Function>>,
     functions_object: v8::Local<'s, v8::Object>,
-    module_init_args: JsFunctionArgs,
-) -> u8 {
-    let module_state_value = {
-        if init_function.is_some() {
-            let v8_args = c_args_to_v8_args_local(
-                scope,
-                module_init_args.args,
-                module_init_args.type_ids,
-                module_init_args.sizes,
-                module_init_args.count,
-            );
-
-            let receiver = v8::undefined(scope).into();
-            init_function
-                .unwrap()
-                .call(scope, receiver, &v8_args)
-                .unwrap()
-        } else {
-            v8::undefined(scope).into()
-        }
-    };
-
-    // Create module object and set the state
+) -> Result<v8::Local<'s, v8::Object>, String> {
     let module_object = v8::Object::new(scope);
-    let state_key = v8::String::new(scope, "state").unwrap();
-    module_object.set(scope, state_key.into(), module_state_value);
+
+    // Kept for the module's instances
+    for (hook_name, hook) in [(INIT_HOOK, init_function), (DISPOSE_HOOK, dispose_function)] {
+        if let Some(hook) = hook {
+            let key = module_hook_key(scope, hook_name);
+            module_object.set_private(scope, key, hook.into());
+        }
+    }
 
     let args = v8::GetPropertyNamesArgs {
         mode: v8::KeyCollectionMode::OwnOnly,
@@ -446,20 +1555,112 @@ pub fn register_js_module<'s>(
 
     let props_array = functions_object
         .get_own_property_names(scope, args)
-        .unwrap();
+        .ok_or_else(|| "Error: Could not read the module's functions".to_string())?;
 
     // Loop over module properties and register functions
     for i in 0..props_array.length() {
-        let key = props_array.get_index(scope, i).unwrap();
-        let value = functions_object.get(scope, key).unwrap();
-        if value.is_function() {
-            module_object.set(scope, key, value.into());
+        let Some(key) = props_array.get_index(scope, i) else {
+            continue;
+        };
+        match functions_object.get(scope, key) {
+            Some(value) if value.is_function() => {
+                module_object.set(scope, key, value);
+            }
+            _ => {}
         }
     }
 
-    // Put module on globalThis
-    let global = scope.get_current_context().global(scope);
-    global.set(scope, module_name.into(), module_object.into());
+    Ok(module_object)
+}
+
+/// Private keys the `init` and `dispose` hooks of a module are kept under on
+/// its module object.
+const INIT_HOOK: &str = "globe:init";
+const DISPOSE_HOOK: &str = "globe:dispose";
+
+fn module_hook_key<'s>(
+    scope: &mut v8::HandleScope<'s>,
+    hook_name: &str,
+) -> v8::Local<'s, v8::Private> {
+    let name = v8::String::new(scope, hook_name).unwrap();
+    v8::Private::for_api(scope, Some(name))
+}
+
+fn get_module_hook<'s>(
+    scope: &mut v8::HandleScope<'s>,
+    module_object: v8::Local<'s, v8::Object>,
+    hook_name: &str,
+) -> Option<v8::Local<'s, v8::Function>> {
+    let key = module_hook_key(scope, hook_name);
+    module_object
+        .get_private(scope, key)
+        .and_then(|value| v8::Local::<v8::Function>::try_from(value).ok())
+}
+
+/// Replaces the async context with a fresh one naming `module_name`, so ops
+/// check the module's permissions, and returns the previous context.
+///
+/// An empty name, used for handles created where the module couldn't be
+/// told, leaves the module unknown, which denies everything once modules have
+/// their own permissions.
+fn enter_module_context<'s>(
+    scope: &mut v8::HandleScope<'s>,
+    module_name: v8::Local<'s, v8::String>,
+) -> v8::Local<'s, v8::Value> {
+    let previous_context = scope.get_continuation_preserved_embedder_data();
+    let module_context = v8::Object::new(scope);
+    if module_name.length() > 0 {
+        let module_key = module_context_key(scope);
+        module_context.set_private(scope, module_key, module_name.into());
+    }
+    scope.set_continuation_preserved_embedder_data(module_context.into());
+
+    previous_context
+}
+
+/// Calls a module's `init` or `dispose` hook in the module's async context,
+/// so work it starts is held to the module's permissions. Returns the
+/// exception, serialized like errors of calls, when the hook throws.
+fn run_module_hook<'s>(
+    scope: &mut v8::HandleScope<'s>,
+    module_name: v8::Local<'s, v8::String>,
+    hook: v8::Local<'s, v8::Function>,
+    args: &[v8::Local<'s, v8::Value>],
+) -> Result<v8::Local<'s, v8::Value>, LoadError> {
+    let previous_context = enter_module_context(scope, module_name);
+
+    let tc_scope = &mut v8::TryCatch::new(scope);
+    let receiver = v8::undefined(tc_scope).into();
+    let result = hook.call(tc_scope, receiver, args);
+    tc_scope.set_continuation_preserved_embedder_data(previous_context);
+
+    if let Some(result) = result {
+        return Ok(result);
+    }
+    let Some(exception) = tc_scope.exception() else {
+        return Err("execution terminated".to_string().into());
+    };
+    Err(LoadError {
+        message: exception.to_rust_string_lossy(tc_scope),
+        js_error: serialize_js_error(tc_scope, exception),
+    })
+}
+
+/// Encodes `error` with `serializeError` in `dart_runtime.ts`, which builds
+/// the structured errors of calls. `None` if JavaScript can't run, e.g.
+/// because execution is terminating.
+fn serialize_js_error<'s>(
+    scope: &mut v8::HandleScope<'s>,
+    error: v8::Local<'s, v8::Value>,
+) -> Option<JsError> {
+    let serialize_error = get_runtime_function(scope, "serialize_error").ok()?;
+
+    let tc_scope = &mut v8::TryCatch::new(scope);
+    let receiver = v8::undefined(tc_scope).into();
+    let encoded = serialize_error.call(tc_scope, receiver, &[error])?;
+    let encoded = v8::Local::<v8::Uint8Array>::try_from(encoded).ok()?;
 
-    0
+    let mut bytes = vec![0; encoded.byte_length()];
+    encoded.copy_contents(&mut bytes);
+    Some(JsError::Encoded(bytes))
 }
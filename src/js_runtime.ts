@@ -62,3 +62,21 @@ Object.defineProperty(globalThis, "DOMException", {
 });
 
 Deno.core.setWasmStreamingCallback(fetch.handleWasmStreaming);
+
+// Denials from the runtime's permission allowlists
+class PermissionDenied extends Error {
+  constructor(message?: string, options?: ErrorOptions) {
+    super(message, options);
+    this.name = "PermissionDenied";
+  }
+}
+
+core.registerErrorClass("PermissionDenied", PermissionDenied);
+core.registerErrorClass("NotCapable", PermissionDenied);
+
+Deno.errors = { ...Deno.errors, PermissionDenied };
+
+Deno.env = {
+  get: (key: string): string | undefined => core.ops.op_env_get(key) ?? undefined,
+  has: (key: string): boolean => core.ops.op_env_get(key) != null,
+};
@@ -1,62 +1,142 @@
 mod dart_api;
+mod dart_callback;
+mod dart_message;
 mod dart_runtime;
+mod dart_value;
+mod external_buffer;
+mod ffi_error;
 mod js_resolver;
 mod js_runtime;
+mod permissions;
+mod runtime_config;
+mod runtime_thread;
 mod utils;
+mod watchdog;
 
 include!(concat!(env!("OUT_DIR"), "/version.rs"));
 
-use deno_runtime::deno_core::{self};
-
 use std::{
-    cell::RefCell,
+    collections::HashMap,
     ffi::{c_char, c_void, CStr, CString},
-    rc::Rc,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Mutex, OnceLock,
+    },
+    time::Duration,
 };
 
-thread_local! {
-    static JS_RUNTIME: RefCell<Option<Rc<RefCell<deno_core::JsRuntime>>>> = RefCell::new(None);
+use ffi_error::{catch_panic, ErrorCode, FfiError};
+use js_runtime::CallTarget;
+use runtime_config::{PermissionsConfig, RuntimeConfig};
+use runtime_thread::{RuntimeCommand, RuntimeThread};
+use tokio::sync::mpsc::UnboundedSender;
+
+/// Opaque identifier returned by `init_runtime`. `0` is never a valid handle.
+pub type RuntimeHandle = u64;
+
+static JS_RUNTIMES: OnceLock<Mutex<HashMap<RuntimeHandle, RuntimeThread>>> = OnceLock::new();
+
+static NEXT_RUNTIME_HANDLE: AtomicU64 = AtomicU64::new(1);
+
+fn js_runtimes() -> &'static Mutex<HashMap<RuntimeHandle, RuntimeThread>> {
+    JS_RUNTIMES.get_or_init(Default::default)
 }
 
-fn get_runtime_instance() -> Rc<RefCell<deno_core::JsRuntime>> {
-    JS_RUNTIME.with(|runtime| {
-        runtime
-            .borrow()
-            .as_ref()
-            .expect("Error: JS Runtime has not been initialized! Call `init_runtime()` first.")
-            .clone()
-    })
+fn get_runtime_instance(
+    handle: RuntimeHandle,
+) -> Result<UnboundedSender<RuntimeCommand>, FfiError> {
+    with_runtime(handle, RuntimeThread::sender)
 }
 
+fn with_runtime<T>(
+    handle: RuntimeHandle,
+    f: impl FnOnce(&RuntimeThread) -> T,
+) -> Result<T, FfiError> {
+    js_runtimes()
+        .lock()
+        .unwrap()
+        .get(&handle)
+        .map(f)
+        .ok_or_else(|| {
+            FfiError::new(
+                ErrorCode::RuntimeNotFound,
+                format!(
+                    "Error: JS Runtime {} has not been initialized! Call `init_runtime()` first.",
+                    handle
+                ),
+            )
+        })
+}
+
+/// Creates a new JS runtime bound to `dart_port` with the default
+/// configuration and returns its handle.
+///
+/// The runtime lives on its own thread; every other export only queues work
+/// for it. Returns `0` and sets `error` if the runtime could not be created.
 #[no_mangle]
 pub unsafe extern "C" fn init_runtime(
     dart_api: *mut c_void,
     dart_port: dart_api::Dart_Port,
     error: *mut *const c_char,
-) -> u8 {
-    if !error.is_null() {
-        *error = std::ptr::null();
-    }
+) -> RuntimeHandle {
+    into_handle(error, || {
+        create_runtime(dart_api, dart_port, RuntimeConfig::default())
+    })
+}
 
+/// Same as `init_runtime`, configured by the msgpack-encoded `RuntimeConfig`
+/// of `config_len` bytes at `config`.
+#[no_mangle]
+pub unsafe extern "C" fn init_runtime_with_config(
+    dart_api: *mut c_void,
+    dart_port: dart_api::Dart_Port,
+    config: *const u8,
+    config_len: usize,
+    error: *mut *const c_char,
+) -> RuntimeHandle {
+    into_handle(error, || {
+        let config_bytes = check_and_get_bytes(config, config_len)?;
+        let config = RuntimeConfig::from_msgpack(config_bytes)
+            .map_err(|e| FfiError::new(ErrorCode::InvalidArgument, e))?;
+
+        create_runtime(dart_api, dart_port, config)
+    })
+}
+
+unsafe fn create_runtime(
+    dart_api: *mut c_void,
+    dart_port: dart_api::Dart_Port,
+    config: RuntimeConfig,
+) -> Result<RuntimeHandle, FfiError> {
     let result = dart_api::Dart_InitializeApiDL(dart_api);
     if result != 0 {
-        set_error(error, "Failed to initialize Dart DL C API: Version mismatch. Ensure that include/ matches Dart SDK version.");
-        return 1;
+        return Err(FfiError::new(
+            ErrorCode::InitFailed,
+            "Failed to initialize Dart DL C API: Version mismatch. Ensure that include/ matches Dart SDK version.",
+        ));
     }
 
-    let runtime = js_runtime::get_runtime(dart_port);
+    let runtime = RuntimeThread::spawn(dart_port, config)
+        .map_err(|e| FfiError::new(ErrorCode::InitFailed, e))?;
+    let handle = NEXT_RUNTIME_HANDLE.fetch_add(1, Ordering::Relaxed);
 
-    JS_RUNTIME.with(|js_runtime| {
-        *js_runtime.borrow_mut() = Some(Rc::new(RefCell::new(runtime)));
-    });
+    js_runtimes().lock().unwrap().insert(handle, runtime);
 
-    0
+    Ok(handle)
 }
 
+/// Queues the registration of `module_name`.
+///
+/// Only problems queueing the registration are reported here; whether the
+/// module loaded and its `init` succeeded is posted to the runtime's Dart
+/// port under `message_identifier`, as a final message without a value or
+/// with the error.
 #[no_mangle]
 pub unsafe extern "C" fn register_module(
+    runtime: RuntimeHandle,
     module_name: *const c_char,
     module_source: *const c_char,
+    message_identifier: i32,
     error: *mut *const c_char,
     //
     args: *const *const c_void, // Arguments pointer
@@ -64,119 +144,127 @@ pub unsafe extern "C" fn register_module(
     arg_sizes: *const isize,    // Argument sizes (for List<String>, Uint8List)
     args_count: i32,            // Number of arguments
 ) -> u8 {
-    if !error.is_null() {
-        *error = std::ptr::null();
-    }
-
-    let module_init_args = js_runtime::JsFunctionArgs {
-        args,
-        sizes: arg_sizes,
-        count: args_count,
-        type_ids: arg_type_ids,
-    };
-
-    let module_name_str = match check_and_get_cstr(module_name) {
-        Ok(name) => name,
-        Err(e) => {
-            set_error(error, e);
-            return 1;
-        }
-    };
-
-    let source_code = match check_and_get_cstr(module_source) {
-        Ok(source) => {
-            let file_path = extract_filepath_from_source(&source)
-                .unwrap_or_else(|| format!("file://{}{}", module_name_str.to_lowercase(), ".js"));
-            (file_path, source.to_string())
-        }
-        Err(e) => {
-            set_error(error, e);
-            return 1;
-        }
-    };
-
-    let runtime_ref = get_runtime_instance();
-    let mut javascript_runtime = runtime_ref.borrow_mut();
-
-    let module_object = {
-        let result = javascript_runtime.lazy_load_es_module_with_code(source_code.0, source_code.1);
-        if let Err(e) = result {
-            set_error(
-                error,
-                &format!("Error loading module: {}, {}", module_name_str, e),
-            );
-            return 1;
-        }
-
-        result.unwrap()
-    };
-
-    let mut handle_scope = javascript_runtime.handle_scope();
-    let scope = &mut handle_scope;
-
-    let local = v8::Local::new(scope, module_object);
-    let obj = local.to_object(scope).unwrap();
-    let key = v8::String::new(scope, "default").unwrap();
-
-    // Check if the module exports a default function
-    let value = obj.get(scope, key.into()).unwrap();
-    if !value.is_object() {
-        set_error(error, "Module does not export a default function");
-        return 1;
-    }
-
-    let module_name = v8::String::new(scope, module_name_str).unwrap();
-    let default_object = value.to_object(scope).unwrap();
-
-    // only run init function if it exists
-    let init_key = v8::String::new(scope, "init").unwrap();
-    let init_fnc_value = default_object.get(scope, init_key.into()).unwrap();
-    let init_function = v8::Local::<v8::Function>::try_from(init_fnc_value)
-        .map_or_else(|_| None, |func| Some(func));
-
-    let functions_key = v8::String::new(scope, "functions").unwrap();
-    let functions_value = default_object.get(scope, functions_key.into()).unwrap();
-    let functions_object = v8::Local::<v8::Object>::try_from(functions_value).unwrap();
-
-    js_runtime::register_js_module(
-        scope,
+    register_module_with_permissions(
+        runtime,
         module_name,
-        init_function,
-        functions_object,
-        module_init_args,
+        module_source,
+        message_identifier,
+        std::ptr::null(),
+        0,
+        error,
+        args,
+        arg_type_ids,
+        arg_sizes,
+        args_count,
     )
 }
 
+/// Same as `register_module`, with the module's code restricted to the
+/// msgpack-encoded `PermissionsConfig` of `permissions_len` bytes at
+/// `permissions`, on top of the runtime's permissions. A null `permissions`
+/// gives the module the runtime's permissions.
+#[no_mangle]
+pub unsafe extern "C" fn register_module_with_permissions(
+    runtime: RuntimeHandle,
+    module_name: *const c_char,
+    module_source: *const c_char,
+    message_identifier: i32,
+    permissions: *const u8,
+    permissions_len: usize,
+    error: *mut *const c_char,
+    //
+    args: *const *const c_void, // Arguments pointer
+    arg_type_ids: *const i32,   // Argument type IDs
+    arg_sizes: *const isize,    // Argument sizes (for List<String>, Uint8List)
+    args_count: i32,            // Number of arguments
+) -> u8 {
+    into_error_code(error, || {
+        // Arguments are taken first, so external buffers are released even
+        // if the registration fails
+        let (sender, send_port) = with_runtime(runtime, |r| (r.sender(), r.send_port()))?;
+        let module_init_args =
+            js_runtime::read_c_args(args, arg_type_ids, arg_sizes, args_count, send_port);
+
+        let module_name_str = check_and_get_cstr(module_name)?;
+        let source = check_and_get_cstr(module_source)?;
+        let (file_path, source) = match extract_filepath_from_source(source) {
+            // Drop the header line, so lines match the file and its source map
+            Some(file_path) => (
+                file_path,
+                source.split_once('\n').map_or("", |(_, rest)| rest),
+            ),
+            None => (
+                format!("file://{}{}", module_name_str.to_lowercase(), ".js"),
+                source,
+            ),
+        };
+
+        let module_permissions = if permissions.is_null() {
+            None
+        } else {
+            let permissions_bytes = check_and_get_bytes(permissions, permissions_len)?;
+            let permissions = PermissionsConfig::from_msgpack(permissions_bytes)
+                .map_err(|e| FfiError::new(ErrorCode::InvalidArgument, e))?;
+            Some(permissions)
+        };
+
+        runtime_thread::enqueue(
+            &sender,
+            RuntimeCommand::RegisterModule {
+                module_name: module_name_str.to_string(),
+                file_path,
+                source: source.to_string(),
+                args: module_init_args,
+                permissions: module_permissions,
+                message_identifier,
+            },
+        )
+        .map_err(|e| FfiError::new(ErrorCode::RuntimeNotFound, e))
+    })
+}
+
 #[no_mangle]
 pub unsafe extern "C" fn get_runtime_version() -> *const c_char {
-    CString::new(VERSION).unwrap().into_raw()
+    catch_panic(|| Ok(CString::new(VERSION).unwrap().into_raw() as *const c_char))
+        .unwrap_or(std::ptr::null())
 }
 
+/// Returns `ErrorCode::Ok` if `module_name` is registered in `runtime` and
+/// `ErrorCode::ModuleNotFound` if it is not.
 #[no_mangle]
-pub unsafe extern "C" fn is_module_registered(module_name: *const c_char) -> u8 {
-    let module_str = unsafe { CStr::from_ptr(module_name).to_str().unwrap() };
-    let runtime_ref = get_runtime_instance();
-
-    utils::tokio_runtime().block_on(async move {
-        let local_set = tokio::task::LocalSet::new();
-
-        local_set
-            .run_until(async move {
-                let mut javascript_runtime = runtime_ref.borrow_mut();
-                let scope = &mut javascript_runtime.handle_scope();
-                let js_module = js_runtime::get_js_module(scope, module_str);
-
-                match js_module {
-                    Ok(_) => 0,
-                    Err(_) => 1,
-                }
+pub unsafe extern "C" fn is_module_registered(
+    runtime: RuntimeHandle,
+    module_name: *const c_char,
+    error: *mut *const c_char,
+) -> u8 {
+    into_error_code(error, || {
+        let module_str = check_and_get_cstr(module_name)?;
+
+        let sender = get_runtime_instance(runtime)?;
+        let registered =
+            runtime_thread::request(&sender, |reply| RuntimeCommand::IsModuleRegistered {
+                module_name: module_str.to_string(),
+                reply,
             })
-            .await
+            .map_err(|e| FfiError::new(ErrorCode::RuntimeNotFound, e))?;
+
+        match registered {
+            true => Ok(()),
+            false => Err(FfiError::new(
+                ErrorCode::ModuleNotFound,
+                format!("Error: Module '{}' is not registered", module_str),
+            )),
+        }
     })
 }
 
+/// Queues a call of `function_name` on `module_name`.
+///
+/// Only problems queueing the call are reported here; the outcome of the call
+/// itself is posted to the runtime's Dart port under `message_identifier`.
 #[no_mangle]
 pub unsafe extern "C" fn call_js_function(
+    runtime: RuntimeHandle,       // Runtime handle
     module_name: *const c_char,   // Module name
     function_name: *const c_char, // Function name
     message_identifier: i32,      // Message identifier
@@ -184,99 +272,480 @@ pub unsafe extern "C" fn call_js_function(
     arg_type_ids: *const i32,     // Argument type IDs
     arg_sizes: *const isize,      // Argument sizes (for List<String>, Uint8List)
     args_count: i32,              // Number of arguments
+    timeout_ms: u32,              // Call deadline, `0` for the runtime default
     error: *mut *const c_char,    // Error message
 ) -> u8 {
-    let module_str = unsafe { CStr::from_ptr(module_name).to_str().unwrap() };
-    let function_str = unsafe { CStr::from_ptr(function_name).to_str().unwrap() };
-
-    let runtime_ref = get_runtime_instance();
-
-    let result = utils::tokio_runtime().block_on(async move {
-        let local_set = tokio::task::LocalSet::new();
-
-        local_set
-            .run_until(async move {
-                let mut javascript_runtime = runtime_ref.borrow_mut();
-                let fnc_call = {
-                    // Retrieve function & module state from the module
-                    let (js_function, module_state) = {
-                        let scope = &mut javascript_runtime.handle_scope();
-                        js_runtime::get_js_function(scope, module_str, function_str)?
-                    };
-
-                    // Prepare arguments
-                    let v8_args = {
-                        let scope = &mut javascript_runtime.handle_scope();
-                        let mut args = js_runtime::c_args_to_v8_args_global(
-                            scope,
-                            args,
-                            arg_type_ids,
-                            arg_sizes,
-                            args_count,
-                        );
-
-                        // Insert module state as first argument
-                        args.insert(0, module_state);
-
-                        let msg_id_value: v8::Local<v8::Value> =
-                            v8::Integer::new(scope, message_identifier).into();
-
-                        // Insert message identifier as last argument
-                        args.push(v8::Global::new(scope, msg_id_value));
-                        args
-                    };
-
-                    javascript_runtime.call_with_args(&js_function, &v8_args)
-                };
-
-                // Wait for module execution
-                if let Err(e) = javascript_runtime.run_event_loop(Default::default()).await {
-                    return Err(e.to_string());
-                }
-
-                if let Err(e) = fnc_call.await {
-                    return Err(e.to_string());
-                }
+    into_error_code(error, || {
+        // Arguments are taken first, so external buffers are released even
+        // if the call can't be queued
+        let (sender, send_port) = with_runtime(runtime, |r| (r.sender(), r.send_port()))?;
+        let args = js_runtime::read_c_args(args, arg_type_ids, arg_sizes, args_count, send_port);
+
+        let module_str = check_and_get_cstr(module_name)?;
+        let function_str = check_and_get_cstr(function_name)?;
+
+        runtime_thread::enqueue(
+            &sender,
+            RuntimeCommand::CallFunction {
+                target: CallTarget::Function {
+                    module_name: module_str.to_string(),
+                    function_name: function_str.to_string(),
+                },
+                message_identifier,
+                args,
+                timeout: timeout_from_ms(timeout_ms),
+            },
+        )
+        .map_err(|e| FfiError::new(ErrorCode::RuntimeNotFound, e))
+    })
+}
 
-                Ok(())
-            })
-            .await
-    });
+/// Queues the creation of an instance of `module_name` with its own state,
+/// returned by the module's `init` for `args`.
+///
+/// Once `init` settled, the id of the instance, or the error it failed with,
+/// is posted to the runtime's Dart port under `message_identifier`.
+#[no_mangle]
+pub unsafe extern "C" fn create_module_instance(
+    runtime: RuntimeHandle,
+    module_name: *const c_char,
+    message_identifier: i32,
+    args: *const *const c_void,
+    arg_type_ids: *const i32,
+    arg_sizes: *const isize,
+    args_count: i32,
+    error: *mut *const c_char,
+) -> u8 {
+    into_error_code(error, || {
+        let (sender, send_port) = with_runtime(runtime, |r| (r.sender(), r.send_port()))?;
+        let args = js_runtime::read_c_args(args, arg_type_ids, arg_sizes, args_count, send_port);
+
+        let module_str = check_and_get_cstr(module_name)?;
+
+        runtime_thread::enqueue(
+            &sender,
+            RuntimeCommand::CreateInstance {
+                module_name: module_str.to_string(),
+                args,
+                message_identifier,
+            },
+        )
+        .map_err(|e| FfiError::new(ErrorCode::RuntimeNotFound, e))
+    })
+}
 
-    if let Err(e) = result {
-        *error = CString::new(e).unwrap().into_raw();
-        return 1;
-    }
+/// Queues a call of `function_name` on the module instance `instance_id`,
+/// which receives the instance's state.
+///
+/// The outcome is posted like the outcome of [`call_js_function`].
+#[no_mangle]
+pub unsafe extern "C" fn call_js_instance_function(
+    runtime: RuntimeHandle,
+    instance_id: i32,
+    function_name: *const c_char,
+    message_identifier: i32,
+    args: *const *const c_void,
+    arg_type_ids: *const i32,
+    arg_sizes: *const isize,
+    args_count: i32,
+    timeout_ms: u32,
+    error: *mut *const c_char,
+) -> u8 {
+    into_error_code(error, || {
+        let (sender, send_port) = with_runtime(runtime, |r| (r.sender(), r.send_port()))?;
+        let args = js_runtime::read_c_args(args, arg_type_ids, arg_sizes, args_count, send_port);
+
+        let function_str = check_and_get_cstr(function_name)?;
+
+        runtime_thread::enqueue(
+            &sender,
+            RuntimeCommand::CallFunction {
+                target: CallTarget::InstanceFunction {
+                    instance_id,
+                    function_name: function_str.to_string(),
+                },
+                message_identifier,
+                args,
+                timeout: timeout_from_ms(timeout_ms),
+            },
+        )
+        .map_err(|e| FfiError::new(ErrorCode::RuntimeNotFound, e))
+    })
+}
 
-    0
+/// Disposes the module instance `instance_id`, running the module's
+/// `dispose` hook with its state. Returns `ErrorCode::InstanceNotFound` if it
+/// was already disposed.
+#[no_mangle]
+pub unsafe extern "C" fn dispose_module_instance(
+    runtime: RuntimeHandle,
+    instance_id: i32,
+    error: *mut *const c_char,
+) -> u8 {
+    into_error_code(error, || {
+        let sender = get_runtime_instance(runtime)?;
+        let existed = runtime_thread::request(&sender, |reply| RuntimeCommand::DisposeInstance {
+            instance_id,
+            reply,
+        })
+        .map_err(|e| FfiError::new(ErrorCode::RuntimeNotFound, e))?;
+
+        match existed {
+            true => Ok(()),
+            false => Err(FfiError::new(
+                ErrorCode::InstanceNotFound,
+                format!("Error: Module instance {} does not exist", instance_id),
+            )),
+        }
+    })
 }
 
+/// Queues a call of `method_name` on the object behind the JS handle
+/// `handle_id`.
+///
+/// The outcome is posted like the outcome of [`call_js_function`]; a
+/// released handle or a missing method is reported there as an error.
 #[no_mangle]
-pub unsafe extern "C" fn dispose_runtime() -> u8 {
-    JS_RUNTIME.with(|runtime| {
-        *runtime.borrow_mut() = None;
-    });
+pub unsafe extern "C" fn call_js_handle_method(
+    runtime: RuntimeHandle,
+    handle_id: i32,
+    method_name: *const c_char,
+    message_identifier: i32,
+    args: *const *const c_void,
+    arg_type_ids: *const i32,
+    arg_sizes: *const isize,
+    args_count: i32,
+    timeout_ms: u32,
+    error: *mut *const c_char,
+) -> u8 {
+    into_error_code(error, || {
+        let (sender, send_port) = with_runtime(runtime, |r| (r.sender(), r.send_port()))?;
+        let args = js_runtime::read_c_args(args, arg_type_ids, arg_sizes, args_count, send_port);
+
+        let method_str = check_and_get_cstr(method_name)?;
+
+        runtime_thread::enqueue(
+            &sender,
+            RuntimeCommand::CallFunction {
+                target: CallTarget::Method {
+                    handle_id,
+                    method_name: method_str.to_string(),
+                },
+                message_identifier,
+                args,
+                timeout: timeout_from_ms(timeout_ms),
+            },
+        )
+        .map_err(|e| FfiError::new(ErrorCode::RuntimeNotFound, e))
+    })
+}
 
-    0
+/// Releases the JS handle `handle_id`, letting its object be garbage
+/// collected. Returns `ErrorCode::HandleNotFound` if it was already released.
+#[no_mangle]
+pub unsafe extern "C" fn release_js_handle(
+    runtime: RuntimeHandle,
+    handle_id: i32,
+    error: *mut *const c_char,
+) -> u8 {
+    into_error_code(error, || {
+        let sender = get_runtime_instance(runtime)?;
+        let released = runtime_thread::request(&sender, |reply| RuntimeCommand::ReleaseHandle {
+            handle_id,
+            reply,
+        })
+        .and_then(|result| result)
+        .map_err(|e| FfiError::new(ErrorCode::RuntimeNotFound, e))?;
+
+        match released {
+            true => Ok(()),
+            false => Err(FfiError::new(
+                ErrorCode::HandleNotFound,
+                format!("Error: JS handle {} is not live", handle_id),
+            )),
+        }
+    })
+}
+
+/// Writes the number of JS handles that were not released yet to `count`.
+#[no_mangle]
+pub unsafe extern "C" fn get_js_handle_count(
+    runtime: RuntimeHandle,
+    count: *mut u32,
+    error: *mut *const c_char,
+) -> u8 {
+    into_error_code(error, || {
+        if count.is_null() {
+            return Err(FfiError::new(
+                ErrorCode::InvalidArgument,
+                "Error: count pointer is null",
+            ));
+        }
+
+        let sender = get_runtime_instance(runtime)?;
+        let live = runtime_thread::request(&sender, |reply| RuntimeCommand::CountHandles { reply })
+            .and_then(|result| result)
+            .map_err(|e| FfiError::new(ErrorCode::RuntimeNotFound, e))?;
+
+        *count = live;
+        Ok(())
+    })
+}
+
+/// Cancels the call started with `message_identifier`.
+///
+/// The function's `AbortSignal` is aborted, fetches started under it are
+/// rejected and Dart receives a final message with `cancelled` set.
+#[no_mangle]
+pub unsafe extern "C" fn cancel_js_call(
+    runtime: RuntimeHandle,
+    message_identifier: i32,
+    error: *mut *const c_char,
+) -> u8 {
+    into_error_code(error, || {
+        let sender = get_runtime_instance(runtime)?;
+        runtime_thread::enqueue(&sender, RuntimeCommand::CancelCall { message_identifier })
+            .map_err(|e| FfiError::new(ErrorCode::RuntimeNotFound, e))
+    })
+}
+
+/// Tells the call started with `message_identifier` that Dart consumed `count`
+/// more of its streamed chunks, so it may send that many more.
+#[no_mangle]
+pub unsafe extern "C" fn acknowledge_stream_chunks(
+    runtime: RuntimeHandle,
+    message_identifier: i32,
+    count: u32,
+    error: *mut *const c_char,
+) -> u8 {
+    into_error_code(error, || {
+        let sender = get_runtime_instance(runtime)?;
+        runtime_thread::enqueue(
+            &sender,
+            RuntimeCommand::AcknowledgeChunks {
+                message_identifier,
+                count,
+            },
+        )
+        .map_err(|e| FfiError::new(ErrorCode::RuntimeNotFound, e))
+    })
+}
+
+/// Answers the `Dart.invoke` request `request_id` with `len` bytes of
+/// msgpack at `payload`. An empty payload resolves to `undefined`.
+#[no_mangle]
+pub unsafe extern "C" fn complete_host_call(
+    runtime: RuntimeHandle,
+    request_id: i32,
+    payload: *const u8,
+    len: usize,
+    error: *mut *const c_char,
+) -> u8 {
+    into_error_code(error, || {
+        let sender = get_runtime_instance(runtime)?;
+        let payload = match len {
+            0 => Vec::new(),
+            _ => check_and_get_bytes(payload, len)?.to_vec(),
+        };
+
+        runtime_thread::enqueue(
+            &sender,
+            RuntimeCommand::CompleteHostCall {
+                request_id,
+                result: Ok(payload),
+            },
+        )
+        .map_err(|e| FfiError::new(ErrorCode::RuntimeNotFound, e))
+    })
+}
+
+/// Rejects the `Dart.invoke` request `request_id` with an `Error` carrying
+/// `message`.
+#[no_mangle]
+pub unsafe extern "C" fn fail_host_call(
+    runtime: RuntimeHandle,
+    request_id: i32,
+    message: *const c_char,
+    error: *mut *const c_char,
+) -> u8 {
+    into_error_code(error, || {
+        let sender = get_runtime_instance(runtime)?;
+        let message = check_and_get_cstr(message)?;
+
+        runtime_thread::enqueue(
+            &sender,
+            RuntimeCommand::CompleteHostCall {
+                request_id,
+                result: Err(message.to_string()),
+            },
+        )
+        .map_err(|e| FfiError::new(ErrorCode::RuntimeNotFound, e))
+    })
+}
+
+/// Enqueues a copy of `len` bytes at `data` in the `ReadableStream` passed to
+/// JS for `stream_id`.
+#[no_mangle]
+pub unsafe extern "C" fn push_stream_chunk(
+    runtime: RuntimeHandle,
+    stream_id: i32,
+    data: *const u8,
+    len: usize,
+    error: *mut *const c_char,
+) -> u8 {
+    into_error_code(error, || {
+        let sender = get_runtime_instance(runtime)?;
+        let chunk = check_and_get_bytes(data, len)?.to_vec();
+
+        runtime_thread::enqueue(
+            &sender,
+            RuntimeCommand::PushStreamChunk { stream_id, chunk },
+        )
+        .map_err(|e| FfiError::new(ErrorCode::RuntimeNotFound, e))
+    })
+}
+
+/// Ends the `ReadableStream` passed to JS for `stream_id` once the chunks
+/// pushed before have been read.
+#[no_mangle]
+pub unsafe extern "C" fn close_stream(
+    runtime: RuntimeHandle,
+    stream_id: i32,
+    error: *mut *const c_char,
+) -> u8 {
+    into_error_code(error, || {
+        let sender = get_runtime_instance(runtime)?;
+        runtime_thread::enqueue(
+            &sender,
+            RuntimeCommand::CloseStream {
+                stream_id,
+                error: None,
+            },
+        )
+        .map_err(|e| FfiError::new(ErrorCode::RuntimeNotFound, e))
+    })
+}
+
+/// Errors the `ReadableStream` passed to JS for `stream_id` with `message`,
+/// discarding chunks that have not been read yet.
+#[no_mangle]
+pub unsafe extern "C" fn error_stream(
+    runtime: RuntimeHandle,
+    stream_id: i32,
+    message: *const c_char,
+    error: *mut *const c_char,
+) -> u8 {
+    into_error_code(error, || {
+        let sender = get_runtime_instance(runtime)?;
+        let message = check_and_get_cstr(message)?;
+
+        runtime_thread::enqueue(
+            &sender,
+            RuntimeCommand::CloseStream {
+                stream_id,
+                error: Some(message.to_string()),
+            },
+        )
+        .map_err(|e| FfiError::new(ErrorCode::RuntimeNotFound, e))
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn dispose_runtime(runtime: RuntimeHandle, error: *mut *const c_char) -> u8 {
+    into_error_code(error, || {
+        let removed = js_runtimes().lock().unwrap().remove(&runtime);
+
+        match removed {
+            Some(runtime) => {
+                runtime.dispose();
+                Ok(())
+            }
+            None => Err(FfiError::new(
+                ErrorCode::RuntimeNotFound,
+                format!("Error: JS Runtime {} does not exist", runtime),
+            )),
+        }
+    })
+}
+
+// Runs an export returning a status code behind a panic guard
+unsafe fn into_error_code(
+    error: *mut *const c_char,
+    f: impl FnOnce() -> Result<(), FfiError>,
+) -> u8 {
+    clear_error(error);
+
+    match catch_panic(f) {
+        Ok(()) => ErrorCode::Ok as u8,
+        Err(e) => {
+            set_error(error, &e.message);
+            e.code as u8
+        }
+    }
+}
+
+// Runs an export returning a runtime handle behind a panic guard
+unsafe fn into_handle(
+    error: *mut *const c_char,
+    f: impl FnOnce() -> Result<RuntimeHandle, FfiError>,
+) -> RuntimeHandle {
+    clear_error(error);
+
+    match catch_panic(f) {
+        Ok(handle) => handle,
+        Err(e) => {
+            set_error(error, &e.message);
+            0
+        }
+    }
+}
+
+unsafe fn clear_error(error: *mut *const c_char) {
+    if !error.is_null() {
+        *error = std::ptr::null();
+    }
 }
 
 // Helper function to set error messages
 unsafe fn set_error(error: *mut *const c_char, msg: &str) {
     if !error.is_null() {
-        *error = CString::new(msg).unwrap().into_raw();
+        let msg = CString::new(msg.replace('\0', "")).unwrap_or_default();
+        *error = msg.into_raw();
     }
 }
 
 // Helper to safely convert a *const c_char into a Rust &str
-unsafe fn check_and_get_cstr(ptr: *const c_char) -> Result<&'static str, &'static str> {
+unsafe fn check_and_get_cstr(ptr: *const c_char) -> Result<&'static str, FfiError> {
     if ptr.is_null() {
-        return Err("Received null pointer");
+        return Err(FfiError::new(
+            ErrorCode::InvalidArgument,
+            "Received null pointer",
+        ));
     }
 
-    match CStr::from_ptr(ptr).to_str() {
-        Ok(val) => Ok(val),
-        Err(_) => Err("Invalid UTF-8 in pointer string"),
+    CStr::from_ptr(ptr).to_str().map_err(|_| {
+        FfiError::new(
+            ErrorCode::InvalidArgument,
+            "Invalid UTF-8 in pointer string",
+        )
+    })
+}
+
+// Helper to borrow `len` bytes at `ptr`
+unsafe fn check_and_get_bytes(ptr: *const u8, len: usize) -> Result<&'static [u8], FfiError> {
+    if ptr.is_null() {
+        return Err(FfiError::new(
+            ErrorCode::InvalidArgument,
+            "Received null pointer",
+        ));
     }
+
+    Ok(std::slice::from_raw_parts(ptr, len))
+}
+
+// Helper to map a millisecond timeout from Dart, where `0` means none
+fn timeout_from_ms(timeout_ms: u32) -> Option<Duration> {
+    (timeout_ms > 0).then(|| Duration::from_millis(timeout_ms as u64))
 }
 
 fn extract_filepath_from_source(source: &str) -> Option<String> {
@@ -0,0 +1,177 @@
+//! Permissions scoped to the module whose code is running.
+
+use std::{
+    borrow::Cow,
+    collections::HashMap,
+    path::{Path, PathBuf},
+    ptr::NonNull,
+};
+
+use deno_runtime::{
+    deno_core::url::Url,
+    deno_fetch::FetchPermissions,
+    deno_net::NetPermissions,
+    deno_permissions::{PermissionCheckError, PermissionsContainer},
+    deno_web::TimersPermission,
+};
+
+/// Private key of the async context entry naming the module a piece of
+/// JavaScript belongs to. Set by Rust for calls and around a module's
+/// evaluation, `init` and `dispose`. JavaScript can't read or write private
+/// keys, so a module can't pass itself off as another one.
+pub const MODULE_CONTEXT_KEY: &str = "globe.module";
+
+/// Permissions handed to Deno's ops.
+///
+/// Every check goes through the runtime's permissions. While code of a module
+/// registered with its own permissions runs, the check must pass that scope
+/// too, so a module can be restricted further but never be granted more than
+/// the runtime. Once any module has a scope, code whose module can't be told
+/// is denied everything.
+pub struct ScopedPermissions {
+    runtime: PermissionsContainer,
+    modules: HashMap<String, PermissionsContainer>,
+    unknown_module: PermissionsContainer,
+    isolate: Option<NonNull<v8::Isolate>>,
+}
+
+impl ScopedPermissions {
+    /// `deny_all` is checked for code whose module can't be told.
+    pub fn new(runtime: PermissionsContainer, deny_all: PermissionsContainer) -> Self {
+        ScopedPermissions {
+            runtime,
+            modules: HashMap::new(),
+            unknown_module: deny_all,
+            isolate: None,
+        }
+    }
+
+    /// Lets checks look up the running module in the async context of
+    /// `isolate`. Until then only the runtime's permissions apply.
+    pub fn attach(&mut self, isolate: NonNull<v8::Isolate>) {
+        self.isolate = Some(isolate);
+    }
+
+    /// Sets the scope of `module_name`, or removes it so the module only has
+    /// the runtime's permissions. Returns the scope it had before.
+    pub fn set_module_scope(
+        &mut self,
+        module_name: &str,
+        scope: Option<PermissionsContainer>,
+    ) -> Option<PermissionsContainer> {
+        match scope {
+            Some(scope) => self.modules.insert(module_name.to_string(), scope),
+            None => self.modules.remove(module_name),
+        }
+    }
+
+    pub fn check_env(&mut self, key: &str) -> Result<(), PermissionCheckError> {
+        if let Some(module) = self.module_scope() {
+            module.check_env(key)?;
+        }
+        self.runtime.check_env(key)
+    }
+
+    fn module_scope(&mut self) -> Option<&mut PermissionsContainer> {
+        if self.modules.is_empty() {
+            return None;
+        }
+
+        match self.running_module() {
+            Some(module_name) => self.modules.get_mut(&module_name),
+            None => Some(&mut self.unknown_module),
+        }
+    }
+
+    /// The module of the JavaScript running on the isolate, if it can be told.
+    pub fn running_module(&self) -> Option<String> {
+        let mut isolate = self.isolate?;
+
+        // SAFETY: checks run inside ops, on the isolate's thread while the
+        // runtime owning it is alive. A callback scope is the way to enter
+        // V8 while the op's own scopes are on the stack.
+        let scope = &mut unsafe { v8::CallbackScope::new(isolate.as_mut()) };
+        let scope = &mut v8::HandleScope::new(scope);
+        let context = scope.get_current_context();
+        let scope = &mut v8::ContextScope::new(scope, context);
+
+        let async_context = scope.get_continuation_preserved_embedder_data();
+        let async_context = v8::Local::<v8::Object>::try_from(async_context).ok()?;
+        let key = module_context_key(scope);
+        let module_name = async_context.get_private(scope, key)?;
+
+        module_name
+            .is_string()
+            .then(|| module_name.to_rust_string_lossy(scope))
+    }
+}
+
+/// The private key of [`MODULE_CONTEXT_KEY`].
+pub fn module_context_key<'s>(scope: &mut v8::HandleScope<'s>) -> v8::Local<'s, v8::Private> {
+    let name = v8::String::new(scope, MODULE_CONTEXT_KEY).unwrap();
+    v8::Private::for_api(scope, Some(name))
+}
+
+impl TimersPermission for ScopedPermissions {
+    fn allow_hrtime(&mut self) -> bool {
+        self.runtime.allow_hrtime()
+    }
+}
+
+impl FetchPermissions for ScopedPermissions {
+    fn check_net_url(&mut self, url: &Url, api_name: &str) -> Result<(), PermissionCheckError> {
+        if let Some(module) = self.module_scope() {
+            FetchPermissions::check_net_url(module, url, api_name)?;
+        }
+        FetchPermissions::check_net_url(&mut self.runtime, url, api_name)
+    }
+
+    fn check_read<'a>(
+        &mut self,
+        path: &'a Path,
+        api_name: &str,
+    ) -> Result<Cow<'a, Path>, PermissionCheckError> {
+        if let Some(module) = self.module_scope() {
+            FetchPermissions::check_read(module, path, api_name)?;
+        }
+        FetchPermissions::check_read(&mut self.runtime, path, api_name)
+    }
+}
+
+impl NetPermissions for ScopedPermissions {
+    fn check_net<T: AsRef<str>>(
+        &mut self,
+        host: &(T, Option<u16>),
+        api_name: &str,
+    ) -> Result<(), PermissionCheckError> {
+        if let Some(module) = self.module_scope() {
+            NetPermissions::check_net(module, host, api_name)?;
+        }
+        NetPermissions::check_net(&mut self.runtime, host, api_name)
+    }
+
+    fn check_read(&mut self, path: &str, api_name: &str) -> Result<PathBuf, PermissionCheckError> {
+        if let Some(module) = self.module_scope() {
+            NetPermissions::check_read(module, path, api_name)?;
+        }
+        NetPermissions::check_read(&mut self.runtime, path, api_name)
+    }
+
+    fn check_write(&mut self, path: &str, api_name: &str) -> Result<PathBuf, PermissionCheckError> {
+        if let Some(module) = self.module_scope() {
+            NetPermissions::check_write(module, path, api_name)?;
+        }
+        NetPermissions::check_write(&mut self.runtime, path, api_name)
+    }
+
+    fn check_write_path<'a>(
+        &mut self,
+        path: &'a Path,
+        api_name: &str,
+    ) -> Result<Cow<'a, Path>, PermissionCheckError> {
+        if let Some(module) = self.module_scope() {
+            NetPermissions::check_write_path(module, path, api_name)?;
+        }
+        NetPermissions::check_write_path(&mut self.runtime, path, api_name)
+    }
+}
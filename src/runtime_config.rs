@@ -0,0 +1,291 @@
+//! Settings a runtime is created with.
+//!
+//! Hosts pass them to `init_runtime_with_config` as a msgpack-encoded map.
+//! Missing fields take their defaults and unknown fields are ignored, so a
+//! config written for a newer version of the runtime still loads in an older
+//! one.
+
+use std::time::Duration;
+
+use serde::Deserialize;
+
+/// Latest config version understood by this runtime.
+pub const CONFIG_VERSION: u32 = 1;
+
+const MEGABYTE: usize = 1024 * 1024;
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct RuntimeConfig {
+    /// Version of the config format the host wrote.
+    pub version: u32,
+    /// Deadline in milliseconds for calls that don't specify their own
+    /// timeout. `0` disables it.
+    pub default_call_timeout_ms: u64,
+    /// Deadline in milliseconds for registering a module, including its
+    /// top-level await and `init`, and for creating an instance. `0` disables
+    /// it.
+    pub module_load_timeout_ms: u64,
+    /// Chunks a call may stream before `Dart.stream_value` waits for Dart to
+    /// acknowledge them. `0` disables flow control.
+    pub stream_window: u32,
+    pub heap: HeapConfig,
+    /// Flags passed to V8 before the first isolate is created. They apply to
+    /// the whole process, so every later runtime must ask for the same flags.
+    pub v8_flags: Vec<String>,
+    pub module_resolution: ModuleResolutionConfig,
+    pub extensions: ExtensionsConfig,
+    pub tokio: TokioConfig,
+    /// Everything is allowed when unset.
+    pub permissions: Option<PermissionsConfig>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct HeapConfig {
+    /// Size the V8 heap starts out with, in megabytes.
+    pub initial_size_mb: usize,
+    /// Size the V8 heap may grow to, in megabytes. `0` lets V8 pick a limit.
+    pub max_size_mb: usize,
+    /// Replace the isolate and register its modules again after it runs out
+    /// of memory, rather than keeping the exhausted heap around.
+    pub recycle_on_out_of_memory: bool,
+}
+
+/// How bare specifiers are resolved to files in `node_modules`.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct ModuleResolutionConfig {
+    pub extensions: Vec<String>,
+    /// `package.json` fields holding a package's entry point, in order of
+    /// preference.
+    pub main_fields: Vec<String>,
+}
+
+/// Optional Deno extensions loaded into the runtime.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct ExtensionsConfig {
+    /// TCP, UDP and TLS sockets from `deno_net`.
+    pub net: bool,
+}
+
+/// Allowlists enforced through `deno_permissions`. An empty list denies
+/// everything of its kind, `"*"` allows everything.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct PermissionsConfig {
+    /// Hosts, optionally with a port (`host:port`), that fetches and sockets
+    /// may connect to.
+    pub net: Vec<String>,
+    /// Paths that may be read, including the files modules are imported from.
+    pub read: Vec<String>,
+    /// Environment variables readable through `Deno.env`.
+    pub env: Vec<String>,
+    /// Origins remote modules may be imported from.
+    pub import: Vec<String>,
+}
+
+impl PermissionsConfig {
+    /// Decodes the msgpack-encoded permissions of a single module.
+    pub fn from_msgpack(bytes: &[u8]) -> Result<Self, String> {
+        rmp_serde::from_slice(bytes).map_err(|e| format!("Error: Invalid permissions: {}", e))
+    }
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct TokioConfig {
+    /// Limit of threads used for blocking work, at least 1. Tokio's default
+    /// when unset.
+    pub max_blocking_threads: Option<usize>,
+}
+
+impl Default for RuntimeConfig {
+    fn default() -> Self {
+        RuntimeConfig {
+            version: CONFIG_VERSION,
+            default_call_timeout_ms: 0,
+            module_load_timeout_ms: 30_000,
+            stream_window: 16,
+            heap: HeapConfig::default(),
+            v8_flags: Vec::new(),
+            module_resolution: ModuleResolutionConfig::default(),
+            extensions: ExtensionsConfig::default(),
+            tokio: TokioConfig::default(),
+            permissions: None,
+        }
+    }
+}
+
+impl Default for ModuleResolutionConfig {
+    fn default() -> Self {
+        ModuleResolutionConfig {
+            extensions: vec![".mjs".to_string()],
+            main_fields: vec!["module".to_string()],
+        }
+    }
+}
+
+impl Default for ExtensionsConfig {
+    fn default() -> Self {
+        ExtensionsConfig { net: true }
+    }
+}
+
+impl RuntimeConfig {
+    /// Decodes a msgpack-encoded config. Only `version` is required.
+    pub fn from_msgpack(bytes: &[u8]) -> Result<Self, String> {
+        #[derive(Deserialize)]
+        struct Versioned {
+            version: Option<u32>,
+        }
+
+        let versioned: Versioned = rmp_serde::from_slice(bytes)
+            .map_err(|e| format!("Error: Invalid runtime config: {}", e))?;
+        if matches!(versioned.version, None | Some(0)) {
+            return Err("Error: Runtime config is missing its `version`.".to_string());
+        }
+
+        let config: RuntimeConfig = rmp_serde::from_slice(bytes)
+            .map_err(|e| format!("Error: Invalid runtime config: {}", e))?;
+
+        // Tokio panics building a runtime without blocking threads
+        if config.tokio.max_blocking_threads == Some(0) {
+            return Err("Error: `max_blocking_threads` must be at least 1.".to_string());
+        }
+
+        Ok(config)
+    }
+
+    pub fn default_call_timeout(&self) -> Option<Duration> {
+        (self.default_call_timeout_ms > 0)
+            .then(|| Duration::from_millis(self.default_call_timeout_ms))
+    }
+
+    pub fn module_load_timeout(&self) -> Option<Duration> {
+        (self.module_load_timeout_ms > 0)
+            .then(|| Duration::from_millis(self.module_load_timeout_ms))
+    }
+
+    /// Initial heap size in bytes.
+    pub fn initial_heap_size(&self) -> usize {
+        self.heap.initial_size_mb * MEGABYTE
+    }
+
+    /// Heap limit in bytes, if one is configured.
+    pub fn max_heap_size(&self) -> Option<usize> {
+        (self.heap.max_size_mb > 0).then(|| self.heap.max_size_mb * MEGABYTE)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use serde::Serialize;
+
+    use super::*;
+
+    #[derive(Serialize)]
+    struct TokioOnly {
+        version: u32,
+        tokio: BTreeMap<&'static str, usize>,
+    }
+
+    fn encode(config: &impl Serialize) -> Vec<u8> {
+        rmp_serde::to_vec_named(config).unwrap()
+    }
+
+    #[test]
+    fn fills_in_defaults() {
+        let config =
+            RuntimeConfig::from_msgpack(&encode(&BTreeMap::from([("version", 1)]))).unwrap();
+
+        assert_eq!(config.default_call_timeout(), None);
+        assert_eq!(config.module_load_timeout(), Some(Duration::from_secs(30)));
+        assert_eq!(config.stream_window, 16);
+        assert_eq!(config.max_heap_size(), None);
+        assert_eq!(config.tokio.max_blocking_threads, None);
+        assert!(config.extensions.net);
+        assert!(config.permissions.is_none());
+    }
+
+    #[test]
+    fn ignores_unknown_fields() {
+        let bytes = encode(&BTreeMap::from([("version", 2), ("added_later", 7)]));
+        let config = RuntimeConfig::from_msgpack(&bytes).unwrap();
+
+        assert_eq!(config.version, 2);
+    }
+
+    #[test]
+    fn converts_sizes_and_timeouts() {
+        let bytes = encode(&BTreeMap::from([
+            ("version", 1),
+            ("default_call_timeout_ms", 250),
+            ("module_load_timeout_ms", 0),
+        ]));
+        let config = RuntimeConfig::from_msgpack(&bytes).unwrap();
+
+        assert_eq!(
+            config.default_call_timeout(),
+            Some(Duration::from_millis(250))
+        );
+        assert_eq!(config.module_load_timeout(), None);
+
+        let heap = HeapConfig {
+            initial_size_mb: 2,
+            max_size_mb: 64,
+            recycle_on_out_of_memory: false,
+        };
+        let config = RuntimeConfig {
+            heap,
+            ..Default::default()
+        };
+        assert_eq!(config.initial_heap_size(), 2 * MEGABYTE);
+        assert_eq!(config.max_heap_size(), Some(64 * MEGABYTE));
+    }
+
+    #[test]
+    fn requires_a_version() {
+        let missing = encode(&BTreeMap::from([("stream_window", 4)]));
+        let zero = encode(&BTreeMap::from([("version", 0)]));
+
+        for bytes in [missing, zero] {
+            let error = RuntimeConfig::from_msgpack(&bytes).unwrap_err();
+            assert!(error.contains("missing its `version`"), "{}", error);
+        }
+    }
+
+    #[test]
+    fn rejects_invalid_msgpack() {
+        let error = RuntimeConfig::from_msgpack(&[0xc1]).unwrap_err();
+        assert!(
+            error.starts_with("Error: Invalid runtime config"),
+            "{}",
+            error
+        );
+
+        let bytes = encode(&BTreeMap::from([("version", "1")]));
+        assert!(RuntimeConfig::from_msgpack(&bytes).is_err());
+    }
+
+    #[test]
+    fn requires_blocking_threads() {
+        let config = |max_blocking_threads| TokioOnly {
+            version: 1,
+            tokio: BTreeMap::from([("max_blocking_threads", max_blocking_threads)]),
+        };
+
+        let error = RuntimeConfig::from_msgpack(&encode(&config(0))).unwrap_err();
+        assert!(
+            error.contains("`max_blocking_threads` must be at least 1"),
+            "{}",
+            error
+        );
+
+        let config = RuntimeConfig::from_msgpack(&encode(&config(1))).unwrap();
+        assert_eq!(config.tokio.max_blocking_threads, Some(1));
+    }
+}
@@ -0,0 +1,813 @@
+//! The thread each runtime runs on, executing the commands Dart queues.
+
+use std::{
+    cell::{Cell, RefCell},
+    collections::{HashMap, VecDeque},
+    future::Future,
+    rc::Rc,
+    thread::JoinHandle,
+    time::Duration,
+};
+
+use deno_runtime::{deno_core::JsRuntime, deno_permissions::PermissionsContainer};
+use tokio::{
+    sync::{mpsc, oneshot},
+    task::AbortHandle,
+};
+
+use crate::{
+    dart_message::DartMessage,
+    dart_runtime::{send_dart_message, CallEnded},
+    dart_value,
+    js_runtime::{self, CallTarget, FFIArg, LoadDriver, LoadError},
+    runtime_config::{PermissionsConfig, RuntimeConfig},
+    utils,
+    watchdog::{Running, Watchdog},
+};
+
+/// Work queued for a runtime thread by the FFI exports.
+pub enum RuntimeCommand {
+    /// Registers the module, posting the outcome to Dart under
+    /// `message_identifier` once its `init` settled.
+    RegisterModule {
+        module_name: String,
+        file_path: String,
+        source: String,
+        args: Vec<FFIArg>,
+        permissions: Option<PermissionsConfig>,
+        message_identifier: i32,
+    },
+    IsModuleRegistered {
+        module_name: String,
+        reply: oneshot::Sender<bool>,
+    },
+    /// Runs the module's `init` for a new instance, posting its id to Dart.
+    CreateInstance {
+        module_name: String,
+        args: Vec<FFIArg>,
+        message_identifier: i32,
+    },
+    /// Runs the module's `dispose` hook for the instance, replying whether
+    /// it existed. Errors thrown by the hook are reported as runtime errors,
+    /// the instance is gone either way.
+    DisposeInstance {
+        instance_id: i32,
+        reply: oneshot::Sender<bool>,
+    },
+    CallFunction {
+        target: CallTarget,
+        message_identifier: i32,
+        args: Vec<FFIArg>,
+        timeout: Option<Duration>,
+    },
+    CancelCall {
+        message_identifier: i32,
+    },
+    AcknowledgeChunks {
+        message_identifier: i32,
+        count: u32,
+    },
+    PushStreamChunk {
+        stream_id: i32,
+        chunk: Vec<u8>,
+    },
+    /// Settles the promise of a `Dart.invoke` request with the
+    /// msgpack-encoded result or an error message.
+    CompleteHostCall {
+        request_id: i32,
+        result: Result<Vec<u8>, String>,
+    },
+    /// Closes the stream, or errors it when `error` is set.
+    CloseStream {
+        stream_id: i32,
+        error: Option<String>,
+    },
+    /// Forgets a `Dart.handle` object, replying whether it existed.
+    ReleaseHandle {
+        handle_id: i32,
+        reply: oneshot::Sender<Result<bool, String>>,
+    },
+    CountHandles {
+        reply: oneshot::Sender<Result<u32, String>>,
+    },
+    /// Sent by the [`Watchdog`] once a call has run past its deadline.
+    CallTimedOut {
+        message_identifier: i32,
+    },
+}
+
+/// A `JsRuntime` living on its own OS thread.
+///
+/// The thread keeps polling the event loop while there is pending work and
+/// picks up queued commands in between, so callers never wait for unrelated
+/// timers or fetches to finish.
+pub struct RuntimeThread {
+    send_port: i64,
+    sender: mpsc::UnboundedSender<RuntimeCommand>,
+    thread: JoinHandle<()>,
+}
+
+impl RuntimeThread {
+    pub fn spawn(send_port: i64, config: RuntimeConfig) -> Result<Self, String> {
+        // Before the thread creates the isolate, which initializes V8
+        js_runtime::set_v8_flags(&config.v8_flags)?;
+        let permissions = js_runtime::create_permissions(config.permissions.as_ref())?;
+        let (sender, receiver) = mpsc::unbounded_channel();
+        let weak_sender = sender.downgrade();
+
+        let thread = std::thread::Builder::new()
+            .name("globe-runtime".to_string())
+            .spawn(move || run(send_port, config, permissions, weak_sender, receiver))
+            .map_err(|e| format!("Failed to spawn runtime thread: {}", e))?;
+
+        Ok(RuntimeThread {
+            send_port,
+            sender,
+            thread,
+        })
+    }
+
+    pub fn sender(&self) -> mpsc::UnboundedSender<RuntimeCommand> {
+        self.sender.clone()
+    }
+
+    /// The Dart port messages of this runtime are posted to.
+    pub fn send_port(&self) -> i64 {
+        self.send_port
+    }
+
+    /// Stops accepting commands and waits for the runtime thread to exit.
+    pub fn dispose(self) {
+        drop(self.sender);
+        let _ = self.thread.join();
+    }
+}
+
+/// Queues `command` without waiting for it to run.
+pub fn enqueue(
+    sender: &mpsc::UnboundedSender<RuntimeCommand>,
+    command: RuntimeCommand,
+) -> Result<(), String> {
+    sender
+        .send(command)
+        .map_err(|_| "Error: JS Runtime has been disposed.".to_string())
+}
+
+/// Queues the command built by `command` and blocks until the runtime thread
+/// replies.
+pub fn request<T>(
+    sender: &mpsc::UnboundedSender<RuntimeCommand>,
+    command: impl FnOnce(oneshot::Sender<T>) -> RuntimeCommand,
+) -> Result<T, String> {
+    let (reply, response) = oneshot::channel();
+    enqueue(sender, command(reply))?;
+
+    response
+        .blocking_recv()
+        .map_err(|_| "Error: JS Runtime stopped before replying.".to_string())
+}
+
+struct RuntimeState {
+    send_port: i64,
+    config: RuntimeConfig,
+    permissions: PermissionsContainer,
+    watchdog: Watchdog,
+    /// Set by the near-heap-limit callback once the isolate runs out of memory.
+    out_of_memory: Rc<Cell<bool>>,
+    /// Calls whose JavaScript has not sent the final message yet, with the
+    /// task waiting for their `invoke` once it is spawned.
+    calls: Rc<RefCell<HashMap<i32, Option<AbortHandle>>>>,
+    /// Modules to register again when the isolate is recycled.
+    modules: RefCell<Vec<ModuleRegistration>>,
+    /// Module instances to create again when the isolate is recycled.
+    instances: RefCell<HashMap<i32, InstanceRegistration>>,
+    last_instance_id: Cell<i32>,
+}
+
+struct ModuleRegistration {
+    module_name: String,
+    file_path: String,
+    source: String,
+    args: Vec<FFIArg>,
+    permissions: Option<PermissionsConfig>,
+}
+
+struct InstanceRegistration {
+    module_name: String,
+    args: Vec<FFIArg>,
+}
+
+/// Commands queued for the runtime thread, with those a module load put off
+/// first.
+struct Commands {
+    receiver: mpsc::UnboundedReceiver<RuntimeCommand>,
+    deferred: VecDeque<RuntimeCommand>,
+}
+
+impl Commands {
+    async fn recv(&mut self) -> Option<RuntimeCommand> {
+        match self.deferred.pop_front() {
+            Some(command) => Some(command),
+            None => self.receiver.recv().await,
+        }
+    }
+}
+
+/// Waits for module loads while delivering the data Dart sends to
+/// JavaScript, as `init` may wait for answers to `Dart.invoke` or chunks of
+/// stream arguments. Dart waits for the answers to queries, so those are
+/// answered right away too. Other commands run once the load is done.
+struct CommandLoadDriver<'a> {
+    state: &'a RuntimeState,
+    commands: &'a mut Commands,
+}
+
+impl LoadDriver for CommandLoadDriver<'_> {
+    async fn drive<T, E: Into<LoadError>>(
+        &mut self,
+        runtime: &mut JsRuntime,
+        mut load: impl Future<Output = Result<T, E>> + Unpin,
+    ) -> Result<T, LoadError> {
+        let mut event_loop_idle = false;
+
+        loop {
+            tokio::select! {
+                biased;
+                result = &mut load => return result.map_err(Into::into),
+                result = runtime.run_event_loop(Default::default()), if !event_loop_idle => {
+                    result.map_err(LoadError::from)?;
+                    event_loop_idle = true;
+                }
+                command = self.commands.receiver.recv() => {
+                    let Some(command) = command else {
+                        return Err("Error: JS Runtime has been disposed.".to_string().into());
+                    };
+                    let command = deliver_to_js(runtime, self.state.send_port, command)
+                        .and_then(|command| {
+                            answer_query(runtime, self.state, command, &mut self.commands.deferred)
+                        });
+                    if let Some(command) = command {
+                        self.commands.deferred.push_back(command);
+                    }
+                    event_loop_idle = false;
+                }
+            }
+        }
+    }
+}
+
+enum Event {
+    Command(RuntimeCommand),
+    EventLoopIdle,
+    /// The event loop stopped at an uncaught error, with work still pending.
+    EventLoopError(String),
+    Closed,
+}
+
+fn run(
+    send_port: i64,
+    config: RuntimeConfig,
+    permissions: PermissionsContainer,
+    weak_sender: mpsc::WeakUnboundedSender<RuntimeCommand>,
+    receiver: mpsc::UnboundedReceiver<RuntimeCommand>,
+) {
+    let tokio_runtime = utils::new_tokio_runtime(&config.tokio);
+    let local_set = tokio::task::LocalSet::new();
+
+    local_set.block_on(&tokio_runtime, async move {
+        let state = RuntimeState {
+            send_port,
+            config,
+            permissions,
+            watchdog: Watchdog::spawn(weak_sender),
+            out_of_memory: Default::default(),
+            calls: Default::default(),
+            modules: Default::default(),
+            instances: Default::default(),
+            last_instance_id: Default::default(),
+        };
+        let mut commands = Commands {
+            receiver,
+            deferred: VecDeque::new(),
+        };
+        let mut javascript_runtime = create_runtime(&state);
+        let mut event_loop_idle = true;
+
+        loop {
+            let event = if event_loop_idle {
+                commands.recv().await.map_or(Event::Closed, Event::Command)
+            } else {
+                let _running = state.watchdog.enter(Running::EventLoop);
+                tokio::select! {
+                    command = commands.recv() => command.map_or(Event::Closed, Event::Command),
+                    result = javascript_runtime.run_event_loop(Default::default()) => {
+                        match result {
+                            Ok(()) => Event::EventLoopIdle,
+                            Err(e) => Event::EventLoopError(e.to_string()),
+                        }
+                    }
+                }
+            };
+
+            // The event loop fails with the termination of JavaScript that
+            // timed out or ran out of memory, which the calls report instead
+            let terminated = state.watchdog.is_terminating()
+                || state.out_of_memory.get()
+                || javascript_runtime.v8_isolate().is_execution_terminating();
+            if recover_from_termination(&mut javascript_runtime, &state) {
+                javascript_runtime =
+                    recycle_runtime(javascript_runtime, &state, &mut commands).await;
+            }
+
+            match event {
+                Event::Command(command) => {
+                    handle_command(&mut javascript_runtime, &state, &mut commands, command).await;
+                    event_loop_idle = false;
+
+                    // The watchdog may have terminated a module load
+                    if recover_from_termination(&mut javascript_runtime, &state) {
+                        javascript_runtime =
+                            recycle_runtime(javascript_runtime, &state, &mut commands).await;
+                    }
+                }
+                Event::EventLoopIdle => event_loop_idle = true,
+                Event::EventLoopError(_) if terminated => {}
+                // There is no telling which call the error came from, so it
+                // is reported for the runtime and the remaining work goes on
+                Event::EventLoopError(error) => {
+                    dart_value::post_runtime_error(state.send_port, &error);
+                }
+                Event::Closed => break,
+            }
+        }
+    });
+}
+
+fn create_runtime(state: &RuntimeState) -> JsRuntime {
+    let mut javascript_runtime =
+        js_runtime::get_runtime(state.send_port, &state.config, state.permissions.clone());
+    js_runtime::add_heap_limit_guard(&mut javascript_runtime, state.out_of_memory.clone());
+    state
+        .watchdog
+        .attach(javascript_runtime.v8_isolate().thread_safe_handle());
+
+    let calls = state.calls.clone();
+    let watchdog = state.watchdog.clone();
+    javascript_runtime
+        .op_state()
+        .borrow_mut()
+        .put(CallEnded(Box::new(move |message_identifier| {
+            calls.borrow_mut().remove(&message_identifier);
+            watchdog.finish(message_identifier);
+        })));
+
+    javascript_runtime
+}
+
+/// Why a module or instance created with [`FFIArg::is_isolate_bound`]
+/// arguments isn't created again when the isolate is recycled.
+const ISOLATE_BOUND_ARGS_ERROR: &str =
+    "its `init` arguments include streams, callbacks or handles of the replaced isolate";
+
+/// Replaces an isolate that ran out of memory with a fresh one that has the
+/// same modules registered.
+///
+/// Streams, callbacks and handles passed to `init` belonged to the replaced
+/// isolate. Modules and instances created with them are dropped and reported
+/// rather than initialized with arguments that no longer work.
+async fn recycle_runtime(
+    javascript_runtime: JsRuntime,
+    state: &RuntimeState,
+    commands: &mut Commands,
+) -> JsRuntime {
+    // Isolates must be dropped before the next one is created
+    drop(javascript_runtime);
+
+    let mut javascript_runtime = create_runtime(state);
+
+    // Taken while `init`s run, so no borrow is held across the awaits
+    let mut modules = state.modules.take();
+    let mut instances = state.instances.take();
+    let mut driver = CommandLoadDriver { state, commands };
+
+    modules.retain(|module| {
+        let reusable = !module.args.iter().any(FFIArg::is_isolate_bound);
+        if !reusable {
+            let message = format!(
+                "Failed to register `{}` again: {}",
+                module.module_name, ISOLATE_BOUND_ARGS_ERROR
+            );
+            dart_value::post_runtime_error(state.send_port, &message);
+        }
+        reusable
+    });
+    instances.retain(|instance_id, instance| {
+        let error = if instance.args.iter().any(FFIArg::is_isolate_bound) {
+            ISOLATE_BOUND_ARGS_ERROR
+        } else if !modules
+            .iter()
+            .any(|m| m.module_name == instance.module_name)
+        {
+            "its module was not registered again"
+        } else {
+            return true;
+        };
+        let message = format!("Failed to create instance {} again: {}", instance_id, error);
+        dart_value::post_runtime_error(state.send_port, &message);
+        false
+    });
+
+    for module in &modules {
+        let registration = js_runtime::register_module(
+            &mut javascript_runtime,
+            &mut driver,
+            &module.module_name,
+            module.file_path.clone(),
+            module.source.clone(),
+            &module.args,
+            module.permissions.as_ref(),
+        );
+        let result = watch_module_load(state, registration).await;
+        cancel_load_termination(&mut javascript_runtime, state);
+        if let Err(e) = result {
+            let message = format!("Failed to register `{}` again: {}", module.module_name, e);
+            dart_value::post_runtime_error(state.send_port, &message);
+        }
+    }
+    for (instance_id, instance) in &instances {
+        let creation = js_runtime::create_module_instance(
+            &mut javascript_runtime,
+            &mut driver,
+            &instance.module_name,
+            *instance_id,
+            &instance.args,
+        );
+        let result = watch_module_load(state, creation).await;
+        cancel_load_termination(&mut javascript_runtime, state);
+        if let Err(e) = result {
+            let message = format!("Failed to create instance {} again: {}", instance_id, e);
+            dart_value::post_runtime_error(state.send_port, &message);
+        }
+    }
+
+    state.modules.replace(modules);
+    state.instances.replace(instances);
+
+    javascript_runtime
+}
+
+/// Runs `load`, registering a module or creating an instance, for at most
+/// the module load timeout. Loads waiting on the event loop time out here,
+/// the watchdog terminates JavaScript of the load that never yields.
+async fn watch_module_load<T>(
+    state: &RuntimeState,
+    load: impl Future<Output = Result<T, LoadError>>,
+) -> Result<T, LoadError> {
+    let Some(timeout) = state.config.module_load_timeout() else {
+        return load.await;
+    };
+
+    let _running = state.watchdog.enter(Running::ModuleLoad);
+    state.watchdog.watch_module_load(timeout);
+    let result = tokio::time::timeout(timeout, load).await;
+    let terminated = state.watchdog.finish_module_load();
+
+    match result {
+        Ok(result) if !terminated => result,
+        _ => Err(format!(
+            "Error: Module load did not finish within {} ms",
+            timeout.as_millis()
+        )
+        .into()),
+    }
+}
+
+/// Lets the next load run after the watchdog terminated the previous one.
+fn cancel_load_termination(javascript_runtime: &mut JsRuntime, state: &RuntimeState) {
+    if state.watchdog.take_termination() {
+        javascript_runtime.v8_isolate().cancel_terminate_execution();
+    }
+}
+
+/// Lets JavaScript run again after the watchdog terminated a runaway call or
+/// the isolate ran out of memory. Returns whether the isolate should be
+/// recycled.
+fn recover_from_termination(javascript_runtime: &mut JsRuntime, state: &RuntimeState) -> bool {
+    let out_of_memory = state.out_of_memory.replace(false);
+
+    if state.watchdog.take_termination() || out_of_memory {
+        javascript_runtime.v8_isolate().cancel_terminate_execution();
+    }
+
+    if !out_of_memory {
+        return false;
+    }
+
+    // There is no telling which call exhausted the heap, so every call that
+    // has not sent its final message yet is failed, see `CallEnded`.
+    let calls: Vec<_> = state.calls.borrow_mut().drain().collect();
+    for (message_identifier, task) in calls {
+        if let Some(task) = task {
+            task.abort();
+        }
+        state.watchdog.finish(message_identifier);
+        state.watchdog.take_timed_out(message_identifier);
+
+        if !state.config.heap.recycle_on_out_of_memory {
+            // Lets the call's JS stop early. Calls JS already ended aren't
+            // reported again, those JS can't be asked about are.
+            let aborted = js_runtime::abort_js_call(
+                javascript_runtime,
+                message_identifier,
+                "QuotaExceededError",
+                "The JS Runtime ran out of memory.",
+            );
+            if let Ok(false) = aborted {
+                continue;
+            }
+        }
+
+        let message = DartMessage::js_error(
+            "QuotaExceededError",
+            "Error: JS Runtime ran out of memory.",
+            "The JS Runtime ran out of memory.",
+        );
+        send_dart_message(state.send_port, message_identifier, &message);
+    }
+
+    if state.config.heap.recycle_on_out_of_memory {
+        return true;
+    }
+
+    js_runtime::reset_heap_limit(
+        javascript_runtime,
+        &state.config,
+        state.out_of_memory.clone(),
+    );
+    false
+}
+
+/// Hands data Dart sent to JavaScript: chunks of stream arguments and answers
+/// to `Dart.invoke`. Returns any other command.
+fn deliver_to_js(
+    javascript_runtime: &mut JsRuntime,
+    send_port: i64,
+    command: RuntimeCommand,
+) -> Option<RuntimeCommand> {
+    let result = match command {
+        RuntimeCommand::PushStreamChunk { stream_id, chunk } => {
+            js_runtime::push_js_stream_chunk(javascript_runtime, stream_id, chunk)
+        }
+        RuntimeCommand::CompleteHostCall { request_id, result } => {
+            js_runtime::complete_js_host_call(javascript_runtime, request_id, result)
+        }
+        RuntimeCommand::CloseStream { stream_id, error } => {
+            js_runtime::close_js_stream(javascript_runtime, stream_id, error.as_deref())
+        }
+        command => return Some(command),
+    };
+
+    if let Err(e) = result {
+        dart_value::post_runtime_error(send_port, &e);
+    }
+    None
+}
+
+/// Answers the commands Dart blocks on, returning any other command.
+///
+/// Releasing a handle or disposing an instance while earlier commands are
+/// still `deferred` is answered now but only applied after them, so calls
+/// queued before still find the handle or instance.
+fn answer_query(
+    javascript_runtime: &mut JsRuntime,
+    state: &RuntimeState,
+    command: RuntimeCommand,
+    deferred: &mut VecDeque<RuntimeCommand>,
+) -> Option<RuntimeCommand> {
+    match command {
+        RuntimeCommand::IsModuleRegistered { module_name, reply } => {
+            let scope = &mut javascript_runtime.handle_scope();
+            let _ = reply.send(js_runtime::get_js_module(scope, &module_name).is_ok());
+        }
+        RuntimeCommand::CountHandles { reply } => {
+            let _ = reply.send(js_runtime::count_js_handles(javascript_runtime));
+        }
+        RuntimeCommand::ReleaseHandle { handle_id, reply } if !deferred.is_empty() => {
+            let _ = reply.send(js_runtime::has_js_handle(javascript_runtime, handle_id));
+            let (reply, _) = oneshot::channel();
+            deferred.push_back(RuntimeCommand::ReleaseHandle { handle_id, reply });
+        }
+        RuntimeCommand::ReleaseHandle { handle_id, reply } => {
+            let _ = reply.send(js_runtime::release_js_handle(javascript_runtime, handle_id));
+        }
+        RuntimeCommand::DisposeInstance { instance_id, reply } => {
+            let existed = state.instances.borrow_mut().remove(&instance_id).is_some();
+            let _ = reply.send(existed);
+
+            // The `dispose` hook runs once the instance's earlier calls did
+            if !deferred.is_empty() {
+                let (reply, _) = oneshot::channel();
+                deferred.push_back(RuntimeCommand::DisposeInstance { instance_id, reply });
+                return None;
+            }
+            if let Err(e) = js_runtime::dispose_module_instance(javascript_runtime, instance_id) {
+                dart_value::post_runtime_error(state.send_port, &e);
+            }
+        }
+        command => return Some(command),
+    }
+    None
+}
+
+/// Runs `command`. Only registering a module or creating an instance with an
+/// async `init` waits, running the event loop until `init` settles. Dart
+/// keeps running meanwhile, so `init` can `Dart.invoke`.
+async fn handle_command(
+    javascript_runtime: &mut JsRuntime,
+    state: &RuntimeState,
+    commands: &mut Commands,
+    command: RuntimeCommand,
+) {
+    let send_port = state.send_port;
+    let mut driver = CommandLoadDriver { state, commands };
+
+    match command {
+        RuntimeCommand::RegisterModule {
+            module_name,
+            file_path,
+            source,
+            args,
+            permissions,
+            message_identifier,
+        } => {
+            let registration = js_runtime::register_module(
+                javascript_runtime,
+                &mut driver,
+                &module_name,
+                file_path.clone(),
+                source.clone(),
+                &args,
+                permissions.as_ref(),
+            );
+            let result = watch_module_load(state, registration).await;
+
+            let message = match result {
+                Ok(()) => {
+                    state.modules.borrow_mut().push(ModuleRegistration {
+                        module_name,
+                        file_path,
+                        source,
+                        args,
+                        permissions,
+                    });
+                    DartMessage::done()
+                }
+                Err(e) => e.into_dart_message(),
+            };
+            send_dart_message(send_port, message_identifier, &message);
+        }
+        RuntimeCommand::CreateInstance {
+            module_name,
+            args,
+            message_identifier,
+        } => {
+            // Instance ids are `i32`s for Dart
+            let instance_id = state.last_instance_id.get() % i32::MAX + 1;
+            state.last_instance_id.set(instance_id);
+
+            let creation = js_runtime::create_module_instance(
+                javascript_runtime,
+                &mut driver,
+                &module_name,
+                instance_id,
+                &args,
+            );
+            let result = watch_module_load(state, creation).await;
+
+            match result {
+                Ok(()) => {
+                    state
+                        .instances
+                        .borrow_mut()
+                        .insert(instance_id, InstanceRegistration { module_name, args });
+                    dart_value::post_value(
+                        send_port,
+                        message_identifier,
+                        true,
+                        dart_value::DartValue::Int(instance_id.into()),
+                    );
+                }
+                Err(e) => send_dart_message(send_port, message_identifier, &e.into_dart_message()),
+            }
+        }
+        RuntimeCommand::CallFunction {
+            target,
+            message_identifier,
+            args,
+            timeout,
+        } => {
+            let timeout = timeout.or(state.config.default_call_timeout());
+            if let Some(timeout) = timeout {
+                state.watchdog.watch(message_identifier, timeout);
+            }
+
+            // Pending until its JavaScript ends it, see `CallEnded`. Functions
+            // may do so before `call_js_function` returns.
+            state.calls.borrow_mut().insert(message_identifier, None);
+
+            // Runs the function up to its first `await`
+            let fnc_call = {
+                let _running = state.watchdog.enter(Running::Call(message_identifier));
+                js_runtime::call_js_function(javascript_runtime, &target, message_identifier, &args)
+            };
+
+            match fnc_call {
+                Ok(fnc_call) => {
+                    let watchdog = state.watchdog.clone();
+                    let calls = state.calls.clone();
+                    let task = tokio::task::spawn_local(async move {
+                        // Calls that settled `invoke` may still answer later,
+                        // e.g. from a timer. Failing means `invoke` itself
+                        // failed, as when the call was terminated.
+                        let Err(e) = fnc_call.await else {
+                            return;
+                        };
+                        calls.borrow_mut().remove(&message_identifier);
+                        watchdog.finish(message_identifier);
+
+                        // A timed out call is reported as such, the error
+                        // here is only the termination unwinding it
+                        if watchdog.take_timed_out(message_identifier) {
+                            return;
+                        }
+
+                        let message = DartMessage::error(e.to_string());
+                        send_dart_message(send_port, message_identifier, &message);
+                    });
+
+                    if let Some(call) = state.calls.borrow_mut().get_mut(&message_identifier) {
+                        *call = Some(task.abort_handle());
+                    }
+                }
+                Err(e) => {
+                    state.calls.borrow_mut().remove(&message_identifier);
+                    state.watchdog.finish(message_identifier);
+                    send_dart_message(send_port, message_identifier, &DartMessage::error(e));
+                }
+            }
+        }
+        RuntimeCommand::CancelCall { message_identifier } => {
+            if let Err(e) = js_runtime::cancel_js_call(javascript_runtime, message_identifier) {
+                dart_value::post_runtime_error(send_port, &e);
+            }
+        }
+        RuntimeCommand::AcknowledgeChunks {
+            message_identifier,
+            count,
+        } => {
+            let result =
+                js_runtime::acknowledge_js_chunks(javascript_runtime, message_identifier, count);
+            if let Err(e) = result {
+                dart_value::post_runtime_error(send_port, &e);
+            }
+        }
+        command @ (RuntimeCommand::PushStreamChunk { .. }
+        | RuntimeCommand::CompleteHostCall { .. }
+        | RuntimeCommand::CloseStream { .. }) => {
+            deliver_to_js(javascript_runtime, send_port, command);
+        }
+        command @ (RuntimeCommand::IsModuleRegistered { .. }
+        | RuntimeCommand::CountHandles { .. }
+        | RuntimeCommand::ReleaseHandle { .. }
+        | RuntimeCommand::DisposeInstance { .. }) => {
+            answer_query(javascript_runtime, state, command, &mut VecDeque::new());
+        }
+        RuntimeCommand::CallTimedOut { message_identifier } => {
+            state.watchdog.finish(message_identifier);
+            state.watchdog.take_timed_out(message_identifier);
+            if let Some(Some(task)) = state.calls.borrow_mut().remove(&message_identifier) {
+                task.abort();
+            }
+
+            // Aborting lets the call's JS stop early. Calls that already sent
+            // their final message or were cancelled aren't reported again.
+            // If aborting fails, the timeout is reported so Dart hears of it.
+            let aborted = js_runtime::abort_js_call(
+                javascript_runtime,
+                message_identifier,
+                "TimeoutError",
+                "The call timed out.",
+            );
+            if let Ok(false) = aborted {
+                return;
+            }
+
+            let message = DartMessage::js_error(
+                "TimeoutError",
+                "Error: JS function call timed out.",
+                "The call timed out.",
+            );
+            send_dart_message(send_port, message_identifier, &message);
+        }
+    }
+}
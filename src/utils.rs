@@ -1,14 +1,15 @@
-use std::sync::OnceLock;
-
 use tokio::runtime::{self, Runtime};
 
-static RUNTIME: OnceLock<Runtime> = OnceLock::new();
+use crate::runtime_config::TokioConfig;
+
+/// Builds the single-threaded Tokio runtime that drives one JS runtime thread.
+pub fn new_tokio_runtime(config: &TokioConfig) -> Runtime {
+    let mut builder = runtime::Builder::new_current_thread();
+    builder.enable_all();
+
+    if let Some(max_blocking_threads) = config.max_blocking_threads {
+        builder.max_blocking_threads(max_blocking_threads);
+    }
 
-pub fn tokio_runtime() -> &'static Runtime {
-    RUNTIME.get_or_init(|| {
-        runtime::Builder::new_current_thread()
-            .enable_all()
-            .build()
-            .expect("Failed to create Tokio runtime")
-    })
+    builder.build().expect("Failed to create Tokio runtime")
 }
@@ -0,0 +1,370 @@
+//! Deadlines of calls and module loads, enforced from outside the runtime.
+
+use std::{
+    collections::{HashMap, HashSet},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc::{self, RecvTimeoutError},
+        Arc, Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+use tokio::sync::mpsc::WeakUnboundedSender;
+
+use crate::runtime_thread::RuntimeCommand;
+
+/// How long the runtime thread gets to handle a timed out call on its own
+/// before the watchdog assumes it is stuck in JavaScript and terminates it.
+const TERMINATION_GRACE_PERIOD: Duration = Duration::from_millis(100);
+
+/// The JavaScript the runtime thread is running, which decides whether a
+/// timed out call may be terminated.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Running {
+    /// No JavaScript, or JavaScript no call is waiting on.
+    Idle,
+    /// The synchronous part of the call with this message identifier.
+    Call(i32),
+    /// An event loop turn, which may continue any call.
+    EventLoop,
+    /// Registering a module or creating an instance, including the event
+    /// loop turns it waits for.
+    ModuleLoad,
+}
+
+/// What a deadline is enforced for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum Target {
+    Call(i32),
+    ModuleLoad,
+}
+
+impl Target {
+    /// Whether the JavaScript `running` may be this target's.
+    fn may_be_running(self, running: Running) -> bool {
+        match self {
+            Target::Call(message_identifier) => {
+                running == Running::EventLoop || running == Running::Call(message_identifier)
+            }
+            Target::ModuleLoad => running == Running::ModuleLoad,
+        }
+    }
+}
+
+enum WatchdogEvent {
+    Watch { target: Target, deadline: Instant },
+    Finish { target: Target },
+}
+
+/// Enforces call and module load deadlines from a separate OS thread.
+///
+/// When a call runs past its deadline the runtime thread is asked to time it
+/// out. If it does not get to that within [`TERMINATION_GRACE_PERIOD`] it is
+/// busy running JavaScript. Execution is only terminated, through the
+/// isolate's thread-safe handle, when that JavaScript may be the timed out
+/// call's, see [`Watchdog::enter`]. Otherwise the timeout is reported once
+/// the other call yields.
+///
+/// Module loads time out on their own while they wait, the watchdog only
+/// terminates loads that never yield.
+#[derive(Clone)]
+pub struct Watchdog {
+    events: mpsc::Sender<WatchdogEvent>,
+    isolate_handle: Arc<Mutex<Option<v8::IsolateHandle>>>,
+    running: Arc<Mutex<Running>>,
+    timed_out: Arc<Mutex<HashSet<Target>>>,
+    terminated: Arc<AtomicBool>,
+}
+
+/// Restores what was running before [`Watchdog::enter`] when dropped.
+pub struct RunningGuard {
+    running: Arc<Mutex<Running>>,
+    previous: Running,
+}
+
+impl Drop for RunningGuard {
+    fn drop(&mut self) {
+        *self.running.lock().unwrap() = self.previous;
+    }
+}
+
+impl Watchdog {
+    pub fn spawn(commands: WeakUnboundedSender<RuntimeCommand>) -> Self {
+        let (events, receiver) = mpsc::channel();
+        let watchdog = Watchdog {
+            events,
+            isolate_handle: Default::default(),
+            running: Arc::new(Mutex::new(Running::Idle)),
+            timed_out: Default::default(),
+            terminated: Default::default(),
+        };
+
+        let isolate_handle = watchdog.isolate_handle.clone();
+        let running = watchdog.running.clone();
+        let timed_out = watchdog.timed_out.clone();
+        let terminated = watchdog.terminated.clone();
+        std::thread::Builder::new()
+            .name("globe-runtime-watchdog".to_string())
+            .spawn(move || {
+                run(
+                    receiver,
+                    isolate_handle,
+                    commands,
+                    running,
+                    timed_out,
+                    terminated,
+                );
+            })
+            .expect("Failed to spawn watchdog thread");
+
+        watchdog
+    }
+
+    /// Sets the isolate to terminate, replacing the previous one when the
+    /// runtime has been recycled.
+    pub fn attach(&self, isolate_handle: v8::IsolateHandle) {
+        *self.isolate_handle.lock().unwrap() = Some(isolate_handle);
+    }
+
+    /// Marks `running` as what the runtime thread runs until the returned
+    /// guard is dropped.
+    pub fn enter(&self, running: Running) -> RunningGuard {
+        let previous = std::mem::replace(&mut *self.running.lock().unwrap(), running);
+        RunningGuard {
+            running: self.running.clone(),
+            previous,
+        }
+    }
+
+    /// Starts enforcing `timeout` for the call `message_identifier`.
+    pub fn watch(&self, message_identifier: i32, timeout: Duration) {
+        self.watch_target(Target::Call(message_identifier), timeout);
+    }
+
+    /// Stops watching `message_identifier`, once the runtime thread has
+    /// reported the timeout or the call has settled.
+    pub fn finish(&self, message_identifier: i32) {
+        let _ = self.events.send(WatchdogEvent::Finish {
+            target: Target::Call(message_identifier),
+        });
+    }
+
+    /// Returns whether `message_identifier` timed out and forgets about it.
+    pub fn take_timed_out(&self, message_identifier: i32) -> bool {
+        self.timed_out
+            .lock()
+            .unwrap()
+            .remove(&Target::Call(message_identifier))
+    }
+
+    /// Starts enforcing `timeout` for the module load about to run as
+    /// [`Running::ModuleLoad`].
+    pub fn watch_module_load(&self, timeout: Duration) {
+        self.watch_target(Target::ModuleLoad, timeout);
+    }
+
+    /// Stops watching the module load, returning whether it timed out.
+    pub fn finish_module_load(&self) -> bool {
+        let _ = self.events.send(WatchdogEvent::Finish {
+            target: Target::ModuleLoad,
+        });
+        self.timed_out.lock().unwrap().remove(&Target::ModuleLoad)
+    }
+
+    fn watch_target(&self, target: Target, timeout: Duration) {
+        let _ = self.events.send(WatchdogEvent::Watch {
+            target,
+            deadline: Instant::now() + timeout,
+        });
+    }
+
+    /// Whether execution was terminated and the runtime thread has not taken
+    /// the termination yet, see [`Watchdog::take_termination`].
+    pub fn is_terminating(&self) -> bool {
+        self.terminated.load(Ordering::SeqCst)
+    }
+
+    /// Returns whether execution was terminated since the last call.
+    pub fn take_termination(&self) -> bool {
+        self.terminated.swap(false, Ordering::SeqCst)
+    }
+}
+
+fn run(
+    receiver: mpsc::Receiver<WatchdogEvent>,
+    isolate_handle: Arc<Mutex<Option<v8::IsolateHandle>>>,
+    commands: WeakUnboundedSender<RuntimeCommand>,
+    running: Arc<Mutex<Running>>,
+    timed_out: Arc<Mutex<HashSet<Target>>>,
+    terminated: Arc<AtomicBool>,
+) {
+    let mut deadlines: HashMap<Target, Instant> = HashMap::new();
+    let mut grace_deadlines: HashMap<Target, Instant> = HashMap::new();
+
+    loop {
+        let next_deadline = deadlines.values().chain(grace_deadlines.values()).min();
+        let event = match next_deadline {
+            Some(deadline) => {
+                receiver.recv_timeout(deadline.saturating_duration_since(Instant::now()))
+            }
+            None => receiver.recv().map_err(|_| RecvTimeoutError::Disconnected),
+        };
+
+        match event {
+            Ok(WatchdogEvent::Watch { target, deadline }) => {
+                deadlines.insert(target, deadline);
+            }
+            Ok(WatchdogEvent::Finish { target }) => {
+                deadlines.remove(&target);
+                grace_deadlines.remove(&target);
+            }
+            Err(RecvTimeoutError::Timeout) => {}
+            Err(RecvTimeoutError::Disconnected) => break,
+        }
+
+        let now = Instant::now();
+
+        let expired: Vec<Target> = deadlines
+            .iter()
+            .filter(|(_, deadline)| **deadline <= now)
+            .map(|(target, _)| *target)
+            .collect();
+
+        for target in expired {
+            deadlines.remove(&target);
+            timed_out.lock().unwrap().insert(target);
+
+            // Module loads time out on their own once they yield
+            if let Target::Call(message_identifier) = target {
+                let Some(commands) = commands.upgrade() else {
+                    return;
+                };
+                let _ = commands.send(RuntimeCommand::CallTimedOut { message_identifier });
+            }
+            grace_deadlines.insert(target, now + TERMINATION_GRACE_PERIOD);
+        }
+
+        let stuck: Vec<Target> = grace_deadlines
+            .iter()
+            .filter(|(_, deadline)| **deadline <= now)
+            .map(|(target, _)| *target)
+            .collect();
+        grace_deadlines.retain(|_, deadline| *deadline > now);
+
+        // Another call's JavaScript is left running, the timeout of the stuck
+        // call is reported once it yields
+        let running = *running.lock().unwrap();
+        let terminate = stuck.iter().any(|target| target.may_be_running(running));
+        if terminate {
+            if let Some(isolate_handle) = isolate_handle.lock().unwrap().as_ref() {
+                terminated.store(true, Ordering::SeqCst);
+                isolate_handle.terminate_execution();
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::sync::mpsc::{
+        error::TryRecvError, unbounded_channel, UnboundedReceiver, UnboundedSender,
+    };
+
+    use super::*;
+
+    /// A watchdog without an isolate and the queue it sends commands to. The
+    /// watchdog only holds a weak sender, the returned one keeps the queue
+    /// open.
+    fn spawn() -> (
+        Watchdog,
+        UnboundedSender<RuntimeCommand>,
+        UnboundedReceiver<RuntimeCommand>,
+    ) {
+        let (sender, receiver) = unbounded_channel();
+        let watchdog = Watchdog::spawn(sender.downgrade());
+        (watchdog, sender, receiver)
+    }
+
+    #[test]
+    fn calls_may_only_be_terminated_while_they_may_run() {
+        let call = Target::Call(1);
+        assert!(call.may_be_running(Running::Call(1)));
+        assert!(call.may_be_running(Running::EventLoop));
+        assert!(!call.may_be_running(Running::Call(2)));
+        assert!(!call.may_be_running(Running::Idle));
+        assert!(!call.may_be_running(Running::ModuleLoad));
+
+        let load = Target::ModuleLoad;
+        assert!(load.may_be_running(Running::ModuleLoad));
+        assert!(!load.may_be_running(Running::EventLoop));
+    }
+
+    #[test]
+    fn restores_what_was_running() {
+        let (watchdog, _sender, _commands) = spawn();
+
+        {
+            let _event_loop = watchdog.enter(Running::EventLoop);
+            {
+                let _call = watchdog.enter(Running::Call(3));
+                assert_eq!(*watchdog.running.lock().unwrap(), Running::Call(3));
+            }
+            assert_eq!(*watchdog.running.lock().unwrap(), Running::EventLoop);
+        }
+        assert_eq!(*watchdog.running.lock().unwrap(), Running::Idle);
+    }
+
+    #[test]
+    fn times_out_calls_past_their_deadline() {
+        let (watchdog, _sender, mut commands) = spawn();
+
+        watchdog.watch(7, Duration::from_millis(10));
+
+        assert!(matches!(
+            commands.blocking_recv(),
+            Some(RuntimeCommand::CallTimedOut {
+                message_identifier: 7
+            })
+        ));
+        assert!(watchdog.take_timed_out(7));
+        assert!(!watchdog.take_timed_out(7));
+        watchdog.finish(7);
+    }
+
+    #[test]
+    fn forgets_finished_calls() {
+        let (watchdog, _sender, mut commands) = spawn();
+
+        watchdog.watch(8, Duration::from_millis(20));
+        watchdog.finish(8);
+        std::thread::sleep(Duration::from_millis(60));
+
+        assert!(matches!(commands.try_recv(), Err(TryRecvError::Empty)));
+        assert!(!watchdog.take_timed_out(8));
+    }
+
+    #[test]
+    fn times_out_module_loads_without_commands() {
+        let (watchdog, _sender, mut commands) = spawn();
+
+        watchdog.watch_module_load(Duration::from_millis(10));
+        std::thread::sleep(Duration::from_millis(40));
+
+        assert!(watchdog.finish_module_load());
+        assert!(!watchdog.finish_module_load());
+        assert!(matches!(commands.try_recv(), Err(TryRecvError::Empty)));
+    }
+
+    #[test]
+    fn only_terminates_an_attached_isolate() {
+        let (watchdog, _sender, _commands) = spawn();
+        let _call = watchdog.enter(Running::Call(9));
+
+        watchdog.watch(9, Duration::from_millis(5));
+        std::thread::sleep(TERMINATION_GRACE_PERIOD + Duration::from_millis(50));
+
+        assert!(!watchdog.is_terminating());
+        assert!(!watchdog.take_termination());
+    }
+}